@@ -0,0 +1,675 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::task::JoinHandle;
+use async_trait::async_trait;
+use log::{info, warn, debug, error};
+use serde::{Serialize, Deserialize};
+use rppal::gpio::{Gpio, InputPin, Level, Trigger};
+use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
+use super::ads1299_registers::{DataRate, PgaGain, RegisterMap};
+use super::capabilities::{DriverCapabilities, SampleRates, TimestampPrecision};
+use super::types::{current_timestamp_micros, AdcConfig, AdcData, DriverStatus, DriverError, DriverEvent, DriverType, LeadOffMonitor, SpiError};
+
+/// SPI command opcodes (ADS1299 datasheet, "SPI Command Definitions").
+pub mod opcode {
+    pub const WAKEUP: u8 = 0x02;
+    pub const STANDBY: u8 = 0x04;
+    pub const RESET: u8 = 0x06;
+    pub const START: u8 = 0x08;
+    pub const STOP: u8 = 0x0A;
+    pub const RDATAC: u8 = 0x10;
+    pub const SDATAC: u8 = 0x11;
+    pub const RDATA: u8 = 0x12;
+    /// RREG is `0x20 | start address`, followed by `register count - 1`.
+    pub const RREG: u8 = 0x20;
+    /// WREG is `0x40 | start address`, followed by `register count - 1` and the data.
+    pub const WREG: u8 = 0x40;
+}
+
+/// Register addresses.
+pub mod reg {
+    pub const ID: u8 = 0x00;
+    pub const CONFIG1: u8 = 0x01;
+    pub const CONFIG2: u8 = 0x02;
+    pub const CONFIG3: u8 = 0x03;
+    pub const LOFF: u8 = 0x04;
+    pub const CH1SET: u8 = 0x05;
+    pub const BIAS_SENSP: u8 = 0x0D;
+    pub const BIAS_SENSN: u8 = 0x0E;
+    pub const LOFF_SENSP: u8 = 0x0F;
+    pub const LOFF_SENSN: u8 = 0x10;
+    pub const LOFF_FLIP: u8 = 0x11;
+    pub const LOFF_STATP: u8 = 0x12;
+    pub const LOFF_STATN: u8 = 0x13;
+    pub const GPIO: u8 = 0x14;
+    pub const MISC1: u8 = 0x15;
+    pub const MISC2: u8 = 0x16;
+    pub const CONFIG4: u8 = 0x17;
+    /// Total number of registers in the map.
+    pub const COUNT: usize = 0x18;
+}
+
+/// Number of input channels on a single ADS1299.
+pub const ADS1299_CHANNELS: usize = 8;
+/// Bytes per channel sample (24-bit two's complement).
+pub(crate) const BYTES_PER_SAMPLE: usize = 3;
+/// Bytes in the status word that leads every data frame.
+pub(crate) const STATUS_BYTES: usize = 3;
+/// Length of one data frame read after DRDY.
+pub(crate) const FRAME_BYTES: usize = STATUS_BYTES + ADS1299_CHANNELS * BYTES_PER_SAMPLE;
+
+/// Internal reference voltage (VREFP - VREFN) with the reference buffer enabled.
+pub const VREF_VOLTS: f64 = 4.5;
+/// Master clock of the internal oscillator.
+pub const FCLK_HZ: f64 = 2_048_000.0;
+/// Positive full-scale output code, 2^23.
+pub(crate) const FULL_SCALE_CODE: f64 = (1 << 23) as f64;
+
+/// Differential input voltage for an output code at PGA `gain`.
+pub fn code_to_volts(code: f32, gain: f32) -> f32 {
+    (code as f64 * VREF_VOLTS / (gain as f64 * FULL_SCALE_CODE)) as f32
+}
+
+/// Output code for a differential input voltage at PGA `gain`, before clipping to 24 bits.
+pub fn volts_to_code(volts: f64, gain: f32) -> f64 {
+    volts * gain as f64 / VREF_VOLTS * FULL_SCALE_CODE
+}
+
+/// How several ADS1299 chips share the SPI bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ChipSelectMode {
+    /// One chip select; DOUT of each chip feeds DAISY_IN of the previous one, so a
+    /// single read returns every chip's frame and register writes reach all chips.
+    #[default]
+    DaisyChain,
+    /// One chip select per chip (chip n on chip select n), each configured and read separately.
+    Separate,
+}
+
+/// Default Raspberry Pi GPIO (BCM numbering) wired to the ADS1299 DRDY output.
+pub const DEFAULT_DRDY_GPIO: u8 = 25;
+/// Default SPI clock. The ADS1299 accepts up to 20 MHz, but long ribbon cables do not.
+pub const DEFAULT_SPI_CLOCK_HZ: u32 = 2_000_000;
+
+/// Low-level access to the ADS1299 SPI bus and DRDY line.
+///
+/// The driver only talks to the chips through this trait, so the same driver code runs
+/// against [`RppalBus`] on a Raspberry Pi and against a fake or emulated bus in tests.
+pub trait Ads1299Bus: Send + 'static {
+    /// Full-duplex transfer with `chip_select` held for the whole buffer.
+    /// `buffer` is clocked out to the chip and overwritten with the bytes clocked back.
+    fn transfer(&mut self, chip_select: usize, buffer: &mut [u8]) -> Result<(), DriverError>;
+
+    /// Block until DRDY falls or `timeout` elapses, and return how many times it fell
+    /// since the previous call: 1 when the previous conversion was read in time, more when
+    /// conversions went by unread, 0 on timeout. A fall since the previous call counts, or
+    /// the conversion it announced would be lost.
+    /// With several chips, DRDY of the first chip is used; all chips convert in lockstep.
+    fn wait_for_drdy(&mut self, timeout: Duration) -> Result<u64, DriverError>;
+
+    /// Wait between commands that need settling time (reset, wakeup).
+    /// Fake buses can override this to skip the delay.
+    fn delay(&mut self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// [`Ads1299Bus`] implementation backed by the Raspberry Pi SPI and GPIO peripherals.
+pub struct RppalBus {
+    spi: Vec<Spi>,
+    drdy: InputPin,
+}
+
+impl RppalBus {
+    /// Open one SPI device per chip select and configure the DRDY pin as a falling-edge
+    /// interrupt. Chip select `n` of the bus trait maps to `slave_selects[n]`.
+    pub fn open(bus: Bus, slave_selects: &[SlaveSelect], drdy_gpio: u8) -> Result<Self, DriverError> {
+        // The ADS1299 samples DIN on the falling SCLK edge: SPI mode 1
+        let spi = slave_selects.iter()
+            .map(|&ss| Spi::new(bus, ss, DEFAULT_SPI_CLOCK_HZ, Mode::Mode1)
+                .map_err(|e| DriverError::HardwareNotFound(format!("Failed to open SPI {:?}: {}", ss, e))))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut drdy = Gpio::new()
+            .and_then(|gpio| gpio.get(drdy_gpio))
+            .map_err(|e| DriverError::HardwareNotFound(format!("Failed to open DRDY GPIO {}: {}", drdy_gpio, e)))?
+            .into_input_pullup();
+        drdy.set_interrupt(Trigger::FallingEdge)
+            .map_err(|e| DriverError::HardwareNotFound(format!("Failed to configure DRDY interrupt: {}", e)))?;
+
+        Ok(Self { spi, drdy })
+    }
+}
+
+impl RppalBus {
+    /// Next queued DRDY edge, or one arriving within `timeout`.
+    fn poll_drdy(&mut self, timeout: Option<Duration>) -> Result<Option<Level>, DriverError> {
+        self.drdy.poll_interrupt(false, timeout)
+            .map_err(|e| DriverError::AcquisitionError(format!("DRDY poll failed: {}", e)))
+    }
+}
+
+impl Ads1299Bus for RppalBus {
+    fn transfer(&mut self, chip_select: usize, buffer: &mut [u8]) -> Result<(), DriverError> {
+        let spi = self.spi.get(chip_select).ok_or_else(|| DriverError::ConfigurationError(
+            format!("No SPI device opened for chip select {}", chip_select)
+        ))?;
+        let write = buffer.to_vec();
+        spi.transfer(buffer, &write).map_err(SpiError)?;
+        Ok(())
+    }
+
+    fn wait_for_drdy(&mut self, timeout: Duration) -> Result<u64, DriverError> {
+        // Edges that came while the previous frame was being handled are kept: the
+        // conversion they announce is still waiting to be read
+        if self.poll_drdy(Some(timeout))?.is_none() {
+            return Ok(0);
+        }
+        // Only the latest conversion can still be read. Each further queued edge counts
+        // one overwritten before it was read, which shows as a gap in `sample_index`
+        let mut edges = 1;
+        while self.poll_drdy(Some(Duration::ZERO))?.is_some() {
+            edges += 1;
+        }
+        Ok(edges)
+    }
+}
+
+/// Driver for the ADS1299 EEG analog front-end chip.
+pub struct Ads1299Driver {
+    inner: Arc<Mutex<Ads1299Inner>>,
+    bus: Arc<std::sync::Mutex<Box<dyn Ads1299Bus>>>,
+    running: Arc<AtomicBool>,
+    task_handle: Option<JoinHandle<()>>,
+    tx: mpsc::Sender<DriverEvent>,
+}
+
+/// Internal state for the Ads1299Driver.
+struct Ads1299Inner {
+    config: AdcConfig,
+    status: DriverStatus,
+}
+
+impl Ads1299Driver {
+    /// Create a driver on the default Raspberry Pi wiring: SPI0, DRDY on [`DEFAULT_DRDY_GPIO`],
+    /// and CE0 (daisy chain) or CE0, CE1, ... (separate chip selects).
+    ///
+    /// See [`Ads1299Driver::with_bus`] for the meaning of `additional_channel_buffering`.
+    pub fn new(
+        config: AdcConfig,
+        additional_channel_buffering: usize
+    ) -> Result<(Self, mpsc::Receiver<DriverEvent>), DriverError> {
+        const SLAVE_SELECTS: [SlaveSelect; 4] = [SlaveSelect::Ss0, SlaveSelect::Ss1, SlaveSelect::Ss2, SlaveSelect::Ss3];
+        let lines = chip_selects(&config).len();
+        if lines > SLAVE_SELECTS.len() {
+            return Err(DriverError::ConfigurationError(
+                format!("{} separate chip selects requested, at most {} are supported", lines, SLAVE_SELECTS.len())
+            ));
+        }
+        let bus = RppalBus::open(Bus::Spi0, &SLAVE_SELECTS[..lines], DEFAULT_DRDY_GPIO)?;
+        Self::with_bus(config, bus, additional_channel_buffering)
+    }
+
+    /// Create a driver that talks to the chip through `bus`.
+    ///
+    /// Every chip is reset, its ID register checked and the configuration written and
+    /// read back before this returns. The additional_channel_buffering parameter has the same meaning as
+    /// for `MockDriver::new`: extra batches that may queue in the event channel beyond
+    /// the batch_size from the config.
+    ///
+    /// # Errors
+    /// Returns an error if:
+    /// - config.board_driver is not DriverType::Ads1299
+    /// - the configuration cannot be expressed in ADS1299 registers
+    /// - the chip does not answer with an ADS1299 device ID
+    pub fn with_bus<B: Ads1299Bus>(
+        config: AdcConfig,
+        bus: B,
+        additional_channel_buffering: usize
+    ) -> Result<(Self, mpsc::Receiver<DriverEvent>), DriverError> {
+        if config.board_driver != DriverType::Ads1299 {
+            return Err(DriverError::ConfigurationError(
+                "Ads1299Driver requires config.board_driver=DriverType::Ads1299".to_string()
+            ));
+        }
+
+        if config.batch_size == 0 {
+            return Err(DriverError::ConfigurationError(
+                "Batch size must be greater than 0".to_string()
+            ));
+        }
+
+        // Reject the configuration before touching the chips
+        let registers = RegisterMap::for_chips(&config)?;
+
+        let mut bus: Box<dyn Ads1299Bus> = Box::new(bus);
+        let selects = chip_selects(&config);
+        for &cs in &selects {
+            reset_chip(bus.as_mut(), cs)?;
+        }
+        for &cs in &selects {
+            check_device_id(bus.as_mut(), cs)?;
+            // Daisy-chained chips all receive the write on chip select 0
+            apply_register_map(bus.as_mut(), cs, &registers[cs])?;
+        }
+
+        let (tx, rx) = mpsc::channel(config.batch_size + additional_channel_buffering);
+
+        info!("Ads1299Driver created with config: {:?}", config);
+
+        let driver = Self {
+            inner: Arc::new(Mutex::new(Ads1299Inner {
+                config,
+                status: DriverStatus::Ok,
+            })),
+            bus: Arc::new(std::sync::Mutex::new(bus)),
+            running: Arc::new(AtomicBool::new(false)),
+            task_handle: None,
+            tx,
+        };
+
+        Ok((driver, rx))
+    }
+
+    /// What an ADS1299 supports with the chips `config` describes.
+    pub fn capabilities_for(config: &AdcConfig) -> DriverCapabilities {
+        DriverCapabilities {
+            sample_rates: SampleRates::Listed(DataRate::ALL.iter().rev().map(|rate| rate.hz()).collect()),
+            gains: PgaGain::ALL.iter().map(|pga| pga.gain()).collect(),
+            max_channels: config.chip_count * ADS1299_CHANNELS,
+            lead_off_detection: true,
+            impedance_measurement: true,
+            timestamp_precision: TimestampPrecision::PerSample,
+        }
+    }
+
+    /// Write a new configuration to the chips while acquisition is stopped. The chip
+    /// selects can't change, as they were opened with the bus.
+    pub(crate) async fn configure(&mut self, config: AdcConfig) -> Result<(), DriverError> {
+        if self.task_handle.is_some() {
+            return Err(DriverError::ConfigurationError("Stop acquisition before reconfiguring".to_string()));
+        }
+        if config.board_driver != DriverType::Ads1299 {
+            return Err(DriverError::ConfigurationError(
+                "Ads1299Driver requires config.board_driver=DriverType::Ads1299".to_string()
+            ));
+        }
+        if config.batch_size == 0 {
+            return Err(DriverError::ConfigurationError(
+                "Batch size must be greater than 0".to_string()
+            ));
+        }
+        let selects = chip_selects(&config);
+        if selects != chip_selects(&self.get_config().await?) {
+            return Err(DriverError::ConfigurationError(
+                "Chip selects are fixed when the bus is opened, create a new driver to change them".to_string()
+            ));
+        }
+
+        // Reject the configuration before touching the chips
+        let registers = RegisterMap::for_chips(&config)?;
+        {
+            let mut bus = self.lock_bus()?;
+            for &cs in &selects {
+                apply_register_map(bus.as_mut(), cs, &registers[cs])?;
+            }
+        }
+
+        info!("Ads1299Driver reconfigured with config: {:?}", config);
+        self.inner.lock().await.config = config;
+        Ok(())
+    }
+
+    /// Return the current configuration.
+    pub(crate) async fn get_config(&self) -> Result<AdcConfig, DriverError> {
+        let inner = self.inner.lock().await;
+        Ok(inner.config.clone())
+    }
+
+    /// Return the current driver status.
+    pub(crate) async fn get_status(&self) -> DriverStatus {
+        let inner = self.inner.lock().await;
+        inner.status
+    }
+
+    /// Start continuous conversion and spawn the DRDY-driven read loop.
+    ///
+    /// The loop runs on a blocking thread because every DRDY wait and SPI transfer
+    /// is a blocking syscall. Frames are grouped into batch_size batches and sent as
+    /// `DriverEvent::Data`, exactly like the mock driver.
+    pub(crate) async fn start_acquisition(&mut self) -> Result<(), DriverError> {
+        if self.running.load(Ordering::SeqCst) {
+            return Err(DriverError::ConfigurationError("Acquisition already running".to_string()));
+        }
+
+        let config = self.get_config().await?;
+
+        {
+            let mut bus = self.lock_bus()?;
+            for cs in chip_selects(&config) {
+                send_command(bus.as_mut(), cs, opcode::START)?;
+                send_command(bus.as_mut(), cs, opcode::RDATAC)?;
+            }
+        }
+
+        self.running.store(true, Ordering::SeqCst);
+        {
+            let mut inner = self.inner.lock().await;
+            inner.status = DriverStatus::Running;
+        }
+        self.notify_status_change().await?;
+
+        let bus = self.bus.clone();
+        let running = self.running.clone();
+        let tx = self.tx.clone();
+
+        let handle = tokio::task::spawn_blocking(move || {
+            if let Err(e) = acquisition_loop(&config, &bus, &running, &tx) {
+                error!("ADS1299 acquisition failed: {}", e);
+                let _ = tx.blocking_send(DriverEvent::Error(e.to_string()));
+            }
+            running.store(false, Ordering::SeqCst);
+            debug!("Acquisition task terminated");
+        });
+
+        self.task_handle = Some(handle);
+        info!("Ads1299Driver acquisition started");
+        Ok(())
+    }
+
+    /// Stop continuous conversion and wait for the read loop to exit.
+    pub(crate) async fn stop_acquisition(&mut self) -> Result<(), DriverError> {
+        if self.task_handle.is_none() {
+            debug!("Stop acquisition called, but acquisition was not running");
+            return Ok(());
+        }
+
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.task_handle.take() {
+            match handle.await {
+                Ok(_) => debug!("Acquisition task completed successfully"),
+                Err(e) => warn!("Acquisition task terminated with error: {}", e),
+            }
+        }
+
+        {
+            let config = self.get_config().await?;
+            let mut bus = self.lock_bus()?;
+            for cs in chip_selects(&config) {
+                send_command(bus.as_mut(), cs, opcode::SDATAC)?;
+                send_command(bus.as_mut(), cs, opcode::STOP)?;
+            }
+        }
+
+        {
+            let mut inner = self.inner.lock().await;
+            inner.status = DriverStatus::Stopped;
+        }
+        self.notify_status_change().await?;
+        info!("Ads1299Driver acquisition stopped");
+        Ok(())
+    }
+
+    /// Shut down the driver, stopping acquisition and putting the chip in standby.
+    pub(crate) async fn shutdown(&mut self) -> Result<(), DriverError> {
+        debug!("Shutting down Ads1299Driver");
+        self.stop_acquisition().await?;
+
+        {
+            let config = self.get_config().await?;
+            let mut bus = self.lock_bus()?;
+            for cs in chip_selects(&config) {
+                send_command(bus.as_mut(), cs, opcode::STANDBY)?;
+            }
+        }
+
+        {
+            let mut inner = self.inner.lock().await;
+            inner.status = DriverStatus::NotInitialized;
+        }
+        self.notify_status_change().await?;
+        info!("Ads1299Driver shutdown complete");
+        Ok(())
+    }
+
+    fn lock_bus(&self) -> Result<std::sync::MutexGuard<'_, Box<dyn Ads1299Bus>>, DriverError> {
+        self.bus.lock()
+            .map_err(|_| DriverError::Other("ADS1299 bus lock poisoned".to_string()))
+    }
+
+    /// Internal helper to notify status changes over the event channel.
+    async fn notify_status_change(&self) -> Result<(), DriverError> {
+        let status = self.get_status().await;
+        debug!("Sending status change notification: {:?}", status);
+        self.tx
+            .send(DriverEvent::StatusChange(status))
+            .await
+            .or_else(|_| {
+                // Nobody is listening any more, e.g. shutting down after the consumer went away
+                debug!("Event channel closed, status change {:?} not delivered", status);
+                Ok(())
+            })
+    }
+}
+
+/// Read frames on every DRDY until `running` is cleared or the event channel closes.
+fn acquisition_loop(
+    config: &AdcConfig,
+    bus: &std::sync::Mutex<Box<dyn Ads1299Bus>>,
+    running: &AtomicBool,
+    tx: &mpsc::Sender<DriverEvent>,
+) -> Result<(), DriverError> {
+    let batch_size = config.batch_size;
+    // Several sample periods, but short enough that stop_acquisition is never kept waiting long
+    let drdy_timeout = Duration::from_micros((10_000_000 / config.sample_rate as u64).max(100_000));
+    let mut batch = Vec::with_capacity(batch_size);
+    let selects = chip_selects(config);
+    let mut frames = vec![0u8; config.chip_count * FRAME_BYTES];
+    let mut lead_off = LeadOffMonitor::new(config);
+    // Every DRDY edge is a conversion, read or not, so the sample index moves on by the
+    // edges counted and skips those that went by unread
+    let mut last_index: Option<u64> = None;
+
+    debug!("Starting acquisition with batch size: {}, sample rate: {} Hz",
+           batch_size, config.sample_rate);
+
+    while running.load(Ordering::SeqCst) {
+        let edges = {
+            let mut bus = bus.lock()
+                .map_err(|_| DriverError::Other("ADS1299 bus lock poisoned".to_string()))?;
+            let edges = bus.wait_for_drdy(drdy_timeout)?;
+            if edges == 0 {
+                continue;
+            }
+            // In RDATAC mode frames are clocked out directly, no opcode needed
+            frames.fill(0);
+            if selects.len() == 1 {
+                // Daisy chain: every chip's frame in one transfer, first chip first
+                bus.transfer(selects[0], &mut frames)?;
+            } else {
+                for (&cs, frame) in selects.iter().zip(frames.chunks_mut(FRAME_BYTES)) {
+                    bus.transfer(cs, frame)?;
+                }
+            }
+            edges
+        };
+
+        // The first frame of a run is sample 0, whatever edges came before it
+        let sample_index = last_index.map_or(0, |index| index + edges);
+        if edges > 1 && last_index.is_some() {
+            debug!("{} ADS1299 conversions went by unread before sample {}", edges - 1, sample_index);
+        }
+        last_index = Some(sample_index);
+
+        let sample = parse_frames(&frames, &config.channels, current_timestamp_micros()?, sample_index)?;
+        // Report electrodes coming off right away rather than at the end of the batch
+        if let Some(monitor) = lead_off.as_mut() {
+            for change in monitor.check(std::slice::from_ref(&sample)) {
+                if !send_while_running(tx, DriverEvent::LeadOff(change), running) {
+                    return Ok(());
+                }
+            }
+        }
+        batch.push(sample);
+
+        if batch.len() == batch_size {
+            let full = std::mem::replace(&mut batch, Vec::with_capacity(batch_size));
+            if !send_while_running(tx, DriverEvent::Data(full), running) {
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Send an event from the blocking read loop, giving up if acquisition is stopped
+/// while the channel is full so stop_acquisition never waits on a stalled consumer.
+fn send_while_running(tx: &mpsc::Sender<DriverEvent>, mut event: DriverEvent, running: &AtomicBool) -> bool {
+    loop {
+        match tx.try_send(event) {
+            Ok(()) => return true,
+            Err(TrySendError::Closed(_)) => {
+                warn!("Ads1299Driver event channel closed");
+                return false;
+            }
+            Err(TrySendError::Full(returned)) => {
+                if !running.load(Ordering::SeqCst) {
+                    return false;
+                }
+                event = returned;
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+    }
+}
+
+/// Decode consecutive status + 8 channel frames, one per chip, keeping only the configured
+/// channels in config order. Channel n is read from frame n / 8.
+fn parse_frames(frames: &[u8], channels: &[usize], timestamp: u64, sample_index: u64) -> Result<AdcData, DriverError> {
+    let status = frames.chunks(FRAME_BYTES).map(|frame| {
+        // The status word always starts with 0b1100
+        if frame[0] & 0xF0 != 0xC0 {
+            return Err(DriverError::AcquisitionError(
+                format!("Invalid status word 0x{:02X}{:02X}{:02X}", frame[0], frame[1], frame[2])
+            ));
+        }
+        Ok((frame[0] as u32) << 16 | (frame[1] as u32) << 8 | frame[2] as u32)
+    }).collect::<Result<Vec<u32>, DriverError>>()?;
+
+    let samples = channels.iter().map(|&ch| {
+        let offset = (ch / ADS1299_CHANNELS) * FRAME_BYTES + STATUS_BYTES + (ch % ADS1299_CHANNELS) * BYTES_PER_SAMPLE;
+        vec![sign_extend_24(&frames[offset..offset + BYTES_PER_SAMPLE]) as f32]
+    }).collect();
+
+    Ok(AdcData { samples, timestamp, sample_index, status })
+}
+
+/// Chip select lines used for commands and register access: one for a daisy chain,
+/// one per chip otherwise.
+fn chip_selects(config: &AdcConfig) -> Vec<usize> {
+    match config.chip_select {
+        ChipSelectMode::DaisyChain => vec![0],
+        ChipSelectMode::Separate => (0..config.chip_count).collect(),
+    }
+}
+
+/// Convert a big-endian 24-bit two's complement value to i32.
+pub(crate) fn sign_extend_24(bytes: &[u8]) -> i32 {
+    let raw = ((bytes[0] as i32) << 16) | ((bytes[1] as i32) << 8) | bytes[2] as i32;
+    (raw << 8) >> 8
+}
+
+fn send_command(bus: &mut dyn Ads1299Bus, cs: usize, command: u8) -> Result<(), DriverError> {
+    bus.transfer(cs, &mut [command])
+}
+
+fn reset_chip(bus: &mut dyn Ads1299Bus, cs: usize) -> Result<(), DriverError> {
+    send_command(bus, cs, opcode::RESET)?;
+    // Reset takes 18 tCLK; leave generous margin for the oscillator to settle
+    bus.delay(Duration::from_millis(1));
+    // The chip powers up in RDATAC mode, which ignores register commands
+    send_command(bus, cs, opcode::SDATAC)
+}
+
+fn check_device_id(bus: &mut dyn Ads1299Bus, cs: usize) -> Result<(), DriverError> {
+    let id = read_registers(bus, cs, reg::ID, 1)?[0];
+    // DEV_ID[3:2] = 0b11 identifies the ADS1299 family, bit 4 always reads 1
+    if id & 0x1C != 0x1C {
+        return Err(DriverError::HardwareNotFound(
+            format!("Unexpected ADS1299 device ID 0x{:02X} on chip select {}", id, cs)
+        ));
+    }
+    debug!("ADS1299 device ID 0x{:02X} on chip select {}", id, cs);
+    Ok(())
+}
+
+/// Write the whole register map and read it back to make sure the chip took it.
+fn apply_register_map(bus: &mut dyn Ads1299Bus, cs: usize, registers: &RegisterMap) -> Result<(), DriverError> {
+    let bytes = registers.to_bytes();
+    write_registers(bus, cs, RegisterMap::FIRST, &bytes)?;
+    let readback = read_registers(bus, cs, RegisterMap::FIRST, bytes.len())?;
+    registers.verify(&readback)
+}
+
+pub(crate) fn write_registers(bus: &mut dyn Ads1299Bus, cs: usize, start: u8, values: &[u8]) -> Result<(), DriverError> {
+    let mut buffer = Vec::with_capacity(values.len() + 2);
+    buffer.push(opcode::WREG | start);
+    buffer.push(values.len() as u8 - 1);
+    buffer.extend_from_slice(values);
+    bus.transfer(cs, &mut buffer)
+}
+
+pub(crate) fn read_registers(bus: &mut dyn Ads1299Bus, cs: usize, start: u8, count: usize) -> Result<Vec<u8>, DriverError> {
+    let mut buffer = vec![0u8; count + 2];
+    buffer[0] = opcode::RREG | start;
+    buffer[1] = count as u8 - 1;
+    bus.transfer(cs, &mut buffer)?;
+    Ok(buffer.split_off(2))
+}
+
+// Implement the AdcDriver trait
+#[async_trait]
+impl super::types::AdcDriver for Ads1299Driver {
+    async fn shutdown(&mut self) -> Result<(), DriverError> {
+        self.shutdown().await
+    }
+
+    async fn start_acquisition(&mut self) -> Result<(), DriverError> {
+        self.start_acquisition().await
+    }
+
+    async fn stop_acquisition(&mut self) -> Result<(), DriverError> {
+        self.stop_acquisition().await
+    }
+
+    async fn get_status(&self) -> DriverStatus {
+        self.get_status().await
+    }
+
+    async fn get_config(&self) -> Result<AdcConfig, DriverError> {
+        self.get_config().await
+    }
+
+    async fn configure(&mut self, config: AdcConfig) -> Result<(), DriverError> {
+        self.configure(config).await
+    }
+
+    async fn capabilities(&self) -> DriverCapabilities {
+        let config = self.inner.lock().await.config.clone();
+        Self::capabilities_for(&config)
+    }
+}
+
+impl Drop for Ads1299Driver {
+    fn drop(&mut self) {
+        if self.task_handle.is_some() {
+            error!("Ads1299Driver dropped while acquiring. Call shutdown() to stop the chip cleanly.");
+            // Let the blocking read loop exit on its own
+            self.running.store(false, Ordering::SeqCst);
+        }
+    }
+}
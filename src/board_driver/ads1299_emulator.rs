@@ -0,0 +1,448 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use log::{debug, trace, warn};
+use rand_distr::{Distribution, Normal};
+use super::ads1299_driver::{opcode, reg, Ads1299Bus, ChipSelectMode, ADS1299_CHANNELS, BYTES_PER_SAMPLE, FRAME_BYTES, STATUS_BYTES, VREF_VOLTS};
+use super::ads1299_registers::{test_signal_amplitude, TestSignalFrequency};
+use super::types::DriverError;
+
+/// Reference voltage assumed by the emulator (internal 4.5 V reference).
+pub const EMULATOR_VREF: f64 = VREF_VOLTS;
+
+/// Power-on register values (ADS1299 datasheet, "Register Map").
+const RESET_REGISTERS: [u8; reg::COUNT] = [
+    0x3E,                                           // ID: ADS1299, 8 channels
+    0x96, 0xC0, 0x60, 0x00,                         // CONFIG1, CONFIG2, CONFIG3, LOFF
+    0x61, 0x61, 0x61, 0x61, 0x61, 0x61, 0x61, 0x61, // CH1SET..CH8SET: gain 24, shorted
+    0x00, 0x00, 0x00, 0x00, 0x00,                   // BIAS_SENSP/N, LOFF_SENSP/N, LOFF_FLIP
+    0x00, 0x00,                                     // LOFF_STATP, LOFF_STATN
+    0x0F,                                           // GPIO: all inputs
+    0x00, 0x00, 0x00,                               // MISC1, MISC2, CONFIG4
+];
+
+/// Registers the host cannot write.
+const READ_ONLY: [u8; 3] = [reg::ID, reg::LOFF_STATP, reg::LOFF_STATN];
+
+/// Differential input voltage applied to a channel, as a function of channel index and time in seconds.
+/// With several chips the channel index is global: channel n is input n % 8 of chip n / 8.
+pub type InputSignal = Box<dyn Fn(usize, f64) -> f64 + Send>;
+
+/// Register-level software model of one ADS1299, or of several sharing the SPI bus.
+///
+/// The emulator speaks the chip's SPI protocol byte for byte and implements
+/// [`Ads1299Bus`], so `Ads1299Driver` can run against it unchanged. Clones share
+/// the same chips, which lets a test hand one clone to the driver and inspect the
+/// registers through another.
+#[derive(Clone)]
+pub struct Ads1299Emulator {
+    board: Arc<Mutex<EmulatedBoard>>,
+}
+
+/// Chips plus the analog front end they share.
+struct EmulatedBoard {
+    chips: Vec<EmulatedChip>,
+    mode: ChipSelectMode,
+    signal: InputSignal,
+    noise: Option<Normal<f64>>,
+    realtime: bool,
+}
+
+/// Internal state of one emulated chip.
+struct EmulatedChip {
+    registers: [u8; reg::COUNT],
+    standby: bool,
+    converting: bool,
+    continuous: bool,
+    data_ready: bool,
+    frame: [u8; FRAME_BYTES],
+    sample_index: u64,
+    first_channel: usize,
+    /// Electrodes physically disconnected, one bit per channel
+    leads_off_p: u8,
+    leads_off_n: u8,
+}
+
+impl Default for Ads1299Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ads1299Emulator {
+    /// Create a single emulated chip in its power-on state with all electrode inputs at 0 V.
+    pub fn new() -> Self {
+        Self::with_chips(1, ChipSelectMode::DaisyChain)
+    }
+
+    /// Emulate `chip_count` chips in a daisy chain behind chip select 0.
+    pub fn daisy_chain(chip_count: usize) -> Self {
+        Self::with_chips(chip_count, ChipSelectMode::DaisyChain)
+    }
+
+    /// Emulate `chip_count` chips, chip n on chip select n.
+    pub fn separate(chip_count: usize) -> Self {
+        Self::with_chips(chip_count, ChipSelectMode::Separate)
+    }
+
+    fn with_chips(chip_count: usize, mode: ChipSelectMode) -> Self {
+        let chips = (0..chip_count.max(1)).map(|chip| EmulatedChip {
+            registers: RESET_REGISTERS,
+            standby: false,
+            // The chip powers up in RDATAC mode
+            continuous: true,
+            converting: false,
+            data_ready: false,
+            frame: [0; FRAME_BYTES],
+            sample_index: 0,
+            first_channel: chip * ADS1299_CHANNELS,
+            leads_off_p: 0,
+            leads_off_n: 0,
+        }).collect();
+
+        Self {
+            board: Arc::new(Mutex::new(EmulatedBoard {
+                chips,
+                mode,
+                signal: Box::new(|_, _| 0.0),
+                noise: None,
+                realtime: false,
+            })),
+        }
+    }
+
+    /// Set the voltage seen on the electrode inputs (mux setting "normal").
+    pub fn set_input_signal<F>(&self, signal: F)
+    where
+        F: Fn(usize, f64) -> f64 + Send + 'static,
+    {
+        self.board().signal = Box::new(signal);
+    }
+
+    /// Add white input-referred noise with the given RMS in microvolts to every conversion.
+    pub fn set_input_noise(&self, microvolts_rms: f64) {
+        self.board().noise = if microvolts_rms > 0.0 {
+            Normal::new(0.0, microvolts_rms * 1e-6).ok()
+        } else {
+            None
+        };
+    }
+
+    /// Pace DRDY at the configured data rate instead of as fast as the host reads.
+    pub fn set_realtime(&self, realtime: bool) {
+        self.board().realtime = realtime;
+    }
+
+    /// Number of emulated chips.
+    pub fn chip_count(&self) -> usize {
+        self.board().chips.len()
+    }
+
+    /// Current value of a register of the first chip, without going through SPI.
+    pub fn register(&self, address: u8) -> u8 {
+        self.chip_register(0, address)
+    }
+
+    /// Current value of a register of `chip`, without going through SPI.
+    pub fn chip_register(&self, chip: usize, address: u8) -> u8 {
+        self.board().chips[chip].registers[address as usize]
+    }
+
+    /// Snapshot of the whole register map of the first chip.
+    pub fn registers(&self) -> [u8; reg::COUNT] {
+        self.board().chips[0].registers
+    }
+
+    /// Force a register value of the first chip, bypassing SPI and the read-only check.
+    /// Used to model chip-side state such as LOFF_STATP/N.
+    pub fn poke_register(&self, address: u8, value: u8) {
+        self.poke_chip_register(0, address, value);
+    }
+
+    /// Force a register value of `chip`, bypassing SPI and the read-only check.
+    pub fn poke_chip_register(&self, chip: usize, address: u8, value: u8) {
+        self.board().chips[chip].registers[address as usize] = value;
+    }
+
+    /// Connect or disconnect the positive and negative electrodes of a channel.
+    /// With DC lead-off detection enabled on that input, the chip flags it in LOFF_STATP/N
+    /// and the status word from the next conversion on.
+    pub fn set_lead_off(&self, channel: usize, positive_off: bool, negative_off: bool) {
+        let mut board = self.board();
+        let chip = &mut board.chips[channel / ADS1299_CHANNELS];
+        let bit = 1 << (channel % ADS1299_CHANNELS);
+        chip.leads_off_p = if positive_off { chip.leads_off_p | bit } else { chip.leads_off_p & !bit };
+        chip.leads_off_n = if negative_off { chip.leads_off_n | bit } else { chip.leads_off_n & !bit };
+    }
+
+    /// Whether conversions are running (START received and not in standby).
+    pub fn is_converting(&self) -> bool {
+        let board = self.board();
+        board.chips[0].converting && !board.chips[0].standby
+    }
+
+    /// Whether the chip is in read-data-continuous mode.
+    pub fn is_continuous(&self) -> bool {
+        self.board().chips[0].continuous
+    }
+
+    /// Whether the chip is in standby.
+    pub fn is_standby(&self) -> bool {
+        self.board().chips[0].standby
+    }
+
+    /// Data rate selected by CONFIG1.
+    pub fn sample_rate(&self) -> u32 {
+        self.board().chips[0].sample_rate()
+    }
+
+    /// Number of conversions completed since the last START.
+    pub fn samples_converted(&self) -> u64 {
+        self.board().chips[0].sample_index
+    }
+
+    /// Complete one conversion on every chip and assert DRDY.
+    /// Returns false if the first chip, which drives DRDY, is not converting.
+    pub fn convert(&self) -> bool {
+        self.board().convert()
+    }
+
+    fn board(&self) -> MutexGuard<'_, EmulatedBoard> {
+        // A panic while holding the lock leaves the register maps intact, so keep going
+        self.board.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl EmulatedBoard {
+    fn convert(&mut self) -> bool {
+        let Self { chips, signal, noise, .. } = self;
+        // All chips share CLK and START, so they convert in lockstep
+        let converted: Vec<bool> = chips.iter_mut().map(|chip| chip.convert(signal, *noise)).collect();
+        converted.first().copied().unwrap_or(false)
+    }
+
+    /// Route one chip-select-framed transfer to the chip(s) on that line.
+    fn transfer(&mut self, chip_select: usize, buffer: &mut [u8]) -> Result<(), DriverError> {
+        if self.mode == ChipSelectMode::Separate {
+            let chip = self.chips.get_mut(chip_select).ok_or_else(|| DriverError::ConfigurationError(
+                format!("No emulated ADS1299 on chip select {}", chip_select)
+            ))?;
+            let (dout, _) = chip.transfer(buffer);
+            copy_dout(buffer, dout);
+            return Ok(());
+        }
+        if chip_select != 0 {
+            return Err(DriverError::ConfigurationError(
+                format!("Daisy-chained emulated ADS1299s only answer on chip select 0, not {}", chip_select)
+            ));
+        }
+
+        // Every chip sees DIN; DOUT comes from the first chip, whose frame is followed
+        // by the frames shifted in from the rest of the chain
+        let mut outputs = self.chips.iter_mut().map(|chip| chip.transfer(buffer)).collect::<Vec<_>>();
+        let (mut dout, frame_start) = outputs.remove(0);
+        if let Some(start) = frame_start {
+            dout.truncate(start + FRAME_BYTES);
+            for (other, other_start) in outputs {
+                if let Some(other_start) = other_start {
+                    dout.extend_from_slice(&other[other_start..other_start + FRAME_BYTES]);
+                }
+            }
+        }
+        copy_dout(buffer, dout);
+        Ok(())
+    }
+}
+
+fn copy_dout(buffer: &mut [u8], dout: Vec<u8>) {
+    buffer.fill(0);
+    for (out, value) in buffer.iter_mut().zip(dout) {
+        *out = value;
+    }
+}
+
+impl EmulatedChip {
+    fn reset(&mut self) {
+        self.registers = RESET_REGISTERS;
+        self.standby = false;
+        self.continuous = true;
+        self.converting = false;
+        self.data_ready = false;
+        self.frame = [0; FRAME_BYTES];
+        self.sample_index = 0;
+    }
+
+    fn sample_rate(&self) -> u32 {
+        16_000 >> (self.registers[reg::CONFIG1 as usize] & 0x07).min(6)
+    }
+
+    fn convert(&mut self, signal: &InputSignal, noise: Option<Normal<f64>>) -> bool {
+        if !self.converting || self.standby {
+            return false;
+        }
+
+        let t = self.sample_index as f64 / self.sample_rate() as f64;
+        self.update_lead_off_status();
+        let status = self.status_word();
+        self.frame[..STATUS_BYTES].copy_from_slice(&status);
+        for ch in 0..ADS1299_CHANNELS {
+            let code = self.channel_code(ch, t, signal, noise).to_be_bytes();
+            let offset = STATUS_BYTES + ch * BYTES_PER_SAMPLE;
+            self.frame[offset..offset + BYTES_PER_SAMPLE].copy_from_slice(&code[1..]);
+        }
+
+        self.sample_index += 1;
+        self.data_ready = true;
+        true
+    }
+
+    /// Latch the DC lead-off comparator outputs for sensed inputs. With the comparators
+    /// powered down the status registers are left alone, so tests can poke them directly.
+    fn update_lead_off_status(&mut self) {
+        let comparators_on = self.registers[reg::CONFIG4 as usize] & 0x02 != 0;
+        let dc_detection = self.registers[reg::LOFF as usize] & 0x03 == 0;
+        if comparators_on && dc_detection {
+            self.registers[reg::LOFF_STATP as usize] = self.leads_off_p & self.registers[reg::LOFF_SENSP as usize];
+            self.registers[reg::LOFF_STATN as usize] = self.leads_off_n & self.registers[reg::LOFF_SENSN as usize];
+        }
+    }
+
+    /// 1100 + LOFF_STATP + LOFF_STATN + GPIO[7:4]
+    fn status_word(&self) -> [u8; STATUS_BYTES] {
+        let statp = self.registers[reg::LOFF_STATP as usize];
+        let statn = self.registers[reg::LOFF_STATN as usize];
+        let gpio = self.registers[reg::GPIO as usize];
+        [
+            0xC0 | (statp >> 4),
+            (statp << 4) | (statn >> 4),
+            (statn << 4) | (gpio >> 4),
+        ]
+    }
+
+    fn channel_code(&self, ch: usize, t: f64, signal: &InputSignal, noise: Option<Normal<f64>>) -> i32 {
+        let chset = self.registers[reg::CH1SET as usize + ch];
+        if chset & 0x80 != 0 {
+            return 0;  // Powered down
+        }
+
+        let gain = [1.0, 2.0, 4.0, 6.0, 8.0, 12.0, 24.0][((chset >> 4) & 0x07).min(6) as usize];
+        let volts = match chset & 0x07 {
+            0b000 => signal(self.first_channel + ch, t),
+            0b001 => 0.0,  // Input shorted
+            0b011 => if ch == 2 || ch == 3 { 1.8 / 4.0 } else { 2.5 },  // MVDD: DVDD/4 or (AVDD - AVSS)/2
+            0b100 => 0.1453,  // Temperature sensor at 25 degC
+            0b101 => self.test_signal(t),
+            _ => 0.0,  // BIAS_MEAS, BIAS_DRP, BIAS_DRN
+        };
+        let noise = noise.map_or(0.0, |n| n.sample(&mut rand::thread_rng()));
+
+        let full_scale = (1i64 << 23) as f64;
+        let code = ((volts + noise) * gain / EMULATOR_VREF * full_scale).round();
+        code.clamp(-full_scale, full_scale - 1.0) as i32
+    }
+
+    /// Internal square-wave test signal selected by CONFIG2.
+    fn test_signal(&self, t: f64) -> f64 {
+        let config2 = self.registers[reg::CONFIG2 as usize];
+        if config2 & 0x10 == 0 {
+            return 0.0;  // Test signal driven externally
+        }
+        let amplitude = test_signal_amplitude(EMULATOR_VREF, config2 & 0x04 != 0);
+        let frequency = match config2 & 0x03 {
+            0b00 => TestSignalFrequency::Slow,
+            0b01 => TestSignalFrequency::Fast,
+            _ => return amplitude,  // DC
+        }.hz().unwrap_or_default();
+        if (t * frequency).fract() < 0.5 { amplitude } else { -amplitude }
+    }
+
+    /// Process one chip-select-framed transfer of DIN. Returns the bytes the chip drives
+    /// on DOUT (possibly longer than DIN) and where its data frame starts, if it sent one.
+    fn transfer(&mut self, din: &[u8]) -> (Vec<u8>, Option<usize>) {
+        // In RDATAC mode a pending frame is shifted out at the start of the transfer
+        let mut dout: Vec<u8> = Vec::new();
+        let mut frame_start = None;
+        if self.continuous && self.data_ready {
+            dout.extend_from_slice(&self.frame);
+            frame_start = Some(0);
+            self.data_ready = false;
+        }
+
+        let mut i = 0;
+        while i < din.len() {
+            let byte = din[i];
+            i += 1;
+            match byte {
+                opcode::WAKEUP => self.standby = false,
+                opcode::STANDBY => self.standby = true,
+                opcode::RESET => self.reset(),
+                opcode::START => {
+                    self.converting = true;
+                    self.sample_index = 0;
+                }
+                opcode::STOP => self.converting = false,
+                opcode::RDATAC => self.continuous = true,
+                opcode::SDATAC => self.continuous = false,
+                opcode::RDATA => {
+                    dout.resize(i, 0);
+                    frame_start = Some(i);
+                    dout.extend_from_slice(&self.frame);
+                    self.data_ready = false;
+                }
+                op if op & 0xE0 == opcode::RREG || op & 0xE0 == opcode::WREG => {
+                    let start = (op & 0x1F) as usize;
+                    let count = din.get(i).map_or(1, |&n| n as usize + 1);
+                    i += 1;
+                    if self.continuous {
+                        // RREG and WREG are ignored in RDATAC mode
+                        warn!("Emulated ADS1299 ignored register access 0x{:02X} in RDATAC mode", op);
+                        i += if op & 0xE0 == opcode::WREG { count } else { 0 };
+                        continue;
+                    }
+                    if op & 0xE0 == opcode::RREG {
+                        dout.resize(i, 0);
+                        for address in start..start + count {
+                            dout.push(self.registers.get(address).copied().unwrap_or(0));
+                        }
+                        i += count;
+                    } else {
+                        for (address, &value) in (start..start + count).zip(din.iter().skip(i)) {
+                            if address < reg::COUNT && !READ_ONLY.contains(&(address as u8)) {
+                                trace!("Emulated ADS1299 WREG 0x{:02X} = 0x{:02X}", address, value);
+                                self.registers[address] = value;
+                            }
+                        }
+                        i += count;
+                    }
+                }
+                _ => {}  // NOP or data clock bytes
+            }
+        }
+
+        (dout, frame_start)
+    }
+}
+
+impl Ads1299Bus for Ads1299Emulator {
+    fn transfer(&mut self, chip_select: usize, buffer: &mut [u8]) -> Result<(), DriverError> {
+        self.board().transfer(chip_select, buffer)
+    }
+
+    fn wait_for_drdy(&mut self, timeout: Duration) -> Result<u64, DriverError> {
+        let (converted, realtime, period) = {
+            let mut board = self.board();
+            let period = Duration::from_secs_f64(1.0 / board.chips[0].sample_rate() as f64);
+            (board.convert(), board.realtime, period)
+        };
+
+        if !converted {
+            debug!("Emulated ADS1299 not converting, DRDY timed out");
+            std::thread::sleep(timeout);
+        } else if realtime {
+            std::thread::sleep(period);
+        }
+        // Conversions only advance on a wait, so none is ever missed
+        Ok(converted as u64)
+    }
+
+    fn delay(&mut self, _duration: Duration) {}
+}
@@ -0,0 +1,743 @@
+use serde::{Serialize, Deserialize};
+use super::ads1299_driver::{reg, ChipSelectMode, ADS1299_CHANNELS, FCLK_HZ};
+use super::types::{AdcConfig, ChannelSettings, DriverError, DriverType, LeadOffConfig, TestSignalConfig};
+
+/// CONFIG1 DR[2:0]: output data rate with the internal 2.048 MHz clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DataRate {
+    Sps16000,
+    Sps8000,
+    Sps4000,
+    Sps2000,
+    Sps1000,
+    Sps500,
+    Sps250,
+}
+
+impl DataRate {
+    pub(crate) const ALL: [DataRate; 7] = [
+        DataRate::Sps16000, DataRate::Sps8000, DataRate::Sps4000, DataRate::Sps2000,
+        DataRate::Sps1000, DataRate::Sps500, DataRate::Sps250,
+    ];
+
+    /// Data rate for a sample rate in Hz, if the chip supports it.
+    pub fn from_hz(hz: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|rate| rate.hz() == hz)
+    }
+
+    pub fn hz(self) -> u32 {
+        16_000 >> self.code()
+    }
+
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    /// Decode DR[2:0]. Code 0b111 is reserved on the ADS1299.
+    pub fn from_code(code: u8) -> Option<Self> {
+        Self::ALL.get(code as usize).copied()
+    }
+}
+
+/// CHnSET GAIN[2:0]: programmable gain amplifier setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PgaGain {
+    X1,
+    X2,
+    X4,
+    X6,
+    X8,
+    X12,
+    X24,
+}
+
+impl PgaGain {
+    pub(crate) const ALL: [PgaGain; 7] = [
+        PgaGain::X1, PgaGain::X2, PgaGain::X4, PgaGain::X6,
+        PgaGain::X8, PgaGain::X12, PgaGain::X24,
+    ];
+
+    /// PGA setting for a gain value, if the PGA supports it.
+    pub fn from_gain(gain: f32) -> Option<Self> {
+        Self::ALL.into_iter().find(|pga| pga.gain() == gain)
+    }
+
+    pub fn gain(self) -> f32 {
+        [1.0, 2.0, 4.0, 6.0, 8.0, 12.0, 24.0][self as usize]
+    }
+
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    /// Decode GAIN[2:0]. Code 0b111 is reserved on the ADS1299.
+    pub fn from_code(code: u8) -> Option<Self> {
+        Self::ALL.get(code as usize).copied()
+    }
+}
+
+/// CHnSET MUX[2:0]: what the channel's PGA is connected to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum InputMux {
+    /// Normal electrode input
+    #[default]
+    Normal,
+    /// Inputs shorted together (offset and noise measurement)
+    Shorted,
+    /// Measure the bias drive output (needs CONFIG3 BIAS_MEAS)
+    BiasMeasure,
+    /// Supply measurement (MVDD)
+    Supply,
+    /// Temperature sensor
+    Temperature,
+    /// Internal test signal
+    TestSignal,
+    /// Bias drive on the positive electrode
+    BiasDrivePositive,
+    /// Bias drive on the negative electrode
+    BiasDriveNegative,
+}
+
+impl InputMux {
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_code(code: u8) -> Self {
+        [
+            InputMux::Normal, InputMux::Shorted, InputMux::BiasMeasure, InputMux::Supply,
+            InputMux::Temperature, InputMux::TestSignal, InputMux::BiasDrivePositive,
+            InputMux::BiasDriveNegative,
+        ][(code & 0x07) as usize]
+    }
+}
+
+/// One CHnSET register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelRegister {
+    pub power_down: bool,
+    pub gain: PgaGain,
+    /// Connect the positive input to SRB2
+    pub srb2: bool,
+    pub mux: InputMux,
+}
+
+impl ChannelRegister {
+    /// Powered-down channel with its inputs shorted, the state unused channels are left in.
+    pub const OFF: ChannelRegister = ChannelRegister {
+        power_down: true,
+        gain: PgaGain::X1,
+        srb2: false,
+        mux: InputMux::Shorted,
+    };
+
+    pub fn encode(self) -> u8 {
+        (self.power_down as u8) << 7 | self.gain.code() << 4 | (self.srb2 as u8) << 3 | self.mux.code()
+    }
+
+    pub fn decode(value: u8) -> Result<Self, DriverError> {
+        let gain = PgaGain::from_code((value >> 4) & 0x07).ok_or_else(|| DriverError::ConfigurationError(
+            format!("Reserved PGA gain code in CHnSET value 0x{:02X}", value)
+        ))?;
+        Ok(Self {
+            power_down: value & 0x80 != 0,
+            gain,
+            srb2: value & 0x08 != 0,
+            mux: InputMux::from_code(value),
+        })
+    }
+}
+
+/// CONFIG2 CAL_FREQ[1:0]: internal test signal frequency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TestSignalFrequency {
+    /// fCLK / 2^21, about 1 Hz
+    #[default]
+    Slow,
+    /// fCLK / 2^20, about 2 Hz
+    Fast,
+    /// DC level
+    Dc,
+}
+
+impl TestSignalFrequency {
+    /// Square-wave frequency with the internal oscillator, `None` for the DC level.
+    pub fn hz(self) -> Option<f64> {
+        match self {
+            TestSignalFrequency::Slow => Some(FCLK_HZ / (1 << 21) as f64),
+            TestSignalFrequency::Fast => Some(FCLK_HZ / (1 << 20) as f64),
+            TestSignalFrequency::Dc => None,
+        }
+    }
+}
+
+/// Peak amplitude in volts of the internal test signal: (VREFP - VREFN) / 2.4 mV, doubled
+/// with CAL_AMP.
+pub fn test_signal_amplitude(vref: f64, double_amplitude: bool) -> f64 {
+    let scale = if double_amplitude { 2.0 } else { 1.0 };
+    scale * vref / 2400.0
+}
+
+/// CONFIG2: test signal source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestSignal {
+    /// Generate the test signal internally (INT_CAL)
+    pub internal: bool,
+    /// 2 x (VREFP - VREFN) / 2.4 mV instead of 1 x
+    pub double_amplitude: bool,
+    pub frequency: TestSignalFrequency,
+}
+
+impl TestSignal {
+    fn encode(self) -> u8 {
+        let freq = match self.frequency {
+            TestSignalFrequency::Slow => 0b00,
+            TestSignalFrequency::Fast => 0b01,
+            TestSignalFrequency::Dc => 0b11,
+        };
+        0xC0 | (self.internal as u8) << 4 | (self.double_amplitude as u8) << 2 | freq
+    }
+
+    fn decode(value: u8) -> Result<Self, DriverError> {
+        let frequency = match value & 0x03 {
+            0b00 => TestSignalFrequency::Slow,
+            0b01 => TestSignalFrequency::Fast,
+            0b11 => TestSignalFrequency::Dc,
+            _ => return Err(DriverError::ConfigurationError(
+                format!("Reserved test signal frequency in CONFIG2 value 0x{:02X}", value)
+            )),
+        };
+        Ok(Self {
+            internal: value & 0x10 != 0,
+            double_amplitude: value & 0x04 != 0,
+            frequency,
+        })
+    }
+}
+
+impl Default for TestSignal {
+    fn default() -> Self {
+        Self { internal: false, double_amplitude: false, frequency: TestSignalFrequency::Slow }
+    }
+}
+
+/// LOFF ILEAD_OFF[1:0]: lead-off excitation current magnitude.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LeadOffCurrent {
+    #[default]
+    Na6,
+    Na24,
+    Ua6,
+    Ua24,
+}
+
+impl LeadOffCurrent {
+    /// Excitation current in amperes.
+    pub fn amps(self) -> f64 {
+        [6e-9, 24e-9, 6e-6, 24e-6][self as usize]
+    }
+}
+
+/// LOFF FLEAD_OFF[1:0]: lead-off excitation frequency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LeadOffFrequency {
+    /// DC lead-off detection, reported through the comparators
+    #[default]
+    Dc,
+    /// AC excitation at fCLK / 2^18 (7.8 Hz)
+    Ac7_8,
+    /// AC excitation at fCLK / 2^16 (31.2 Hz)
+    Ac31_2,
+    /// AC excitation at a quarter of the data rate
+    AcQuarterDataRate,
+}
+
+impl LeadOffFrequency {
+    /// Excitation frequency in Hz at `sample_rate`, or `None` for DC.
+    pub fn hz(self, sample_rate: u32) -> Option<f64> {
+        match self {
+            LeadOffFrequency::Dc => None,
+            LeadOffFrequency::Ac7_8 => Some(2_048_000.0 / (1 << 18) as f64),
+            LeadOffFrequency::Ac31_2 => Some(2_048_000.0 / (1 << 16) as f64),
+            LeadOffFrequency::AcQuarterDataRate => Some(sample_rate as f64 / 4.0),
+        }
+    }
+}
+
+/// LOFF COMP_TH[2:0]: comparator threshold, as the positive-side percentage of full scale.
+/// The negative side threshold is 100% minus this value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LeadOffThreshold {
+    #[default]
+    Pct95,
+    Pct92_5,
+    Pct90,
+    Pct87_5,
+    Pct85,
+    Pct80,
+    Pct75,
+    Pct70,
+}
+
+/// The LOFF register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct LeadOffRegister {
+    pub threshold: LeadOffThreshold,
+    pub current: LeadOffCurrent,
+    pub frequency: LeadOffFrequency,
+}
+
+impl LeadOffRegister {
+    pub fn encode(self) -> u8 {
+        (self.threshold as u8) << 5 | (self.current as u8) << 2 | self.frequency as u8
+    }
+
+    pub fn decode(value: u8) -> Self {
+        const THRESHOLDS: [LeadOffThreshold; 8] = [
+            LeadOffThreshold::Pct95, LeadOffThreshold::Pct92_5, LeadOffThreshold::Pct90,
+            LeadOffThreshold::Pct87_5, LeadOffThreshold::Pct85, LeadOffThreshold::Pct80,
+            LeadOffThreshold::Pct75, LeadOffThreshold::Pct70,
+        ];
+        const CURRENTS: [LeadOffCurrent; 4] = [
+            LeadOffCurrent::Na6, LeadOffCurrent::Na24, LeadOffCurrent::Ua6, LeadOffCurrent::Ua24,
+        ];
+        const FREQUENCIES: [LeadOffFrequency; 4] = [
+            LeadOffFrequency::Dc, LeadOffFrequency::Ac7_8, LeadOffFrequency::Ac31_2,
+            LeadOffFrequency::AcQuarterDataRate,
+        ];
+        Self {
+            threshold: THRESHOLDS[(value >> 5) as usize],
+            current: CURRENTS[((value >> 2) & 0x03) as usize],
+            frequency: FREQUENCIES[(value & 0x03) as usize],
+        }
+    }
+}
+
+/// Human-readable register names, indexed by address.
+const REGISTER_NAMES: [&str; reg::COUNT] = [
+    "ID", "CONFIG1", "CONFIG2", "CONFIG3", "LOFF",
+    "CH1SET", "CH2SET", "CH3SET", "CH4SET", "CH5SET", "CH6SET", "CH7SET", "CH8SET",
+    "BIAS_SENSP", "BIAS_SENSN", "LOFF_SENSP", "LOFF_SENSN", "LOFF_FLIP",
+    "LOFF_STATP", "LOFF_STATN", "GPIO", "MISC1", "MISC2", "CONFIG4",
+];
+
+/// Bits compared during read-back verification, indexed by address. Read-only
+/// registers and status bits driven by the chip are masked out.
+const VERIFY_MASK: [u8; reg::COUNT] = [
+    0x00, 0xFF, 0xFF, 0xFE, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0x00, 0x00, 0x0F, 0xFF, 0xFF, 0xFF,
+];
+
+/// A register whose read-back value differs from what was written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterMismatch {
+    pub address: u8,
+    pub name: &'static str,
+    pub expected: u8,
+    pub actual: u8,
+}
+
+impl std::fmt::Display for RegisterMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (0x{:02X}): wrote 0x{:02X}, read 0x{:02X}", self.name, self.address, self.expected, self.actual)
+    }
+}
+
+/// Typed model of the writable ADS1299 register map.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegisterMap {
+    // CONFIG1
+    pub data_rate: DataRate,
+    /// Multiple-readback mode instead of daisy-chain mode (DAISY_EN)
+    pub multiple_readback: bool,
+    /// Drive the oscillator clock on the CLK pin (CLK_EN)
+    pub clock_output: bool,
+    // CONFIG2
+    pub test_signal: TestSignal,
+    // CONFIG3
+    /// Internal reference buffer enabled (PD_REFBUF)
+    pub internal_reference: bool,
+    /// Route BIAS_IN to the channels set to InputMux::BiasMeasure (BIAS_MEAS)
+    pub bias_measure: bool,
+    /// Generate the bias reference internally (BIASREF_INT)
+    pub bias_reference_internal: bool,
+    /// Bias buffer powered up (PD_BIAS)
+    pub bias_enabled: bool,
+    pub lead_off: LeadOffRegister,
+    pub channels: [ChannelRegister; ADS1299_CHANNELS],
+    pub bias_sensp: u8,
+    pub bias_sensn: u8,
+    pub lead_off_sensp: u8,
+    pub lead_off_sensn: u8,
+    pub lead_off_flip: u8,
+    /// GPIO control nibble (1 = input)
+    pub gpio_control: u8,
+    /// Connect SRB1 to all negative inputs (MISC1)
+    pub srb1: bool,
+    // CONFIG4
+    pub single_shot: bool,
+    /// Lead-off comparators powered up (PD_LOFF_COMP)
+    pub lead_off_comparators: bool,
+}
+
+impl RegisterMap {
+    /// First register written by [`RegisterMap::to_bytes`].
+    pub const FIRST: u8 = reg::CONFIG1;
+
+    /// Encode a single-chip ADC configuration. See [`RegisterMap::for_chips`].
+    pub fn from_config(config: &AdcConfig) -> Result<Self, DriverError> {
+        let mut maps = Self::for_chips(config)?;
+        if maps.len() != 1 {
+            return Err(DriverError::ConfigurationError(
+                format!("Configuration spans {} chips, use RegisterMap::for_chips", maps.len())
+            ));
+        }
+        Ok(maps.remove(0))
+    }
+
+    /// Encode an ADC configuration into one register map per chip. Channel `n` lives on
+    /// chip `n / 8`. Configured channels get their per-channel settings (or the flat
+    /// gain on the normal electrode input), the others are powered down with their
+    /// inputs shorted. Chip-wide bits that channels depend on (internal test signal,
+    /// bias buffer, BIAS_MEAS) are switched on as needed.
+    ///
+    /// Daisy-chained chips share DIN and chip select, so every register write reaches
+    /// all of them. In that mode the maps are merged into one identical map per chip,
+    /// and channels at the same position on different chips must agree.
+    pub fn for_chips(config: &AdcConfig) -> Result<Vec<Self>, DriverError> {
+        let data_rate = DataRate::from_hz(config.sample_rate).ok_or_else(|| DriverError::ConfigurationError(
+            format!("ADS1299 does not support a sample rate of {} Hz", config.sample_rate)
+        ))?;
+        config.validate_channel_settings()?;
+        if config.chip_count == 0 {
+            return Err(DriverError::ConfigurationError("Chip count must be greater than 0".to_string()));
+        }
+
+        let total_channels = config.chip_count * ADS1299_CHANNELS;
+        let mut maps = vec![Self { data_rate, ..Self::default() }; config.chip_count];
+        for (i, &ch) in config.channels.iter().enumerate() {
+            if ch >= total_channels {
+                return Err(DriverError::ConfigurationError(
+                    format!("Channel {} out of range ({} ADS1299 chips have {} channels)",
+                            ch, config.chip_count, total_channels)
+                ));
+            }
+            if config.channels[..i].contains(&ch) {
+                return Err(DriverError::ConfigurationError(format!("Channel {} configured twice", ch)));
+            }
+
+            let settings = config.settings_for(i);
+            let gain = PgaGain::from_gain(settings.gain).ok_or_else(|| DriverError::ConfigurationError(
+                format!("ADS1299 does not support a gain of {} (channel {})", settings.gain, ch)
+            ))?;
+            let map = &mut maps[ch / ADS1299_CHANNELS];
+            let local = ch % ADS1299_CHANNELS;
+            map.channels[local] = ChannelRegister {
+                power_down: settings.power_down,
+                gain,
+                srb2: settings.srb2,
+                mux: settings.input,
+            };
+            if settings.bias {
+                map.bias_sensp |= 1 << local;
+                map.bias_sensn |= 1 << local;
+            }
+            if settings.power_down {
+                continue;
+            }
+            match settings.input {
+                InputMux::TestSignal => {
+                    map.test_signal = TestSignal {
+                        internal: true,
+                        double_amplitude: config.test_signal.double_amplitude,
+                        frequency: config.test_signal.frequency,
+                    };
+                }
+                InputMux::BiasMeasure => map.bias_measure = true,
+                _ => {}
+            }
+            // Only electrode inputs have leads that can come off
+            if let (Some(lead_off), InputMux::Normal) = (&config.lead_off, settings.input) {
+                map.lead_off_sensp |= (lead_off.positive as u8) << local;
+                map.lead_off_sensn |= (lead_off.negative as u8) << local;
+            }
+        }
+        for map in maps.iter_mut().filter(|map| map.bias_sensp != 0) {
+            map.bias_enabled = true;
+            map.bias_reference_internal = true;
+        }
+        if let Some(lead_off) = &config.lead_off {
+            for map in maps.iter_mut() {
+                map.lead_off = LeadOffRegister {
+                    threshold: lead_off.threshold,
+                    current: lead_off.current,
+                    frequency: lead_off.frequency,
+                };
+                map.lead_off_comparators = true;
+            }
+        }
+
+        if config.chip_select == ChipSelectMode::DaisyChain && maps.len() > 1 {
+            merge_daisy_chain(&mut maps)?;
+        }
+        for map in &maps {
+            map.validate()?;
+        }
+        Ok(maps)
+    }
+
+    /// Decode a single-chip register map back into an ADC configuration.
+    /// See [`RegisterMap::chips_to_config`].
+    pub fn to_config(&self) -> Result<AdcConfig, DriverError> {
+        Self::chips_to_config(std::slice::from_ref(self))
+    }
+
+    /// Decode one register map per chip back into an ADC configuration. Every channel
+    /// that is not in the unused state ([`ChannelRegister::OFF`]) becomes a configured
+    /// channel with explicit per-channel settings.
+    pub fn chips_to_config(maps: &[Self]) -> Result<AdcConfig, DriverError> {
+        let first = maps.first().ok_or_else(|| DriverError::ConfigurationError(
+            "No register maps to decode".to_string()
+        ))?;
+
+        let mut channels = Vec::new();
+        let mut channel_settings: Vec<ChannelSettings> = Vec::new();
+        for (chip, map) in maps.iter().enumerate() {
+            for (local, channel) in map.channels.iter().enumerate() {
+                if *channel == ChannelRegister::OFF {
+                    continue;
+                }
+                channels.push(chip * ADS1299_CHANNELS + local);
+                channel_settings.push(ChannelSettings {
+                    gain: channel.gain.gain(),
+                    input: channel.mux,
+                    power_down: channel.power_down,
+                    bias: map.bias_sensp & (1 << local) != 0,
+                    srb2: channel.srb2,
+                });
+            }
+        }
+
+        Ok(AdcConfig {
+            sample_rate: first.data_rate.hz(),
+            gain: channel_settings.first().map_or(1.0, |s| s.gain),
+            channels,
+            board_driver: DriverType::Ads1299,
+            channel_settings,
+            chip_count: maps.len(),
+            lead_off: first.lead_off_comparators.then(|| LeadOffConfig {
+                current: first.lead_off.current,
+                frequency: first.lead_off.frequency,
+                threshold: first.lead_off.threshold,
+                positive: maps.iter().any(|map| map.lead_off_sensp != 0),
+                negative: maps.iter().any(|map| map.lead_off_sensn != 0),
+            }),
+            test_signal: TestSignalConfig {
+                frequency: first.test_signal.frequency,
+                double_amplitude: first.test_signal.double_amplitude,
+            },
+            ..AdcConfig::default()
+        })
+    }
+
+    /// Reject register combinations the chip would accept but that cannot produce valid data.
+    pub fn validate(&self) -> Result<(), DriverError> {
+        for (ch, channel) in self.channels.iter().enumerate().filter(|(_, c)| !c.power_down) {
+            let n = ch + 1;
+            if self.srb1 && channel.srb2 {
+                return Err(DriverError::ConfigurationError(
+                    format!("Channel {} cannot be referenced to both SRB1 and SRB2", n)
+                ));
+            }
+            match channel.mux {
+                InputMux::TestSignal if !self.test_signal.internal => {
+                    return Err(DriverError::ConfigurationError(
+                        format!("Channel {} uses the test signal but the internal test source is disabled", n)
+                    ));
+                }
+                InputMux::BiasMeasure if !self.bias_measure => {
+                    return Err(DriverError::ConfigurationError(
+                        format!("Channel {} measures bias but CONFIG3 BIAS_MEAS is disabled", n)
+                    ));
+                }
+                InputMux::Supply if channel.gain != PgaGain::X1 => {
+                    return Err(DriverError::ConfigurationError(
+                        format!("Channel {} supply measurement saturates above gain 1", n)
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Register values from CONFIG1 through CONFIG4, ready for a single WREG.
+    /// Read-only registers are included as zero; the chip ignores writes to them.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0u8; reg::COUNT];
+        bytes[reg::CONFIG1 as usize] = 0x90
+            | (self.multiple_readback as u8) << 6
+            | (self.clock_output as u8) << 5
+            | self.data_rate.code();
+        bytes[reg::CONFIG2 as usize] = self.test_signal.encode();
+        bytes[reg::CONFIG3 as usize] = 0x60
+            | (self.internal_reference as u8) << 7
+            | (self.bias_measure as u8) << 4
+            | (self.bias_reference_internal as u8) << 3
+            | (self.bias_enabled as u8) << 2;
+        bytes[reg::LOFF as usize] = self.lead_off.encode();
+        for (ch, channel) in self.channels.iter().enumerate() {
+            bytes[reg::CH1SET as usize + ch] = channel.encode();
+        }
+        bytes[reg::BIAS_SENSP as usize] = self.bias_sensp;
+        bytes[reg::BIAS_SENSN as usize] = self.bias_sensn;
+        bytes[reg::LOFF_SENSP as usize] = self.lead_off_sensp;
+        bytes[reg::LOFF_SENSN as usize] = self.lead_off_sensn;
+        bytes[reg::LOFF_FLIP as usize] = self.lead_off_flip;
+        bytes[reg::GPIO as usize] = self.gpio_control & 0x0F;
+        bytes[reg::MISC1 as usize] = (self.srb1 as u8) << 5;
+        bytes[reg::CONFIG4 as usize] = (self.single_shot as u8) << 3 | (self.lead_off_comparators as u8) << 1;
+        bytes.split_off(Self::FIRST as usize)
+    }
+
+    /// Decode a full register dump (addresses 0x00 through 0x17).
+    pub fn from_bytes(registers: &[u8]) -> Result<Self, DriverError> {
+        if registers.len() != reg::COUNT {
+            return Err(DriverError::ConfigurationError(
+                format!("Expected {} register values, got {}", reg::COUNT, registers.len())
+            ));
+        }
+        let at = |address: u8| registers[address as usize];
+
+        let config1 = at(reg::CONFIG1);
+        let data_rate = DataRate::from_code(config1 & 0x07).ok_or_else(|| DriverError::ConfigurationError(
+            format!("Reserved data rate in CONFIG1 value 0x{:02X}", config1)
+        ))?;
+        let config3 = at(reg::CONFIG3);
+        let config4 = at(reg::CONFIG4);
+
+        let mut channels = [ChannelRegister::OFF; ADS1299_CHANNELS];
+        for (ch, channel) in channels.iter_mut().enumerate() {
+            *channel = ChannelRegister::decode(at(reg::CH1SET + ch as u8))?;
+        }
+
+        Ok(Self {
+            data_rate,
+            multiple_readback: config1 & 0x40 != 0,
+            clock_output: config1 & 0x20 != 0,
+            test_signal: TestSignal::decode(at(reg::CONFIG2))?,
+            internal_reference: config3 & 0x80 != 0,
+            bias_measure: config3 & 0x10 != 0,
+            bias_reference_internal: config3 & 0x08 != 0,
+            bias_enabled: config3 & 0x04 != 0,
+            lead_off: LeadOffRegister::decode(at(reg::LOFF)),
+            channels,
+            bias_sensp: at(reg::BIAS_SENSP),
+            bias_sensn: at(reg::BIAS_SENSN),
+            lead_off_sensp: at(reg::LOFF_SENSP),
+            lead_off_sensn: at(reg::LOFF_SENSN),
+            lead_off_flip: at(reg::LOFF_FLIP),
+            gpio_control: at(reg::GPIO) & 0x0F,
+            srb1: at(reg::MISC1) & 0x20 != 0,
+            single_shot: config4 & 0x08 != 0,
+            lead_off_comparators: config4 & 0x02 != 0,
+        })
+    }
+
+    /// Compare a read-back of the registers written by [`RegisterMap::to_bytes`]
+    /// (starting at [`RegisterMap::FIRST`]) and list every register that differs.
+    pub fn diff(&self, readback: &[u8]) -> Vec<RegisterMismatch> {
+        self.to_bytes().into_iter()
+            .zip(readback.iter().copied().chain(std::iter::repeat(0)))
+            .enumerate()
+            .filter_map(|(i, (expected, actual))| {
+                let address = Self::FIRST as usize + i;
+                let mask = VERIFY_MASK[address];
+                (expected & mask != actual & mask).then_some(RegisterMismatch {
+                    address: address as u8,
+                    name: REGISTER_NAMES[address],
+                    expected,
+                    actual,
+                })
+            })
+            .collect()
+    }
+
+    /// Like [`RegisterMap::diff`], but as a `ConfigurationError` naming the mismatched registers.
+    pub fn verify(&self, readback: &[u8]) -> Result<(), DriverError> {
+        let mismatches = self.diff(readback);
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+        let report: Vec<String> = mismatches.iter().map(|m| m.to_string()).collect();
+        Err(DriverError::ConfigurationError(
+            format!("Register read-back mismatch: {}", report.join("; "))
+        ))
+    }
+}
+
+impl Default for RegisterMap {
+    /// Internal reference on, every channel powered down, everything else at reset values.
+    fn default() -> Self {
+        Self {
+            data_rate: DataRate::Sps250,
+            multiple_readback: false,
+            clock_output: false,
+            test_signal: TestSignal::default(),
+            internal_reference: true,
+            bias_measure: false,
+            bias_reference_internal: false,
+            bias_enabled: false,
+            lead_off: LeadOffRegister::default(),
+            channels: [ChannelRegister::OFF; ADS1299_CHANNELS],
+            bias_sensp: 0x00,
+            bias_sensn: 0x00,
+            lead_off_sensp: 0x00,
+            lead_off_sensn: 0x00,
+            lead_off_flip: 0x00,
+            gpio_control: 0x0F,
+            srb1: false,
+            single_shot: false,
+            lead_off_comparators: false,
+        }
+    }
+}
+
+/// Fold per-chip maps into the single map every daisy-chained chip receives.
+fn merge_daisy_chain(maps: &mut [RegisterMap]) -> Result<(), DriverError> {
+    let mut shared = maps[0].clone();
+    let mut owner = [0usize; ADS1299_CHANNELS];
+
+    for (chip, map) in maps.iter().enumerate().skip(1) {
+        for (local, &theirs) in map.channels.iter().enumerate() {
+            if theirs == ChannelRegister::OFF {
+                continue;
+            }
+            let ours = &mut shared.channels[local];
+            if *ours == ChannelRegister::OFF {
+                *ours = theirs;
+                owner[local] = chip;
+            } else if *ours != theirs {
+                return Err(DriverError::ConfigurationError(format!(
+                    "Daisy-chained chips share register writes, but channels {} and {} have different settings",
+                    owner[local] * ADS1299_CHANNELS + local, chip * ADS1299_CHANNELS + local
+                )));
+            }
+        }
+        shared.bias_sensp |= map.bias_sensp;
+        shared.bias_sensn |= map.bias_sensn;
+        shared.lead_off_sensp |= map.lead_off_sensp;
+        shared.lead_off_sensn |= map.lead_off_sensn;
+        if map.test_signal.internal {
+            shared.test_signal = map.test_signal;
+        }
+        shared.bias_measure |= map.bias_measure;
+        shared.bias_enabled |= map.bias_enabled;
+        shared.bias_reference_internal |= map.bias_reference_internal;
+    }
+
+    maps.fill(shared);
+    Ok(())
+}
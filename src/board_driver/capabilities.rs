@@ -0,0 +1,83 @@
+use serde::{Serialize, Deserialize};
+
+use super::types::{AdcConfig, DriverError, DriverType};
+
+/// Sample rates a driver can run at.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SampleRates {
+    /// Only these rates, in Hz, ascending
+    Listed(Vec<u32>),
+    /// Any whole number of Hz from `min` to `max`
+    Range { min: u32, max: u32 },
+}
+
+impl SampleRates {
+    pub fn contains(&self, hz: u32) -> bool {
+        match self {
+            SampleRates::Listed(rates) => rates.contains(&hz),
+            SampleRates::Range { min, max } => (*min..=*max).contains(&hz),
+        }
+    }
+}
+
+/// How `AdcData.timestamp` relates to when a sample was taken. Timestamps are system
+/// time in microseconds either way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampPrecision {
+    /// Taken as each sample is read off the chip, off by the read loop's scheduling jitter
+    PerSample,
+    /// Taken as a batch is put together, so the samples of a batch carry about the same
+    /// time; only the batch timing is meaningful
+    PerBatch,
+}
+
+/// What a driver and the hardware behind it support.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DriverCapabilities {
+    pub sample_rates: SampleRates,
+    /// PGA gains, ascending
+    pub gains: Vec<f32>,
+    /// Channels on the configured chips; channel numbers run below this
+    pub max_channels: usize,
+    pub lead_off_detection: bool,
+    pub impedance_measurement: bool,
+    pub timestamp_precision: TimestampPrecision,
+}
+
+impl DriverCapabilities {
+    /// What the driver `config` selects supports with the chips it describes, without
+    /// creating the driver.
+    pub fn for_config(config: &AdcConfig) -> Self {
+        match config.board_driver {
+            DriverType::Ads1299 => super::ads1299_driver::Ads1299Driver::capabilities_for(config),
+            DriverType::Mock => super::mock_driver::MockDriver::capabilities_for(config),
+        }
+    }
+
+    /// Check that `config` only asks for what is supported.
+    pub fn validate(&self, config: &AdcConfig) -> Result<(), DriverError> {
+        if !self.sample_rates.contains(config.sample_rate) {
+            return Err(DriverError::ConfigurationError(
+                format!("Sample rate of {} Hz not supported, use one of {:?}", config.sample_rate, self.sample_rates)
+            ));
+        }
+        for gain in (0..config.channels.len()).map(|i| config.settings_for(i).gain) {
+            if !self.gains.contains(&gain) {
+                return Err(DriverError::ConfigurationError(
+                    format!("Gain {} not supported, use one of {:?}", gain, self.gains)
+                ));
+            }
+        }
+        if let Some(&ch) = config.channels.iter().find(|&&ch| ch >= self.max_channels) {
+            return Err(DriverError::ConfigurationError(
+                format!("Channel {} out of range, {} channels available", ch, self.max_channels)
+            ));
+        }
+        if config.lead_off.is_some() && !self.lead_off_detection {
+            return Err(DriverError::ConfigurationError("Lead-off detection not supported".to_string()));
+        }
+        Ok(())
+    }
+}
@@ -5,36 +5,126 @@ use tokio::time::{sleep, Duration};
 use async_trait::async_trait;
 use log::{info, warn, debug, trace, error};
 use lazy_static::lazy_static;
-use super::types::{AdcConfig, AdcData, DriverStatus, DriverError, DriverEvent, DriverType};
+use rand_distr::{Distribution, Normal};
+use serde::{Serialize, Deserialize};
+use super::ads1299_driver::{volts_to_code, Ads1299Driver, ADS1299_CHANNELS, FULL_SCALE_CODE};
+use super::capabilities::{DriverCapabilities, SampleRates, TimestampPrecision};
+use super::ads1299_registers::InputMux;
+use super::types::{current_timestamp_micros, AdcConfig, AdcData, DriverStatus, DriverError, DriverEvent, DriverType, LeadOffMonitor};
 
 // Static hardware lock to simulate real hardware access constraints
 lazy_static! {
     static ref HARDWARE_LOCK: std::sync::Mutex<bool> = std::sync::Mutex::new(false);
 }
 
+/// Amplitude of the mock test sine waves at the electrode input.
+pub const MOCK_AMPLITUDE_UV: f32 = 50.0;
+
+/// Electrode impedance simulated for channels without an entry in `MockSettings::impedance_kohms`.
+pub const DEFAULT_MOCK_IMPEDANCE_KOHMS: f32 = 10.0;
+
+/// Highest sample rate the mock runs at, that of the ADS1299.
+const MAX_SAMPLE_RATE: u32 = 16_000;
+
+/// Simulation settings for the mock driver, given to [`MockDriver::with_settings`]. They
+/// stay with the driver across reconfigurations.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MockSettings {
+    /// Electrodes that fall off (and optionally reconnect) during acquisition.
+    /// Only reported when `AdcConfig::lead_off` enables detection on that input.
+    #[serde(default)]
+    pub lead_off: Vec<ScriptedLeadOff>,
+    /// Electrode impedance per configured channel, parallel to `AdcConfig::channels`.
+    /// With AC lead-off excitation enabled, each channel picks up the excitation
+    /// current times this impedance.
+    #[serde(default)]
+    pub impedance_kohms: Vec<f32>,
+    /// White input-referred noise added to every enabled channel, in microvolts RMS
+    #[serde(default)]
+    pub noise_uv_rms: f32,
+    /// Artifacts injected into the electrode signals during acquisition
+    #[serde(default)]
+    pub artifacts: Vec<ScriptedArtifact>,
+    /// Runs of samples that are never delivered, like missed SPI reads, as (index of the
+    /// first, count)
+    #[serde(default)]
+    pub dropped_samples: Vec<(u64, u64)>,
+}
+
+impl MockSettings {
+    /// Simulated impedance of the channel at position `index` in `AdcConfig::channels`.
+    pub fn impedance_for(&self, index: usize) -> f32 {
+        self.impedance_kohms.get(index).copied().unwrap_or(DEFAULT_MOCK_IMPEDANCE_KOHMS)
+    }
+}
+
+/// One scripted electrode disconnection, in seconds since acquisition started.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScriptedLeadOff {
+    pub channel: usize,
+    /// The negative (reference) input comes off instead of the positive one
+    #[serde(default)]
+    pub negative: bool,
+    pub off_at_secs: f32,
+    /// When the electrode is reattached; `None` keeps it off
+    #[serde(default)]
+    pub on_at_secs: Option<f32>,
+}
+
+impl ScriptedLeadOff {
+    fn is_off(&self, t_secs: f32) -> bool {
+        t_secs >= self.off_at_secs && self.on_at_secs.is_none_or(|on| t_secs < on)
+    }
+}
+
+/// Disturbance injected into a mock electrode signal.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MockArtifact {
+    /// The input is driven to the positive rail
+    Saturation,
+    /// The input reads a constant zero, as if disconnected, noise included
+    Flatline,
+    /// A DC offset for the duration, stepping up at the start and back down at the end
+    Step { offset_uv: f32 },
+    /// A sine added to the signal: slow and large for movement, fast for muscle
+    Oscillation { amplitude_uv: f32, hz: f32 },
+}
+
+/// One scripted artifact, in seconds since acquisition started.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScriptedArtifact {
+    pub channel: usize,
+    pub artifact: MockArtifact,
+    pub from_secs: f32,
+    /// When the artifact ends; `None` keeps it going
+    #[serde(default)]
+    pub until_secs: Option<f32>,
+}
+
+impl ScriptedArtifact {
+    fn is_active(&self, t_secs: f32) -> bool {
+        t_secs >= self.from_secs && self.until_secs.is_none_or(|until| t_secs < until)
+    }
+}
+
 /// A stubbed-out driver that does not access any hardware.
 pub struct MockDriver {
     inner: Arc<Mutex<MockInner>>,
     task_handle: Option<JoinHandle<()>>,
     tx: mpsc::Sender<DriverEvent>,
-    additional_channel_buffering: usize,
+    // Whether this instance still holds HARDWARE_LOCK
+    owns_hardware: bool,
 }
 
 /// Internal state for the MockDriver.
 struct MockInner {
     config: AdcConfig,
+    settings: MockSettings,
     running: bool,
     status: DriverStatus,
 }
 
-/// Helper function to get current timestamp in microseconds
-fn current_timestamp_micros() -> Result<u64, DriverError> {
-    std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .map(|d| d.as_micros() as u64)
-        .map_err(|e| DriverError::Other(format!("Failed to get timestamp: {}", e)))
-}
-
 impl MockDriver {
     /// Create a new instance of the MockDriver.
     ///
@@ -64,9 +154,20 @@ impl MockDriver {
     /// - config.board_driver is not DriverType::Mock
     /// - config.batch_size is 0 (batch size must be positive)
     /// - config.batch_size is less than the number of channels (need at least one sample per channel)
+    /// - the configuration asks for more than [`MockDriver::capabilities_for`] offers
     pub fn new(
         config: AdcConfig,
         additional_channel_buffering: usize
+    ) -> Result<(Self, mpsc::Receiver<DriverEvent>), DriverError> {
+        Self::with_settings(config, MockSettings::default(), additional_channel_buffering)
+    }
+
+    /// Like [`MockDriver::new`], simulating the electrodes, noise, artifacts and lost
+    /// samples `settings` script.
+    pub fn with_settings(
+        config: AdcConfig,
+        settings: MockSettings,
+        additional_channel_buffering: usize
     ) -> Result<(Self, mpsc::Receiver<DriverEvent>), DriverError> {
         // Try to acquire the hardware lock to simulate real hardware access constraints
         let mut hardware_in_use = HARDWARE_LOCK.lock()
@@ -82,31 +183,10 @@ impl MockDriver {
         *hardware_in_use = true;
         
         // Validate config
-        if config.board_driver != DriverType::Mock {
+        if let Err(e) = check_config(&config) {
             // Release the lock if we're returning an error
             *hardware_in_use = false;
-            return Err(DriverError::ConfigurationError(
-                "MockDriver requires config.board_driver=DriverType::Mock".to_string()
-            ));
-        }
-        
-        // Validate batch size
-        if config.batch_size == 0 {
-            // Release the lock if we're returning an error
-            *hardware_in_use = false;
-            return Err(DriverError::ConfigurationError(
-                "Batch size must be greater than 0".to_string()
-            ));
-        }
-        
-        // Validate batch size relative to channel count
-        if config.batch_size < config.channels.len() {
-            // Release the lock if we're returning an error
-            *hardware_in_use = false;
-            return Err(DriverError::ConfigurationError(
-                format!("Batch size ({}) must be at least equal to the number of channels ({})",
-                        config.batch_size, config.channels.len())
-            ));
+            return Err(e);
         }
         
         // Validate total buffer size (prevent excessive memory usage)
@@ -123,6 +203,7 @@ impl MockDriver {
         
         let inner = MockInner {
             config: config.clone(),
+            settings,
             running: false,
             status: DriverStatus::Ok,
         };
@@ -134,7 +215,7 @@ impl MockDriver {
             inner: Arc::new(Mutex::new(inner)),
             task_handle: None,
             tx,
-            additional_channel_buffering,
+            owns_hardware: true,
         };
         
         info!("MockDriver created with config: {:?}", config);
@@ -144,6 +225,28 @@ impl MockDriver {
         Ok((driver, rx))
     }
     
+    /// What the mock supports: the ADS1299's gains and channels on the chips `config`
+    /// describes, at any sample rate up to the ADS1299's highest.
+    pub fn capabilities_for(config: &AdcConfig) -> DriverCapabilities {
+        DriverCapabilities {
+            sample_rates: SampleRates::Range { min: 1, max: MAX_SAMPLE_RATE },
+            timestamp_precision: TimestampPrecision::PerBatch,
+            ..Ads1299Driver::capabilities_for(config)
+        }
+    }
+
+    /// Take on a new configuration while acquisition is stopped.
+    pub(crate) async fn configure(&mut self, config: AdcConfig) -> Result<(), DriverError> {
+        check_config(&config)?;
+        let mut inner = self.inner.lock().await;
+        if inner.running {
+            return Err(DriverError::ConfigurationError("Stop acquisition before reconfiguring".to_string()));
+        }
+        info!("MockDriver reconfigured with config: {:?}", config);
+        inner.config = config;
+        Ok(())
+    }
+
     /// Return the current configuration.
     pub(crate) async fn get_config(&self) -> Result<AdcConfig, DriverError> {
         let inner = self.inner.lock().await;
@@ -181,68 +284,66 @@ impl MockDriver {
         // Spawn a task that periodically sends dummy data
         let handle = tokio::spawn(async move {
             // Get configuration without holding the lock for the entire task
-            let config = {
+            let (config, settings) = {
                 let inner = inner_arc.lock().await;
-                inner.config.clone()
+                (inner.config.clone(), inner.settings.clone())
             };
             
             // Get batch size from config
             let batch_size = config.batch_size;
             
-            // Get initial time as our zero reference
-            let start_time = match current_timestamp_micros() {
-                Ok(time) => time,
-                Err(e) => {
-                    error!("Failed to get start timestamp: {:?}", e);
-                    return;
-                }
-            };
+            // Samples generated so far; the test signals are a function of sample time
+            // so they stay continuous across batches
+            let mut sample_count: u64 = 0;
             
             debug!("Starting acquisition with batch size: {}, sample rate: {} Hz",
                    batch_size, config.sample_rate);
+            let mut lead_off = LeadOffMonitor::new(&config);
+            let noise = (settings.noise_uv_rms > 0.0)
+                .then(|| Normal::new(0.0, settings.noise_uv_rms as f64 * 1e-6).ok())
+                .flatten();
             
             // Main acquisition loop
             loop {
                 // Check if we should continue running
                 let should_continue = {
                     let inner = inner_arc.lock().await;
-                    if !inner.running {
-                        false
-                    } else {
-                        true
-                    }
+                    inner.running
                 };
                 
                 if !should_continue {
                     break;
                 }
                 
-                // Calculate timing parameters
+                // Generate a batch of samples at consecutive sample times, leaving out
+                // those scripted to be lost
                 let mut batch = Vec::with_capacity(batch_size);
-                let sample_interval = (1_000_000 / config.sample_rate) as u64; // microseconds between samples
-                debug!("Sample interval: {} microseconds", sample_interval);
-                
-                // Get current timestamp relative to start time
-                let base_timestamp = match current_timestamp_micros() {
-                    Ok(time) => time.saturating_sub(start_time),
-                    Err(e) => {
-                        error!("Failed to get current timestamp: {:?}", e);
-                        break;
-                    }
-                };
-                
-                // Generate a batch of samples with incrementing timestamps
                 for i in 0..batch_size {
-                    let relative_timestamp = base_timestamp + i as u64 * sample_interval;
+                    let relative_timestamp = sample_count * 1_000_000 / config.sample_rate as u64;
                     trace!("Sample {}: relative_time={} microseconds", i, relative_timestamp);
-                    let sample = test_data(&config, relative_timestamp);
-                    batch.push(sample);
+                    let dropped = settings.dropped_samples.iter()
+                        .any(|&(first, count)| (first..first + count).contains(&sample_count));
+                    if !dropped {
+                        batch.push(test_data(&config, &settings, sample_count, relative_timestamp, noise));
+                    }
+                    sample_count += 1;
                 }
                 
-                // Send the batch of data
-                if let Err(e) = tx.send(DriverEvent::Data(batch)).await {
-                    warn!("MockDriver event channel closed: {}", e);
-                    break;
+                // Report lead-off changes ahead of the samples they were seen in
+                let changes = lead_off.as_mut().map(|monitor| monitor.check(&batch)).unwrap_or_default();
+                for change in changes {
+                    if let Err(e) = tx.send(DriverEvent::LeadOff(change)).await {
+                        warn!("MockDriver event channel closed: {}", e);
+                        return;
+                    }
+                }
+
+                // Send the batch of data, unless every sample in it was lost
+                if !batch.is_empty() {
+                    if let Err(e) = tx.send(DriverEvent::Data(batch)).await {
+                        warn!("MockDriver event channel closed: {}", e);
+                        break;
+                    }
                 }
                 
                 // Sleep for the time it would take to collect this batch via SPI
@@ -335,12 +436,31 @@ impl MockDriver {
             // Config is now static, so we don't need to reset it
         }
         
+        // Free the simulated hardware so a replacement driver can be created
+        // while this one is still alive
+        self.release_hardware();
+        
         // Notify about the status change
         self.notify_status_change().await?;
         info!("MockDriver shutdown complete");
         Ok(())
     }
 
+    /// Release HARDWARE_LOCK if this instance still holds it.
+    fn release_hardware(&mut self) {
+        if !self.owns_hardware {
+            return;
+        }
+        match HARDWARE_LOCK.lock() {
+            Ok(mut lock) => {
+                *lock = false;
+                self.owns_hardware = false;
+                debug!("Hardware lock released");
+            }
+            Err(_) => error!("Failed to release hardware lock"),
+        }
+    }
+
     /// Internal helper to notify status changes over the event channel.
     ///
     /// This method sends a status change event to any listeners.
@@ -357,7 +477,11 @@ impl MockDriver {
         self.tx
             .send(DriverEvent::StatusChange(status))
             .await
-            .map_err(|e| DriverError::Other(format!("Failed to send status change: {}", e)))
+            .or_else(|_| {
+                // Nobody is listening any more, e.g. shutting down after the consumer went away
+                debug!("Event channel closed, status change {:?} not delivered", status);
+                Ok(())
+            })
     }
 }
 
@@ -365,15 +489,53 @@ impl MockDriver {
 /// Each channel's sine wave frequency is defined by:
 ///     channel 0: 2 Hz, channel 1: 6 Hz, channel 2: 10 Hz, etc.
 /// (i.e., channel i gets 2 + 4*i Hz).
-fn test_data(config: &AdcConfig, relative_micros: u64) -> AdcData {
+/// Each is a [`MOCK_AMPLITUDE_UV`] sine at the input, returned as the output codes the
+/// ADS1299 would produce at the channel's gain; powered-down channels read zero.
+/// Channels on the internal test signal see its square wave, and other non-electrode
+/// inputs read zero. `noise`, if any, is added to every enabled channel in volts, and
+/// the artifacts `mock` scripts are applied on top. Codes saturate at the ADC's full scale.
+fn test_data(config: &AdcConfig, mock: &MockSettings, sample_index: u64, relative_micros: u64, noise: Option<Normal<f64>>) -> AdcData {
     let t_secs = relative_micros as f32 / 1_000_000.0;
     trace!("Generating sample at t={} secs", t_secs);
 
+    // AC lead-off excitation current flowing through the positive electrodes, if enabled
+    let excitation = config.lead_off.as_ref()
+        .filter(|lead_off| lead_off.positive)
+        .and_then(|lead_off| lead_off.frequency.hz(config.sample_rate).map(|hz| (hz, lead_off.current.amps())));
+
     // For each channel, generate a sine wave sample based on its unique frequency.
-    let samples: Vec<Vec<f32>> = config.channels.iter().enumerate().map(|(i, _)| {
+    let samples: Vec<Vec<f32>> = config.channels.iter().enumerate().map(|(i, &channel)| {
+        let settings = config.settings_for(i);
+        if settings.power_down {
+            return vec![0.0];
+        }
         let freq = 2.0 + (i as f32) * 4.0; // 2 Hz for ch0, 6 Hz for ch1, etc.
         let angle = 2.0 * std::f32::consts::PI * freq * t_secs;
-        let waveform = angle.sin();
+        let mut volts = match settings.input {
+            InputMux::Normal => (MOCK_AMPLITUDE_UV * 1e-6 * angle.sin()) as f64,
+            InputMux::TestSignal => test_signal(config, t_secs as f64),
+            _ => 0.0,
+        };
+        if let (Some((hz, amps)), InputMux::Normal) = (excitation, settings.input) {
+            volts += amps * mock.impedance_for(i) as f64 * 1e3
+                * (2.0 * std::f64::consts::PI * hz * t_secs as f64).sin();
+        }
+        volts += noise.map_or(0.0, |n| n.sample(&mut rand::thread_rng()));
+        let mut saturated = false;
+        if settings.input == InputMux::Normal {
+            for script in mock.artifacts.iter().filter(|s| s.channel == channel && s.is_active(t_secs)) {
+                match script.artifact {
+                    MockArtifact::Saturation => saturated = true,
+                    MockArtifact::Flatline => volts = 0.0,
+                    MockArtifact::Step { offset_uv } => volts += offset_uv as f64 * 1e-6,
+                    MockArtifact::Oscillation { amplitude_uv, hz } => {
+                        volts += amplitude_uv as f64 * 1e-6 * (2.0 * std::f64::consts::PI * hz as f64 * t_secs as f64).sin();
+                    }
+                }
+            }
+        }
+        let code = if saturated { FULL_SCALE_CODE } else { volts_to_code(volts, settings.gain).round() };
+        let waveform = code.clamp(-FULL_SCALE_CODE, FULL_SCALE_CODE - 1.0) as f32;
         trace!("Channel {}: freq={} Hz, angle={} rad, value={}", i, freq, angle, waveform);
         vec![waveform]
     }).collect();
@@ -391,7 +553,58 @@ fn test_data(config: &AdcConfig, relative_micros: u64) -> AdcData {
         }
     };
     
-    AdcData { samples, timestamp }
+    // Status word of every chip: 1100 + LOFF_STATP + LOFF_STATN + GPIO
+    let mut status = vec![0xC0_0000u32; config.chip_count];
+    if let Some(lead_off) = &config.lead_off {
+        for script in mock.lead_off.iter().filter(|s| s.is_off(t_secs)) {
+            let sensed = if script.negative { lead_off.negative } else { lead_off.positive };
+            if let (true, Some(word)) = (sensed, status.get_mut(script.channel / ADS1299_CHANNELS)) {
+                let shift = if script.negative { 4 } else { 12 };
+                *word |= 1 << (shift + script.channel % ADS1299_CHANNELS);
+            }
+        }
+    }
+
+    AdcData { samples, timestamp, sample_index, status }
+}
+
+/// The internal square-wave test signal selected by `config.test_signal`, in volts.
+fn test_signal(config: &AdcConfig, t_secs: f64) -> f64 {
+    let amplitude = config.test_signal.amplitude_volts(config.vref);
+    match config.test_signal.frequency.hz() {
+        Some(hz) if (t_secs * hz).fract() >= 0.5 => -amplitude,
+        _ => amplitude,
+    }
+}
+
+/// Check that `config` is one the mock can run.
+fn check_config(config: &AdcConfig) -> Result<(), DriverError> {
+    if config.board_driver != DriverType::Mock {
+        return Err(DriverError::ConfigurationError(
+            "MockDriver requires config.board_driver=DriverType::Mock".to_string()
+        ));
+    }
+    
+    // Validate batch size
+    if config.batch_size == 0 {
+        return Err(DriverError::ConfigurationError(
+            "Batch size must be greater than 0".to_string()
+        ));
+    }
+    
+    // Validate per-channel settings, and that rates, gains and channels are ones the
+    // emulated chips have (8 channels per ADS1299)
+    config.validate_channel_settings()?;
+    MockDriver::capabilities_for(config).validate(config)?;
+    
+    // Validate batch size relative to channel count
+    if config.batch_size < config.channels.len() {
+        return Err(DriverError::ConfigurationError(
+            format!("Batch size ({}) must be at least equal to the number of channels ({})",
+                    config.batch_size, config.channels.len())
+        ));
+    }
+    Ok(())
 }
 
 // Implement the AdcDriver trait
@@ -416,6 +629,15 @@ impl super::types::AdcDriver for MockDriver {
     async fn get_config(&self) -> Result<AdcConfig, DriverError> {
         self.get_config().await
     }
+
+    async fn configure(&mut self, config: AdcConfig) -> Result<(), DriverError> {
+        self.configure(config).await
+    }
+
+    async fn capabilities(&self) -> DriverCapabilities {
+        let config = self.inner.lock().await.config.clone();
+        Self::capabilities_for(&config)
+    }
 }
 
 /// Implementation of Drop for MockDriver to handle cleanup when the driver is dropped.
@@ -426,8 +648,10 @@ impl super::types::AdcDriver for MockDriver {
 impl Drop for MockDriver {
     fn drop(&mut self) {
         // Since we can't use .await in Drop, we'll just log a warning
-        error!("MockDriver dropped without calling shutdown() first. This may lead to resource leaks.");
-        error!("Always call driver.shutdown().await before dropping the driver.");
+        if self.owns_hardware {
+            error!("MockDriver dropped without calling shutdown() first. This may lead to resource leaks.");
+            error!("Always call driver.shutdown().await before dropping the driver.");
+        }
         
         // Note: We can't properly clean up in Drop because we can't use .await
         // This is why users should call shutdown() explicitly.
@@ -438,72 +662,7 @@ impl Drop for MockDriver {
             error!("Background task may still be running. Call shutdown() to properly terminate it.");
         }
         
-        // Release the hardware lock
-        if let Ok(mut lock) = HARDWARE_LOCK.lock() {
-            *lock = false;
-            debug!("Hardware lock released in Drop implementation");
-        } else {
-            error!("Failed to release hardware lock in Drop implementation");
-        }
-    }
-}
-
-// The following is a reference implementation for a real hardware driver.
-// This is kept as documentation to show how a real hardware implementation
-// might differ from the mock implementation.
-/*
-/// Driver for the ADS1299 EEG analog front-end chip.
-/// This is a conceptual implementation that would be used with real hardware.
-pub struct Ads1299Driver {
-    spi: Spi,                     // SPI interface to communicate with the chip
-    drdy_pin: Pin,                // Data Ready pin for interrupt-driven acquisition
-    ring_buffer: RingBuffer,      // Circular buffer for samples
-    sample_counter: u64,          // Track samples for timing
-    tx: mpsc::Sender<DriverEvent>, // Event channel
-    config: Option<AdcConfig>,    // Current configuration
-    status: DriverStatus,         // Current status
-}
-
-impl Ads1299Driver {
-    /// Create a new instance of the ADS1299 driver
-    pub fn new() -> Result<(Self, mpsc::Receiver<DriverEvent>), DriverError> {
-        // Initialize SPI, GPIO, etc.
-        // ...
-        
-        let (tx, rx) = mpsc::channel(100);
-        Ok((Self { /* ... */ }, rx))
+        // Release the hardware lock unless shutdown() already did
+        self.release_hardware();
     }
-    
-    /// Start data acquisition from the ADS1299
-    async fn start_acquisition(&mut self) -> Result<(), DriverError> {
-        // Set up DRDY interrupt handler for real-time data collection
-        self.drdy_pin.set_interrupt_handler(move || {
-            // Read data immediately when DRDY triggers
-            let samples = self.spi.read_frame()?;
-            self.ring_buffer.push(samples);
-        });
-
-        // Start continuous conversion mode by writing to the chip's registers
-        self.write_register(REG_CONFIG1, START_CONTINUOUS_CONVERSION)?;
-        
-        // Spawn high-priority task for buffer management and event dispatch
-        tokio::spawn(async move {
-            while self.is_running() {
-                // Read a batch of samples from the ring buffer
-                let batch = self.ring_buffer.read_batch(32)?;
-                
-                // Send the batch to listeners
-                if let Err(e) = self.tx.send(DriverEvent::Data(batch)).await {
-                    error!("Failed to send batch: {}", e);
-                    break;
-                }
-            }
-        });
-        
-        Ok(())
-    }
-    
-    // Other methods would be implemented similarly to MockDriver
-    // but with real hardware interactions
 }
-*/
\ No newline at end of file
@@ -1,7 +1,22 @@
+pub mod ads1299_driver;
+pub mod ads1299_emulator;
+pub mod ads1299_registers;
+pub mod capabilities;
 pub mod mock_driver;
+pub mod montage;
+pub mod self_test;
 pub mod types;
 
 // Re-export types for convenience
-pub use self::types::{AdcData, AdcConfig, DriverEvent, DriverStatus, DriverError, AdcDriver, DriverType};
-pub use self::mock_driver::MockDriver;
-pub use self::types::create_driver;
\ No newline at end of file
+pub use self::types::{AdcData, AdcConfig, ChannelSettings, DriverEvent, DriverStatus, DriverError, AdcDriver, DriverType, ChannelCalibration, LeadOffConfig, LeadOffStatus, TestSignalConfig};
+pub use self::capabilities::{DriverCapabilities, SampleRates, TimestampPrecision};
+pub use self::mock_driver::{MockArtifact, MockDriver, MockSettings, ScriptedArtifact, ScriptedLeadOff};
+pub use self::montage::{ElectrodeInfo, ElectrodeType, Montage, Position};
+pub use self::ads1299_driver::{Ads1299Bus, Ads1299Driver, ChipSelectMode, RppalBus};
+pub use self::ads1299_emulator::Ads1299Emulator;
+pub use self::ads1299_registers::RegisterMap;
+pub use self::self_test::{run_self_test, run_self_test_with, ChannelSelfTest, SelfTestReport, SelfTestSettings};
+pub use self::types::create_driver;
+
+#[cfg(test)]
+pub(crate) mod tests;
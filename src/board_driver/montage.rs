@@ -0,0 +1,173 @@
+use serde::{Serialize, Deserialize};
+
+/// What an input is wired to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ElectrodeType {
+    #[default]
+    Eeg,
+    Eog,
+    Emg,
+    Ecg,
+    /// Anything else: sensors, triggers, spare inputs
+    Aux,
+}
+
+/// Electrode position on a unit sphere centred in the head: `x` towards the right ear,
+/// `y` towards the nose and `z` towards the vertex.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Position {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Position {
+    /// Point at `elevation` degrees above the plane through the nasion and ears and
+    /// `azimuth` degrees from the right ear towards the nose.
+    fn spherical(elevation: f64, azimuth: f64) -> Self {
+        let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+        Self {
+            x: (elevation.cos() * azimuth.cos()) as f32,
+            y: (elevation.cos() * azimuth.sin()) as f32,
+            z: elevation.sin() as f32,
+        }
+    }
+
+    fn to_array(self) -> [f64; 3] {
+        [self.x as f64, self.y as f64, self.z as f64]
+    }
+}
+
+/// Name, type and position of the electrode on one input.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ElectrodeInfo {
+    pub label: String,
+    #[serde(default)]
+    pub kind: ElectrodeType,
+    #[serde(default)]
+    pub position: Option<Position>,
+}
+
+impl ElectrodeInfo {
+    /// An electrode of `kind` with no known position.
+    pub fn new(label: impl Into<String>, kind: ElectrodeType) -> Self {
+        Self { label: label.into(), kind, position: None }
+    }
+
+    /// An EEG electrode named in `montage`, placed where the montage puts it. Names the
+    /// montage doesn't know get no position.
+    pub fn eeg(label: impl Into<String>, montage: Montage) -> Self {
+        let label = label.into();
+        let position = montage.position(&label);
+        Self { label, kind: ElectrodeType::Eeg, position }
+    }
+}
+
+/// Standard electrode placement systems, on an idealized spherical head.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Montage {
+    /// The 21 sites of the international 10-20 system, the ear lobes A1 and A2, and the
+    /// older names T3, T4, T5 and T6
+    Standard1020,
+    /// The 10-10 extension, a superset of 10-20 with the sites between them
+    Standard1010,
+}
+
+/// Sites on the circumference 10% above the nasion, ears and inion, every 18°.
+const RING: [(&str, f64); 20] = [
+    ("T8", 0.0), ("FT8", 18.0), ("F8", 36.0), ("AF8", 54.0), ("Fp2", 72.0), ("Fpz", 90.0),
+    ("Fp1", 108.0), ("AF7", 126.0), ("F7", 144.0), ("FT7", 162.0), ("T7", 180.0),
+    ("TP7", 198.0), ("P7", 216.0), ("PO7", 234.0), ("O1", 252.0), ("Oz", 270.0),
+    ("O2", 288.0), ("PO8", 306.0), ("P8", 324.0), ("TP8", 342.0),
+];
+
+/// Rows across the head, each an arc from a left ring site through a midline site to a
+/// right ring site in four equal steps per side: `(prefix, midline elevation, front)`.
+const ROWS: [(&str, f64, bool); 7] = [
+    ("AF", 36.0, true), ("F", 54.0, true), ("FC", 72.0, true), ("C", 90.0, true),
+    ("CP", 72.0, false), ("P", 54.0, false), ("PO", 36.0, false),
+];
+
+/// Sites of the 10-20 system, named as in 10-10.
+const SITES_1020: [&str; 21] = [
+    "Fp1", "Fpz", "Fp2", "F7", "F3", "Fz", "F4", "F8", "T7", "C3", "Cz", "C4", "T8",
+    "P7", "P3", "Pz", "P4", "P8", "O1", "Oz", "O2",
+];
+
+/// 10-20 names that 10-10 replaced.
+const OLD_NAMES: [(&str, &str); 4] = [("T3", "T7"), ("T4", "T8"), ("T5", "P7"), ("T6", "P8")];
+
+impl Montage {
+    /// Position of the site called `label`, ignoring case.
+    pub fn position(&self, label: &str) -> Option<Position> {
+        let label = OLD_NAMES.iter()
+            .find(|(old, _)| old.eq_ignore_ascii_case(label))
+            .map_or(label, |(_, new)| new);
+        let known = match self {
+            Montage::Standard1020 => SITES_1020.iter().any(|site| site.eq_ignore_ascii_case(label))
+                || ["A1", "A2"].iter().any(|site| site.eq_ignore_ascii_case(label)),
+            Montage::Standard1010 => true,
+        };
+        if !known {
+            return None;
+        }
+        Self::sites().into_iter()
+            .find(|(site, _)| site.eq_ignore_ascii_case(label))
+            .map(|(_, position)| position)
+    }
+
+    /// Every 10-10 site with its position. Rows are built the usual way, dividing the arc
+    /// through three known sites into equal angles.
+    fn sites() -> Vec<(String, Position)> {
+        let mut sites: Vec<(String, Position)> = RING.iter()
+            .map(|&(name, azimuth)| (name.to_string(), Position::spherical(18.0, azimuth)))
+            .collect();
+        for (name, azimuth) in [("Nz", 90.0), ("Iz", 270.0), ("A1", 180.0), ("A2", 0.0)] {
+            sites.push((name.to_string(), Position::spherical(0.0, azimuth)));
+        }
+
+        let ring = |name: &str| RING.iter().find(|(site, _)| *site == name).map(|&(_, azimuth)| Position::spherical(18.0, azimuth));
+        for (prefix, elevation, front) in ROWS {
+            let (left, right) = match prefix {
+                "C" => (ring("T7"), ring("T8")),
+                "FC" => (ring("FT7"), ring("FT8")),
+                "CP" => (ring("TP7"), ring("TP8")),
+                _ => (ring(&format!("{}7", prefix)), ring(&format!("{}8", prefix))),
+            };
+            let (Some(left), Some(right)) = (left, right) else { continue };
+            let middle = Position::spherical(elevation, if front { 90.0 } else { 270.0 });
+            sites.push((format!("{}z", prefix), middle));
+            // Odd numbers on the left, from 5 next to the ring inwards; even on the right
+            for step in 1..4 {
+                let fraction = step as f64 / 4.0;
+                sites.push((format!("{}{}", prefix, 7 - 2 * step), along_arc(left, middle, right, fraction)));
+                sites.push((format!("{}{}", prefix, 8 - 2 * step), along_arc(right, middle, left, fraction)));
+            }
+        }
+        sites
+    }
+}
+
+/// The point `fraction` of the way from `from` to `to` along the circle on the unit sphere
+/// through `from`, `to` and `other`.
+fn along_arc(from: Position, to: Position, other: Position, fraction: f64) -> Position {
+    let [a, b, c] = [from.to_array(), to.to_array(), other.to_array()];
+    let sub = |p: [f64; 3], q: [f64; 3]| [p[0] - q[0], p[1] - q[1], p[2] - q[2]];
+    let dot = |p: [f64; 3], q: [f64; 3]| p[0] * q[0] + p[1] * q[1] + p[2] * q[2];
+    let cross = |p: [f64; 3], q: [f64; 3]| [p[1] * q[2] - p[2] * q[1], p[2] * q[0] - p[0] * q[2], p[0] * q[1] - p[1] * q[0]];
+    let scale = |p: [f64; 3], s: f64| [p[0] * s, p[1] * s, p[2] * s];
+
+    // The circle lies in the plane through the three points, centred where its normal
+    // through the sphere's centre meets it
+    let normal = cross(sub(b, a), sub(c, a));
+    let normal = scale(normal, 1.0 / dot(normal, normal).sqrt());
+    let centre = scale(normal, dot(normal, a));
+    let (u, v) = (sub(a, centre), sub(b, centre));
+    let angle = dot(cross(u, v), normal).atan2(dot(u, v)) * fraction;
+    // Rotate `u` about the normal
+    let rotated = sub(scale(u, angle.cos()), scale(cross(u, normal), angle.sin()));
+    let [x, y, z] = [centre[0] + rotated[0], centre[1] + rotated[1], centre[2] + rotated[2]];
+    Position { x: x as f32, y: y as f32, z: z as f32 }
+}
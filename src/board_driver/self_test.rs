@@ -0,0 +1,293 @@
+use std::future::Future;
+use log::{debug, info, warn};
+use serde::{Serialize, Deserialize};
+use tokio::sync::mpsc;
+use tokio::time::{timeout, Duration};
+
+use super::ads1299_registers::{InputMux, TestSignalFrequency};
+use super::types::{create_driver, AdcConfig, AdcDriver, ChannelSettings, DriverError, DriverEvent, TestSignalConfig};
+use crate::dsp::units::{SignalUnit, UnitConverter};
+
+/// How long each self-test phase records and what a channel must meet to pass.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SelfTestSettings {
+    /// Internal test signal used for the amplitude and frequency check; must not be DC
+    pub test_signal: TestSignalConfig,
+    /// Samples kept per phase, in seconds
+    pub phase_secs: f32,
+    /// Samples discarded at the start of each phase while the digital filter settles
+    pub settle_secs: f32,
+    /// Allowed relative error of the measured test-signal amplitude and frequency
+    pub tolerance: f32,
+    /// Highest input-referred noise accepted with the inputs shorted
+    pub max_noise_uv_rms: f32,
+    pub max_noise_uv_pp: f32,
+}
+
+impl Default for SelfTestSettings {
+    /// The 2 Hz test signal over one-second phases, within 10%, and noise limits that hold
+    /// for the ADS1299 at gain 24 and 250 SPS with margin.
+    fn default() -> Self {
+        Self {
+            test_signal: TestSignalConfig { frequency: TestSignalFrequency::Fast, double_amplitude: false },
+            phase_secs: 1.0,
+            settle_secs: 0.1,
+            tolerance: 0.1,
+            max_noise_uv_rms: 1.0,
+            max_noise_uv_pp: 6.0,
+        }
+    }
+}
+
+/// Self-test results of one channel.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChannelSelfTest {
+    pub channel: usize,
+    /// Measured test-signal amplitude, half the step between its two levels
+    pub test_amplitude_uv: f32,
+    /// Measured test-signal frequency, 0 if fewer than two edges were seen
+    pub test_frequency_hz: f32,
+    /// Mean level with the inputs shorted, the channel's input offset
+    pub offset_uv: f32,
+    pub noise_uv_rms: f32,
+    pub noise_uv_pp: f32,
+    pub amplitude_ok: bool,
+    pub frequency_ok: bool,
+    pub noise_ok: bool,
+}
+
+impl ChannelSelfTest {
+    pub fn passed(&self) -> bool {
+        self.amplitude_ok && self.frequency_ok && self.noise_ok
+    }
+}
+
+/// Outcome of [`run_self_test`], one entry per enabled channel in config order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SelfTestReport {
+    pub expected_amplitude_uv: f32,
+    pub expected_frequency_hz: f32,
+    pub channels: Vec<ChannelSelfTest>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.channels.iter().all(ChannelSelfTest::passed)
+    }
+
+    /// Results for `channel`, if it was tested.
+    pub fn channel(&self, channel: usize) -> Option<&ChannelSelfTest> {
+        self.channels.iter().find(|c| c.channel == channel)
+    }
+}
+
+/// Run the self-test on the driver `config` selects. See [`run_self_test_with`].
+pub async fn run_self_test(config: &AdcConfig, settings: &SelfTestSettings) -> Result<SelfTestReport, DriverError> {
+    run_self_test_with(config, settings, create_driver).await
+}
+
+/// Check every enabled channel of `config` in two phases: first on the internal test
+/// signal, whose amplitude and frequency are compared with the datasheet values, then
+/// with the inputs shorted to measure offset and noise. Channels keep their gain.
+///
+/// `make_driver` creates a driver for each phase's configuration; it is shut down once
+/// the phase is recorded, so at most one exists at a time. Failing checks are reported,
+/// not returned as errors.
+pub async fn run_self_test_with<F, Fut>(
+    config: &AdcConfig,
+    settings: &SelfTestSettings,
+    mut make_driver: F,
+) -> Result<SelfTestReport, DriverError>
+where
+    F: FnMut(AdcConfig) -> Fut,
+    Fut: Future<Output = Result<(Box<dyn AdcDriver>, mpsc::Receiver<DriverEvent>), DriverError>>,
+{
+    let expected_frequency = settings.test_signal.frequency.hz().ok_or_else(|| DriverError::ConfigurationError(
+        "Self-test needs a square-wave test signal, not DC".to_string()
+    ))? as f32;
+    if settings.phase_secs <= 0.0 || settings.settle_secs < 0.0 {
+        return Err(DriverError::ConfigurationError(
+            "Self-test phase must be longer than 0 s and settling time not negative".to_string()
+        ));
+    }
+    config.validate_channel_settings()?;
+
+    let enabled: Vec<usize> = (0..config.channels.len())
+        .filter(|&i| !config.settings_for(i).power_down)
+        .collect();
+    if enabled.is_empty() {
+        return Err(DriverError::ConfigurationError("No enabled channels to test".to_string()));
+    }
+    let expected_amplitude = (settings.test_signal.amplitude_volts(config.vref) * 1e6) as f32;
+
+    info!("Self-test: recording test signal");
+    let test_config = phase_config(config, settings, &enabled, InputMux::TestSignal);
+    let test = record_phase(test_config, settings, &mut make_driver).await?;
+    info!("Self-test: recording shorted inputs");
+    let shorted_config = phase_config(config, settings, &enabled, InputMux::Shorted);
+    let shorted = record_phase(shorted_config, settings, &mut make_driver).await?;
+
+    let sample_rate = config.sample_rate as f32;
+    let channels = enabled.iter().zip(test.iter().zip(&shorted))
+        .map(|(&i, (square, noise))| {
+            let amplitude = square_amplitude(square);
+            let frequency = square_frequency(square, expected_amplitude / 2.0, sample_rate);
+            let (offset, rms, pp) = noise_stats(noise);
+            let within = |measured: f32, expected: f32| (measured - expected).abs() <= settings.tolerance * expected;
+            ChannelSelfTest {
+                channel: config.channels[i],
+                test_amplitude_uv: amplitude,
+                test_frequency_hz: frequency,
+                offset_uv: offset,
+                noise_uv_rms: rms,
+                noise_uv_pp: pp,
+                amplitude_ok: within(amplitude, expected_amplitude),
+                frequency_ok: within(frequency, expected_frequency),
+                noise_ok: rms <= settings.max_noise_uv_rms && pp <= settings.max_noise_uv_pp,
+            }
+        })
+        .collect();
+
+    Ok(SelfTestReport {
+        expected_amplitude_uv: expected_amplitude,
+        expected_frequency_hz: expected_frequency,
+        channels,
+    })
+}
+
+/// `config` reduced to the `enabled` channels, all switched to `input` and uncalibrated.
+fn phase_config(config: &AdcConfig, settings: &SelfTestSettings, enabled: &[usize], input: InputMux) -> AdcConfig {
+    AdcConfig {
+        channels: enabled.iter().map(|&i| config.channels[i]).collect(),
+        channel_settings: enabled.iter()
+            .map(|&i| ChannelSettings { input, ..ChannelSettings::with_gain(config.settings_for(i).gain) })
+            .collect(),
+        lead_off: None,
+        calibration: Vec::new(),
+        electrodes: enabled.iter().filter_map(|&i| config.electrodes.get(i).cloned()).collect(),
+        test_signal: settings.test_signal,
+        ..config.clone()
+    }
+}
+
+/// Acquire one phase with a fresh driver and return each channel's samples in microvolts.
+async fn record_phase<F, Fut>(
+    config: AdcConfig,
+    settings: &SelfTestSettings,
+    make_driver: &mut F,
+) -> Result<Vec<Vec<f32>>, DriverError>
+where
+    F: FnMut(AdcConfig) -> Fut,
+    Fut: Future<Output = Result<(Box<dyn AdcDriver>, mpsc::Receiver<DriverEvent>), DriverError>>,
+{
+    let sample_rate = config.sample_rate as f32;
+    let skip = (settings.settle_secs * sample_rate).round() as usize;
+    let wanted = ((settings.phase_secs * sample_rate).ceil() as usize).max(1);
+    let converter = UnitConverter::new(&config, SignalUnit::Microvolts);
+    // Generous bound: the phase itself plus time to bring the driver up
+    let limit = Duration::from_secs_f32(settings.settle_secs + settings.phase_secs * 2.0 + 5.0);
+
+    let (mut driver, mut rx) = make_driver(config.clone()).await?;
+    let recorded = match driver.start_acquisition().await {
+        Ok(()) => timeout(limit, collect(&mut rx, &converter, skip, wanted)).await
+            .unwrap_or_else(|_| Err(DriverError::Other("Self-test phase timed out".to_string()))),
+        Err(e) => Err(e),
+    };
+
+    // Keep draining so a producer blocked on a full channel can see the stop request
+    let drain = tokio::spawn(async move { while rx.recv().await.is_some() {} });
+    if let Err(e) = driver.shutdown().await {
+        warn!("Self-test driver shutdown failed: {}", e);
+    }
+    drop(driver);
+    let _ = drain.await;
+
+    let samples = recorded?;
+    debug!("Self-test phase recorded {} samples per channel", wanted);
+    Ok(samples)
+}
+
+async fn collect(
+    rx: &mut mpsc::Receiver<DriverEvent>,
+    converter: &UnitConverter,
+    mut skip: usize,
+    wanted: usize,
+) -> Result<Vec<Vec<f32>>, DriverError> {
+    let mut channels: Vec<Vec<f32>> = Vec::new();
+    while channels.first().is_none_or(|c| c.len() < wanted) {
+        let batch = match rx.recv().await {
+            Some(DriverEvent::Data(batch)) => batch,
+            Some(DriverEvent::Error(e)) => return Err(DriverError::Other(e)),
+            Some(_) => continue,
+            None => return Err(DriverError::Other("Driver event channel closed during self-test".to_string())),
+        };
+        for sample in batch {
+            if skip > 0 {
+                skip -= 1;
+                continue;
+            }
+            channels.resize_with(sample.samples.len(), Vec::new);
+            for (i, (buffer, values)) in channels.iter_mut().zip(&sample.samples).enumerate() {
+                if buffer.len() < wanted {
+                    buffer.extend(values.iter().map(|&code| converter.convert(i, code)));
+                }
+            }
+        }
+    }
+    Ok(channels)
+}
+
+/// Half the distance between the median high and median low level of a square wave.
+fn square_amplitude(samples: &[f32]) -> f32 {
+    let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+    let (mut high, mut low): (Vec<f32>, Vec<f32>) = samples.iter().partition(|&&x| x >= mean);
+    (median(&mut high) - median(&mut low)) / 2.0
+}
+
+/// Frequency of a square wave from the spacing of its edges, with `hysteresis` either
+/// side of the mean so noise does not register as extra edges.
+fn square_frequency(samples: &[f32], hysteresis: f32, sample_rate: f32) -> f32 {
+    let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+    let mut high: Option<bool> = None;
+    let mut edges: Vec<usize> = Vec::new();
+    for (i, &x) in samples.iter().enumerate() {
+        let level = if x > mean + hysteresis {
+            true
+        } else if x < mean - hysteresis {
+            false
+        } else {
+            continue;
+        };
+        if high.is_some_and(|h| h != level) {
+            edges.push(i);
+        }
+        high = Some(level);
+    }
+    match (edges.first(), edges.last()) {
+        (Some(&first), Some(&last)) if last > first => {
+            // Consecutive edges are half a period apart
+            let half_periods = (edges.len() - 1) as f32;
+            half_periods * sample_rate / (2.0 * (last - first) as f32)
+        }
+        _ => 0.0,
+    }
+}
+
+/// Mean, RMS deviation from the mean and peak-to-peak of `samples`.
+fn noise_stats(samples: &[f32]) -> (f32, f32, f32) {
+    let n = samples.len() as f32;
+    let mean = samples.iter().sum::<f32>() / n;
+    let rms = (samples.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / n).sqrt();
+    let max = samples.iter().copied().fold(f32::MIN, f32::max);
+    let min = samples.iter().copied().fold(f32::MAX, f32::min);
+    (mean, rms, max - min)
+}
+
+fn median(values: &mut [f32]) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(f32::total_cmp);
+    values[values.len() / 2]
+}
@@ -0,0 +1,903 @@
+use super::*;
+use super::ads1299_driver::{opcode, reg, sign_extend_24, ChipSelectMode, FRAME_BYTES};
+use super::ads1299_registers::{ChannelRegister, DataRate, InputMux, LeadOffCurrent, LeadOffFrequency, LeadOffRegister, LeadOffThreshold, PgaGain, TestSignalFrequency};
+use crate::dsp::units::{SignalUnit, UnitConverter};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// MockDriver simulates exclusive hardware access, so tests that create one take this first.
+pub(crate) static MOCK_HARDWARE: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Minimal scripted bus: stores register writes, answers register reads, records
+/// every transfer and returns a fixed frame whenever the driver reads data. DRDY is
+/// ready at once unless scripted otherwise.
+#[derive(Clone, Default)]
+struct FakeBus {
+    state: Arc<Mutex<FakeBusState>>,
+}
+
+struct FakeBusState {
+    writes: Vec<Vec<u8>>,
+    registers: [u8; reg::COUNT],
+    converting: bool,
+    /// DRDY waits still to come while converting, as (time taken, edges counted)
+    drdy_script: std::collections::VecDeque<(Duration, u64)>,
+}
+
+impl Default for FakeBusState {
+    fn default() -> Self {
+        let mut registers = [0u8; reg::COUNT];
+        registers[reg::ID as usize] = 0x3E;
+        Self { writes: Vec::new(), registers, converting: false, drdy_script: Default::default() }
+    }
+}
+
+impl FakeBus {
+    /// Frame with status 0xC00000 and channel n holding the 24-bit code n * 1000 - 4000.
+    fn frame() -> [u8; FRAME_BYTES] {
+        let mut frame = [0u8; FRAME_BYTES];
+        frame[0] = 0xC0;
+        for ch in 0..8 {
+            let code = (ch as i32 * 1000 - 4000).to_be_bytes();
+            frame[3 + ch * 3..6 + ch * 3].copy_from_slice(&code[1..]);
+        }
+        frame
+    }
+}
+
+impl Ads1299Bus for FakeBus {
+    fn transfer(&mut self, _chip_select: usize, buffer: &mut [u8]) -> Result<(), DriverError> {
+        let mut state = self.state.lock().unwrap();
+        state.writes.push(buffer.to_vec());
+        match buffer[0] {
+            opcode::START => state.converting = true,
+            opcode::STOP => state.converting = false,
+            op if op & 0xE0 == opcode::WREG => {
+                let start = (op & 0x1F) as usize;
+                let values = buffer[2..].to_vec();
+                state.registers[start..start + values.len()].copy_from_slice(&values);
+            }
+            op if op & 0xE0 == opcode::RREG => {
+                let start = (op & 0x1F) as usize;
+                let count = buffer.len() - 2;
+                buffer[2..].copy_from_slice(&state.registers[start..start + count]);
+            }
+            0x00 if buffer.len() == FRAME_BYTES => buffer.copy_from_slice(&Self::frame()),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn wait_for_drdy(&mut self, timeout: Duration) -> Result<u64, DriverError> {
+        let (converting, scripted) = {
+            let mut state = self.state.lock().unwrap();
+            let scripted = if state.converting { state.drdy_script.pop_front() } else { None };
+            (state.converting, scripted)
+        };
+        if !converting {
+            std::thread::sleep(timeout);
+            return Ok(0);
+        }
+        let (wait, edges) = scripted.unwrap_or((Duration::ZERO, 1));
+        std::thread::sleep(wait);
+        Ok(edges)
+    }
+
+    fn delay(&mut self, _duration: Duration) {}
+}
+
+/// Wait for the next lead-off change, skipping data and status events.
+async fn next_lead_off(rx: &mut tokio::sync::mpsc::Receiver<DriverEvent>) -> LeadOffStatus {
+    loop {
+        match rx.recv().await {
+            Some(DriverEvent::LeadOff(status)) => return status,
+            Some(_) => continue,
+            None => panic!("event channel closed"),
+        }
+    }
+}
+
+/// Wait for the next batch of samples, skipping status events.
+async fn next_batch(rx: &mut tokio::sync::mpsc::Receiver<DriverEvent>) -> Vec<AdcData> {
+    loop {
+        match rx.recv().await {
+            Some(DriverEvent::Data(batch)) => return batch,
+            Some(_) => continue,
+            None => panic!("event channel closed"),
+        }
+    }
+}
+
+fn ads_config() -> AdcConfig {
+    AdcConfig {
+        sample_rate: 500,
+        gain: 24.0,
+        channels: vec![0, 3, 7],
+        board_driver: DriverType::Ads1299,
+        batch_size: 4,
+        ..AdcConfig::default()
+    }
+}
+
+#[tokio::test]
+async fn test_ads1299_driver_writes_configuration() -> Result<(), DriverError> {
+    let bus = FakeBus::default();
+    let (mut driver, _rx) = Ads1299Driver::with_bus(ads_config(), bus.clone(), 0)?;
+    assert_eq!(driver.get_status().await, DriverStatus::Ok);
+
+    let writes = bus.state.lock().unwrap().writes.clone();
+    assert_eq!(writes[0], vec![opcode::RESET]);
+    assert_eq!(writes[1], vec![opcode::SDATAC]);
+
+    let wreg = writes.iter()
+        .find(|w| w[0] == opcode::WREG | reg::CONFIG1)
+        .expect("configuration registers written");
+    assert_eq!(wreg[1] as usize + 1, wreg.len() - 2);
+    assert_eq!(wreg[2], 0x95, "CONFIG1 should select 500 SPS");
+    // Channels 0, 3 and 7 at gain 24, the rest powered down and shorted
+    assert_eq!(&wreg[6..14], &[0x60, 0x81, 0x81, 0x60, 0x81, 0x81, 0x81, 0x60]);
+
+    driver.shutdown().await
+}
+
+#[tokio::test]
+async fn test_ads1299_driver_emits_batches() -> Result<(), DriverError> {
+    let (mut driver, mut rx) = Ads1299Driver::with_bus(ads_config(), FakeBus::default(), 0)?;
+    driver.start_acquisition().await?;
+
+    let batch = loop {
+        match rx.recv().await {
+            Some(DriverEvent::Data(batch)) => break batch,
+            Some(_) => continue,
+            None => panic!("event channel closed"),
+        }
+    };
+
+    assert_eq!(batch.len(), 4);
+    for sample in &batch {
+        assert_eq!(sample.samples, vec![vec![-4000.0], vec![-1000.0], vec![3000.0]]);
+    }
+    // Data is ready whenever the fake bus is asked, so no conversion goes by unread
+    assert!(batch.iter().map(|sample| sample.sample_index).eq(0..4));
+
+    // Keep draining so status notifications are not stuck behind queued batches
+    let drain = tokio::spawn(async move { while rx.recv().await.is_some() {} });
+
+    driver.stop_acquisition().await?;
+    assert_eq!(driver.get_status().await, DriverStatus::Stopped);
+    driver.shutdown().await?;
+    drop(driver);
+    drain.await.unwrap();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ads1299_driver_numbers_samples_by_drdy_edges() -> Result<(), DriverError> {
+    // Reads early and late within a 2 ms period keep consecutive indices; only edges
+    // that went by unread move them further
+    let bus = FakeBus::default();
+    let ms = Duration::from_millis;
+    bus.state.lock().unwrap().drdy_script = [
+        (ms(0), 1), (ms(3), 1), (ms(0), 1), (ms(1), 1),
+        (ms(2), 3), (ms(0), 1), (ms(5), 1), (ms(0), 2),
+    ].into_iter().collect();
+    let (mut driver, mut rx) = Ads1299Driver::with_bus(ads_config(), bus, 0)?;
+    driver.start_acquisition().await?;
+
+    let mut indices = Vec::new();
+    for _ in 0..3 {
+        indices.extend(next_batch(&mut rx).await.iter().map(|sample| sample.sample_index));
+    }
+    assert_eq!(indices, vec![0, 1, 2, 3, 6, 7, 8, 10, 11, 12, 13, 14]);
+
+    let drain = tokio::spawn(async move { while rx.recv().await.is_some() {} });
+    driver.shutdown().await?;
+    drop(driver);
+    drain.await.unwrap();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ads1299_driver_rejects_unsupported_config() {
+    let config = AdcConfig { sample_rate: 300, ..ads_config() };
+    assert!(matches!(
+        Ads1299Driver::with_bus(config, FakeBus::default(), 0),
+        Err(DriverError::ConfigurationError(_))
+    ));
+
+    let config = AdcConfig { gain: 3.0, ..ads_config() };
+    assert!(matches!(
+        Ads1299Driver::with_bus(config, FakeBus::default(), 0),
+        Err(DriverError::ConfigurationError(_))
+    ));
+}
+
+#[test]
+fn test_emulator_register_access() -> Result<(), DriverError> {
+    let mut chip = Ads1299Emulator::new();
+
+    // Register commands are ignored until RDATAC is left
+    chip.transfer(0, &mut [opcode::WREG | reg::CONFIG1, 0x00, 0x95])?;
+    assert_eq!(chip.register(reg::CONFIG1), 0x96);
+
+    chip.transfer(0, &mut [opcode::SDATAC])?;
+    chip.transfer(0, &mut [opcode::WREG | reg::CONFIG1, 0x01, 0x94, 0xD0])?;
+    assert_eq!(chip.register(reg::CONFIG1), 0x94);
+    assert_eq!(chip.register(reg::CONFIG2), 0xD0);
+    assert_eq!(chip.sample_rate(), 1000);
+
+    // ID is read-only
+    chip.transfer(0, &mut [opcode::WREG | reg::ID, 0x00, 0x00])?;
+    let mut rreg = [opcode::RREG | reg::ID, 0x02, 0, 0, 0];
+    chip.transfer(0, &mut rreg)?;
+    assert_eq!(&rreg[2..], &[0x3E, 0x94, 0xD0]);
+
+    chip.transfer(0, &mut [opcode::RESET])?;
+    assert_eq!(chip.register(reg::CONFIG1), 0x96);
+    assert!(chip.is_continuous());
+    Ok(())
+}
+
+#[test]
+fn test_emulator_frames() -> Result<(), DriverError> {
+    let mut chip = Ads1299Emulator::new();
+    chip.set_input_signal(|ch, _| (ch + 1) as f64 * 1e-4);
+    chip.transfer(0, &mut [opcode::SDATAC])?;
+    // Channel 1 at gain 1 on the electrode input, channel 2 on the internal DC test signal,
+    // channel 3 powered down, the rest left shorted at gain 24
+    chip.transfer(0, &mut [opcode::WREG | reg::CONFIG2, 0x00, 0xD3])?;
+    chip.transfer(0, &mut [opcode::WREG | reg::CH1SET, 0x02, 0x00, 0x05, 0x81])?;
+    chip.poke_register(reg::LOFF_STATP, 0xA5);
+
+    assert!(!chip.convert(), "no conversions before START");
+    chip.transfer(0, &mut [opcode::START])?;
+    assert_eq!(chip.wait_for_drdy(Duration::from_millis(1))?, 1);
+
+    let mut frame = [0u8; FRAME_BYTES + 1];
+    frame[0] = opcode::RDATA;
+    chip.transfer(0, &mut frame)?;
+    let frame = &frame[1..];
+
+    assert_eq!(&frame[..3], &[0xCA, 0x50, 0x00]);
+    let code = |ch: usize| sign_extend_24(&frame[3 + ch * 3..6 + ch * 3]);
+    assert_eq!(code(0), (1e-4 / 4.5 * 8_388_608.0_f64).round() as i32);
+    assert_eq!(code(1), (4.5 / 2400.0 / 4.5 * 8_388_608.0_f64).round() as i32);
+    assert_eq!(code(2), 0);
+    assert_eq!(code(3), 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ads1299_driver_against_emulator() -> Result<(), DriverError> {
+    let chip = Ads1299Emulator::new();
+    chip.set_input_signal(|ch, _| if ch == 3 { 50e-6 } else { -20e-6 });
+
+    let (mut driver, mut rx) = Ads1299Driver::with_bus(ads_config(), chip.clone(), 0)?;
+    assert_eq!(chip.register(reg::CONFIG1) & 0x07, 0b101);
+    assert_eq!(chip.register(reg::CH1SET + 3), 0x60);
+    assert_eq!(chip.register(reg::CH1SET + 1), 0x81);
+
+    driver.start_acquisition().await?;
+    assert!(chip.is_converting() && chip.is_continuous());
+
+    let batch = loop {
+        match rx.recv().await {
+            Some(DriverEvent::Data(batch)) => break batch,
+            Some(_) => continue,
+            None => panic!("event channel closed"),
+        }
+    };
+    let expected = |volts: f64| (volts * 24.0 / 4.5 * 8_388_608.0).round() as f32;
+    for sample in &batch {
+        assert_eq!(sample.samples, vec![vec![expected(-20e-6)], vec![expected(50e-6)], vec![expected(-20e-6)]]);
+    }
+
+    let drain = tokio::spawn(async move { while rx.recv().await.is_some() {} });
+    driver.shutdown().await?;
+    assert!(!chip.is_converting() && !chip.is_continuous());
+    assert!(chip.is_standby());
+    drop(driver);
+    drain.await.unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_register_map_round_trip() -> Result<(), DriverError> {
+    let config = AdcConfig { sample_rate: 1000, gain: 8.0, ..ads_config() };
+    let map = RegisterMap::from_config(&config)?;
+    assert_eq!(map.data_rate, DataRate::Sps1000);
+    assert_eq!(map.channels[3], ChannelRegister { power_down: false, gain: PgaGain::X8, srb2: false, mux: InputMux::Normal });
+    assert_eq!(map.channels[1], ChannelRegister::OFF);
+
+    let bytes = map.to_bytes();
+    assert_eq!(bytes[0], 0x94);
+    assert_eq!(bytes[2], 0xE0, "internal reference enabled");
+    assert_eq!(bytes[4], 0x40);
+
+    let mut dump = vec![0x3E];
+    dump.extend_from_slice(&bytes);
+    let decoded = RegisterMap::from_bytes(&dump)?;
+    assert_eq!(decoded, map);
+
+    let round_trip = decoded.to_config()?;
+    assert_eq!(round_trip.sample_rate, 1000);
+    assert_eq!(round_trip.gain, 8.0);
+    assert_eq!(round_trip.channels, vec![0, 3, 7]);
+    Ok(())
+}
+
+#[test]
+fn test_register_map_rejects_invalid_combinations() {
+    let reject = |map: RegisterMap| assert!(matches!(map.validate(), Err(DriverError::ConfigurationError(_))));
+    let on = |mux| ChannelRegister { power_down: false, gain: PgaGain::X24, srb2: false, mux };
+
+    let mut map = RegisterMap { srb1: true, ..RegisterMap::default() };
+    map.channels[0] = ChannelRegister { srb2: true, ..on(InputMux::Normal) };
+    reject(map);
+
+    let mut map = RegisterMap::default();
+    map.channels[2] = on(InputMux::TestSignal);
+    reject(map.clone());
+    map.test_signal.internal = true;
+    assert!(map.validate().is_ok());
+
+    let mut map = RegisterMap::default();
+    map.channels[4] = on(InputMux::BiasMeasure);
+    reject(map);
+
+    let mut map = RegisterMap::default();
+    map.channels[5] = on(InputMux::Supply);
+    reject(map);
+
+    assert!(RegisterMap::from_config(&AdcConfig { channels: vec![0, 8], ..ads_config() }).is_err());
+    assert!(RegisterMap::from_config(&AdcConfig { channels: vec![2, 2], ..ads_config() }).is_err());
+}
+
+#[test]
+fn test_register_map_verify_reports_mismatches() {
+    let map = RegisterMap::from_config(&ads_config()).unwrap();
+    let mut readback = map.to_bytes();
+    assert!(map.verify(&readback).is_ok());
+
+    // Status bits the chip drives itself are ignored
+    readback[(reg::LOFF_STATP - RegisterMap::FIRST) as usize] = 0xFF;
+    readback[(reg::GPIO - RegisterMap::FIRST) as usize] |= 0xF0;
+    assert!(map.verify(&readback).is_ok());
+
+    readback[(reg::CONFIG1 - RegisterMap::FIRST) as usize] = 0x96;
+    readback[(reg::CH1SET + 3 - RegisterMap::FIRST) as usize] = 0x61;
+    let mismatches = map.diff(&readback);
+    let names: Vec<&str> = mismatches.iter().map(|m| m.name).collect();
+    assert_eq!(names, vec!["CONFIG1", "CH4SET"]);
+
+    match map.verify(&readback) {
+        Err(DriverError::ConfigurationError(msg)) => assert!(msg.contains("CONFIG1") && msg.contains("CH4SET")),
+        other => panic!("expected a configuration error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_channel_settings_serde_backward_compatible() {
+    // Configs written before per-channel settings existed
+    let old = r#"{"sample_rate":250,"gain":12.0,"channels":[0,1],"board_driver":"Mock","batch_size":32}"#;
+    let config: AdcConfig = serde_json::from_str(old).unwrap();
+    assert!(config.channel_settings.is_empty());
+    assert_eq!(config.settings_for(1), ChannelSettings::with_gain(12.0));
+
+    let new = r#"{"sample_rate":250,"channels":[0,1],"board_driver":"Mock","batch_size":32,
+        "channel_settings":[{"gain":24.0,"bias":true},{"gain":2.0,"input":"Shorted","srb2":true}]}"#;
+    let config: AdcConfig = serde_json::from_str(new).unwrap();
+    assert_eq!(config.settings_for(0), ChannelSettings { bias: true, ..ChannelSettings::with_gain(24.0) });
+    assert_eq!(config.settings_for(1).input, InputMux::Shorted);
+    assert!(config.settings_for(1).srb2);
+}
+
+#[test]
+fn test_electrodes_from_standard_montages() {
+    let distance = |a: Position, b: Position| ((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt();
+    let at = |montage: Montage, label: &str| montage.position(label).unwrap_or_else(|| panic!("{} missing", label));
+
+    let cz = at(Montage::Standard1020, "Cz");
+    assert!(distance(cz, Position { x: 0.0, y: 0.0, z: 1.0 }) < 1e-6);
+    // Sites lie on the unit sphere, the right hemisphere mirroring the left
+    for (left, right) in [("Fp1", "Fp2"), ("F3", "F4"), ("C3", "C4"), ("P7", "P8"), ("FC5", "FC6"), ("CP1", "CP2"), ("AF3", "AF4")] {
+        let (left, right) = (at(Montage::Standard1010, left), at(Montage::Standard1010, right));
+        assert!((distance(left, Position { x: 0.0, y: 0.0, z: 0.0 }) - 1.0).abs() < 1e-5);
+        assert!(left.x < 0.0 && (left.x + right.x).abs() < 1e-5 && (left.y - right.y).abs() < 1e-5 && (left.z - right.z).abs() < 1e-5);
+    }
+    // C3 sits halfway between T7 and Cz along the central arc
+    let (t7, c3) = (at(Montage::Standard1020, "T7"), at(Montage::Standard1020, "C3"));
+    assert!((distance(t7, c3) - distance(c3, cz)).abs() < 1e-5);
+    assert!((distance(at(Montage::Standard1010, "C5"), t7) - distance(at(Montage::Standard1010, "C5"), c3)).abs() < 1e-5);
+    // Front of the head is +y
+    assert!(at(Montage::Standard1020, "Fz").y > 0.0 && at(Montage::Standard1020, "Pz").y < 0.0);
+
+    // Old 10-20 names and any case resolve; 10-10 sites are not part of 10-20
+    assert_eq!(Montage::Standard1020.position("T3"), Some(t7));
+    assert_eq!(Montage::Standard1020.position("cz"), Some(cz));
+    assert_eq!(Montage::Standard1020.position("FC1"), None);
+    assert!(Montage::Standard1010.position("FC1").is_some());
+    assert_eq!(Montage::Standard1010.position("EOG"), None);
+
+    let config = AdcConfig {
+        channels: vec![0, 1, 2],
+        electrodes: vec![
+            ElectrodeInfo::eeg("Fz", Montage::Standard1020),
+            ElectrodeInfo::eeg("Cz", Montage::Standard1020),
+            ElectrodeInfo::new("HEOG", ElectrodeType::Eog),
+        ],
+        ..Default::default()
+    };
+    config.validate_channel_settings().unwrap();
+    assert_eq!(config.electrode_for(1).position, Some(cz));
+    assert_eq!(config.electrode_for(2).kind, ElectrodeType::Eog);
+    let json = serde_json::to_string(&config).unwrap();
+    assert!(json.contains(r#""kind":"EOG""#));
+    let parsed: AdcConfig = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed.electrodes, config.electrodes);
+
+    // Unnamed channels are EEG named after their channel number
+    let unnamed = AdcConfig { channels: vec![4, 7], ..Default::default() };
+    assert_eq!(unnamed.electrode_for(1), ElectrodeInfo::new("Ch7", ElectrodeType::Eeg));
+    let mismatched = AdcConfig { electrodes: vec![ElectrodeInfo::new("Fz", ElectrodeType::Eeg)], ..unnamed };
+    assert!(mismatched.validate_channel_settings().is_err());
+}
+
+#[test]
+fn test_register_map_per_channel_settings() -> Result<(), DriverError> {
+    let config = AdcConfig {
+        channels: vec![0, 1, 2],
+        channel_settings: vec![
+            ChannelSettings { bias: true, srb2: true, ..ChannelSettings::with_gain(24.0) },
+            ChannelSettings { input: InputMux::TestSignal, ..ChannelSettings::with_gain(1.0) },
+            ChannelSettings { power_down: true, ..ChannelSettings::with_gain(6.0) },
+        ],
+        ..ads_config()
+    };
+    let map = RegisterMap::from_config(&config)?;
+    assert_eq!(map.channels[0].encode(), 0x68);
+    assert_eq!(map.channels[1].encode(), 0x05);
+    assert_eq!(map.channels[2].encode(), 0xB0);
+    assert_eq!((map.bias_sensp, map.bias_sensn), (0x01, 0x01));
+    assert!(map.bias_enabled && map.test_signal.internal);
+
+    let decoded = map.to_config()?;
+    assert_eq!(decoded.channels, config.channels);
+    assert_eq!(decoded.channel_settings, config.channel_settings);
+
+    let mismatched = AdcConfig { channel_settings: vec![ChannelSettings::with_gain(1.0)], ..config };
+    assert!(RegisterMap::from_config(&mismatched).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_driver_honors_channel_gain() -> Result<(), DriverError> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let config = AdcConfig {
+        sample_rate: 1000,
+        channels: vec![0, 1, 2],
+        batch_size: 250,
+        channel_settings: vec![
+            ChannelSettings::with_gain(1.0),
+            ChannelSettings::with_gain(24.0),
+            ChannelSettings { power_down: true, ..ChannelSettings::with_gain(24.0) },
+        ],
+        ..AdcConfig::default()
+    };
+    let (mut driver, mut rx) = MockDriver::new(config, 0)?;
+    driver.start_acquisition().await?;
+
+    let batch = loop {
+        match rx.recv().await {
+            Some(DriverEvent::Data(batch)) => break batch,
+            Some(_) => continue,
+            None => panic!("event channel closed"),
+        }
+    };
+    let peak = |ch: usize| batch.iter().map(|s| s.samples[ch][0].abs()).fold(0.0f32, f32::max);
+    // The same input amplitude comes out 24 times larger in codes at gain 24
+    let full_scale = 50e-6 / 4.5 * 8_388_608.0;
+    assert!(peak(0) > full_scale * 0.9 && peak(0) <= full_scale.round());
+    assert!(peak(1) > full_scale * 24.0 * 0.9 && peak(1) <= (full_scale * 24.0).round());
+    assert_eq!(peak(2), 0.0);
+
+    // Both channels read the same number of microvolts, to within one LSB at gain 1 (0.54 uV)
+    let converter = UnitConverter::new(&driver.get_config().await?, SignalUnit::Microvolts);
+    let uv = |ch: usize| batch.iter().map(|s| converter.convert(ch, s.samples[ch][0]).abs()).fold(0.0f32, f32::max);
+    assert!((uv(0) - uv(1)).abs() < 0.6 && uv(0) > 45.0 && uv(0) <= 50.1);
+
+    let drain = tokio::spawn(async move { while rx.recv().await.is_some() {} });
+    driver.shutdown().await?;
+    drop(driver);
+    drain.await.unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_register_map_splits_channels_across_chips() -> Result<(), DriverError> {
+    let config = AdcConfig {
+        channels: vec![0, 9, 15],
+        chip_count: 2,
+        chip_select: ChipSelectMode::Separate,
+        ..ads_config()
+    };
+    let maps = RegisterMap::for_chips(&config)?;
+    assert_eq!(maps.len(), 2);
+    let active = |map: &RegisterMap| (0..8).filter(|&ch| map.channels[ch] != ChannelRegister::OFF).collect::<Vec<_>>();
+    assert_eq!(active(&maps[0]), vec![0]);
+    assert_eq!(active(&maps[1]), vec![1, 7]);
+    assert_eq!(RegisterMap::chips_to_config(&maps)?.channels, config.channels);
+    assert!(RegisterMap::from_config(&config).is_err(), "two chips need for_chips");
+
+    // Daisy-chained chips all receive the same register writes
+    let daisy = AdcConfig { chip_select: ChipSelectMode::DaisyChain, ..config.clone() };
+    let maps = RegisterMap::for_chips(&daisy)?;
+    assert_eq!(maps[0], maps[1]);
+    assert_eq!(active(&maps[0]), vec![0, 1, 7]);
+
+    // Channels 1 and 9 share CH2SET on a daisy chain, so their settings must agree
+    let conflicting = AdcConfig {
+        channels: vec![1, 9],
+        channel_settings: vec![ChannelSettings::with_gain(24.0), ChannelSettings::with_gain(1.0)],
+        ..daisy
+    };
+    assert!(RegisterMap::for_chips(&conflicting).is_err());
+    let separate = AdcConfig { chip_select: ChipSelectMode::Separate, ..conflicting };
+    assert!(RegisterMap::for_chips(&separate).is_ok());
+
+    assert!(RegisterMap::for_chips(&AdcConfig { channels: vec![16], ..config }).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ads1299_driver_daisy_chain_against_emulator() -> Result<(), DriverError> {
+    let chips = Ads1299Emulator::daisy_chain(2);
+    chips.set_input_signal(|ch, _| ch as f64 * 1e-5);
+
+    let config = AdcConfig { channels: vec![1, 9, 12], chip_count: 2, ..ads_config() };
+    let (mut driver, mut rx) = Ads1299Driver::with_bus(config, chips.clone(), 0)?;
+    // Lead-off on the second chip's first positive input shows up in its status word only
+    chips.poke_chip_register(1, reg::LOFF_STATP, 0x01);
+    assert_eq!(chips.chip_register(0, reg::CH1SET + 4), 0x60);
+    assert_eq!(chips.chip_register(1, reg::CH1SET + 4), 0x60);
+
+    driver.start_acquisition().await?;
+    let batch = next_batch(&mut rx).await;
+    let expected = |ch: usize| (ch as f64 * 1e-5 * 24.0 / 4.5 * 8_388_608.0).round() as f32;
+    for sample in &batch {
+        assert_eq!(sample.samples, vec![vec![expected(1)], vec![expected(9)], vec![expected(12)]]);
+        assert_eq!(sample.status, vec![0xC0_0000, 0xC0_1000]);
+    }
+
+    let drain = tokio::spawn(async move { while rx.recv().await.is_some() {} });
+    driver.shutdown().await?;
+    drop(driver);
+    drain.await.unwrap();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ads1299_driver_separate_chip_selects_against_emulator() -> Result<(), DriverError> {
+    let chips = Ads1299Emulator::separate(2);
+    chips.set_input_signal(|_, _| 100e-6);
+
+    let config = AdcConfig {
+        channels: vec![1, 9],
+        channel_settings: vec![ChannelSettings::with_gain(24.0), ChannelSettings::with_gain(1.0)],
+        chip_count: 2,
+        chip_select: ChipSelectMode::Separate,
+        ..ads_config()
+    };
+    let (mut driver, mut rx) = Ads1299Driver::with_bus(config, chips.clone(), 0)?;
+    assert_eq!(chips.chip_register(0, reg::CH1SET + 1), 0x60);
+    assert_eq!(chips.chip_register(1, reg::CH1SET + 1), 0x00);
+
+    driver.start_acquisition().await?;
+    let batch = next_batch(&mut rx).await;
+    let expected = |gain: f64| (100e-6 * gain / 4.5 * 8_388_608.0).round() as f32;
+    for sample in &batch {
+        assert_eq!(sample.samples, vec![vec![expected(24.0)], vec![expected(1.0)]]);
+        assert_eq!(sample.status.len(), 2);
+    }
+
+    let drain = tokio::spawn(async move { while rx.recv().await.is_some() {} });
+    driver.shutdown().await?;
+    drop(driver);
+    drain.await.unwrap();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_driver_spans_chips() -> Result<(), DriverError> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let config = AdcConfig { channels: vec![0, 8], ..AdcConfig::default() };
+    assert!(matches!(MockDriver::new(config.clone(), 0), Err(DriverError::ConfigurationError(_))));
+
+    let (mut driver, mut rx) = MockDriver::new(AdcConfig { chip_count: 2, ..config }, 0)?;
+    driver.start_acquisition().await?;
+    let batch = next_batch(&mut rx).await;
+    assert!(batch.iter().all(|s| s.samples.len() == 2 && s.status.len() == 2));
+
+    let drain = tokio::spawn(async move { while rx.recv().await.is_some() {} });
+    driver.shutdown().await?;
+    drop(driver);
+    drain.await.unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_register_map_lead_off() -> Result<(), DriverError> {
+    let register = LeadOffRegister {
+        threshold: LeadOffThreshold::Pct80,
+        current: LeadOffCurrent::Ua6,
+        frequency: LeadOffFrequency::Ac31_2,
+    };
+    assert_eq!(register.encode(), 0xAA);
+    assert_eq!(LeadOffRegister::decode(0xAA), register);
+
+    let config = AdcConfig {
+        channels: vec![0, 2, 5],
+        channel_settings: vec![
+            ChannelSettings::with_gain(24.0),
+            ChannelSettings { input: InputMux::Shorted, ..ChannelSettings::with_gain(24.0) },
+            ChannelSettings::with_gain(24.0),
+        ],
+        lead_off: Some(LeadOffConfig { current: LeadOffCurrent::Na24, negative: false, ..LeadOffConfig::default() }),
+        ..ads_config()
+    };
+    let map = RegisterMap::from_config(&config)?;
+    assert_eq!(map.to_bytes()[(reg::LOFF - RegisterMap::FIRST) as usize], 0x04);
+    // Shorted inputs have no electrode to sense
+    assert_eq!((map.lead_off_sensp, map.lead_off_sensn), (0b0010_0001, 0x00));
+    assert!(map.lead_off_comparators);
+    assert_eq!(map.to_config()?.lead_off, config.lead_off);
+
+    let status = LeadOffStatus::from_status(&[0xC0_0000 | 0x21 << 12 | 0x04 << 4, 0xC0_1000], &[0, 2, 5, 8], 7);
+    assert_eq!((status.positive, status.negative), (vec![0, 5, 8], vec![2]));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ads1299_driver_reports_lead_off() -> Result<(), DriverError> {
+    let chip = Ads1299Emulator::new();
+    let config = AdcConfig { lead_off: Some(LeadOffConfig::default()), ..ads_config() };
+    let (mut driver, mut rx) = Ads1299Driver::with_bus(config, chip.clone(), 0)?;
+    assert_eq!(chip.register(reg::CONFIG4), 0x02);
+    assert_eq!(chip.register(reg::LOFF_SENSP), 0b1000_1001);
+
+    // Unconfigured channel 1 is not sensed, so only channel 3 is reported
+    chip.set_lead_off(1, true, false);
+    chip.set_lead_off(3, true, true);
+    driver.start_acquisition().await?;
+    let status = next_lead_off(&mut rx).await;
+    assert_eq!((status.positive, status.negative), (vec![3], vec![3]));
+
+    chip.set_lead_off(3, false, false);
+    assert!(next_lead_off(&mut rx).await.all_connected());
+
+    let drain = tokio::spawn(async move { while rx.recv().await.is_some() {} });
+    driver.shutdown().await?;
+    drop(driver);
+    drain.await.unwrap();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_driver_scripted_lead_off() -> Result<(), DriverError> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let config = AdcConfig {
+        sample_rate: 1000,
+        channels: vec![0, 1],
+        batch_size: 20,
+        lead_off: Some(LeadOffConfig { negative: false, ..LeadOffConfig::default() }),
+        ..AdcConfig::default()
+    };
+    let settings = MockSettings {
+        lead_off: vec![
+            ScriptedLeadOff { channel: 1, negative: false, off_at_secs: 0.0, on_at_secs: Some(0.05) },
+            // Negative inputs are not sensed in this configuration
+            ScriptedLeadOff { channel: 0, negative: true, off_at_secs: 0.0, on_at_secs: None },
+        ],
+        ..MockSettings::default()
+    };
+    let (mut driver, mut rx) = MockDriver::with_settings(config, settings, 0)?;
+    driver.start_acquisition().await?;
+
+    let status = next_lead_off(&mut rx).await;
+    assert_eq!((status.positive, status.negative), (vec![1], vec![]));
+    assert!(next_lead_off(&mut rx).await.all_connected());
+
+    let drain = tokio::spawn(async move { while rx.recv().await.is_some() {} });
+    driver.shutdown().await?;
+    drop(driver);
+    drain.await.unwrap();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_self_test_against_mock() -> Result<(), DriverError> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let config = AdcConfig {
+        gain: 24.0,
+        channels: vec![0, 1, 2],
+        ..AdcConfig::default()
+    };
+    let make_driver = |config: AdcConfig| async move {
+        let settings = MockSettings { noise_uv_rms: 0.3, ..MockSettings::default() };
+        let (driver, rx) = MockDriver::with_settings(config, settings, 0)?;
+        Ok((Box::new(driver) as Box<dyn AdcDriver>, rx))
+    };
+    let report = run_self_test_with(&config, &SelfTestSettings::default(), make_driver).await?;
+
+    assert!((report.expected_amplitude_uv - 1875.0).abs() < 1e-3);
+    assert!(report.passed(), "{:?}", report);
+    assert_eq!(report.channels.iter().map(|c| c.channel).collect::<Vec<_>>(), vec![0, 1, 2]);
+    for channel in &report.channels {
+        assert!((channel.test_amplitude_uv - 1875.0).abs() < 2.0, "{:?}", channel);
+        assert!((channel.test_frequency_hz - report.expected_frequency_hz).abs() < 0.05, "{:?}", channel);
+        assert!((0.2..0.4).contains(&channel.noise_uv_rms), "{:?}", channel);
+        assert!(channel.offset_uv.abs() < 0.1, "{:?}", channel);
+    }
+
+    // The hardware lock was released, so a regular driver can follow
+    let (mut driver, _rx) = MockDriver::new(config, 0)?;
+    driver.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_self_test_against_emulator() -> Result<(), DriverError> {
+    let chip = Ads1299Emulator::new();
+    let make_driver = |config: AdcConfig| {
+        let bus = chip.clone();
+        async move {
+            let (driver, rx) = Ads1299Driver::with_bus(config, bus, 0)?;
+            Ok((Box::new(driver) as Box<dyn AdcDriver>, rx))
+        }
+    };
+    let config = AdcConfig {
+        channel_settings: vec![
+            ChannelSettings::with_gain(24.0),
+            ChannelSettings { power_down: true, ..ChannelSettings::with_gain(24.0) },
+            ChannelSettings::with_gain(8.0),
+        ],
+        ..ads_config()
+    };
+    let settings = SelfTestSettings {
+        test_signal: TestSignalConfig { double_amplitude: true, ..SelfTestSettings::default().test_signal },
+        ..SelfTestSettings::default()
+    };
+
+    chip.set_input_noise(0.5);
+    let report = run_self_test_with(&config, &settings, make_driver).await?;
+    assert!(report.passed(), "{:?}", report);
+    // The powered-down channel is skipped
+    assert_eq!(report.channels.iter().map(|c| c.channel).collect::<Vec<_>>(), vec![0, 7]);
+    for channel in &report.channels {
+        assert!((channel.test_amplitude_uv - 3750.0).abs() < 3.0, "{:?}", channel);
+        assert!((0.35..0.65).contains(&channel.noise_uv_rms), "{:?}", channel);
+    }
+
+    // Too much noise fails only the noise check
+    chip.set_input_noise(3.0);
+    let report = run_self_test_with(&config, &settings, make_driver).await?;
+    assert!(!report.passed());
+    let channel = report.channel(0).unwrap();
+    assert!(channel.amplitude_ok && channel.frequency_ok && !channel.noise_ok, "{:?}", channel);
+
+    let dc = SelfTestSettings {
+        test_signal: TestSignalConfig { frequency: TestSignalFrequency::Dc, double_amplitude: false },
+        ..SelfTestSettings::default()
+    };
+    assert!(run_self_test_with(&config, &dc, make_driver).await.is_err());
+    Ok(())
+}
+
+#[test]
+fn test_capabilities_validate_config() {
+    let ads = DriverCapabilities::for_config(&ads_config());
+    assert_eq!(ads.sample_rates, SampleRates::Listed(vec![250, 500, 1000, 2000, 4000, 8000, 16000]));
+    assert_eq!(ads.gains, vec![1.0, 2.0, 4.0, 6.0, 8.0, 12.0, 24.0]);
+    assert_eq!(ads.max_channels, 8);
+    assert!(ads.lead_off_detection && ads.impedance_measurement);
+    assert_eq!(ads.timestamp_precision, TimestampPrecision::PerSample);
+    assert!(ads.validate(&ads_config()).is_ok());
+    for config in [
+        AdcConfig { sample_rate: 300, ..ads_config() },
+        AdcConfig { gain: 3.0, ..ads_config() },
+        AdcConfig { channels: vec![0, 8], ..ads_config() },
+    ] {
+        assert!(matches!(ads.validate(&config), Err(DriverError::ConfigurationError(_))), "{:?}", config);
+    }
+    // A second chip adds its channels
+    let two_chips = AdcConfig { channels: vec![0, 8], chip_count: 2, ..ads_config() };
+    assert!(DriverCapabilities::for_config(&two_chips).validate(&two_chips).is_ok());
+
+    // The mock runs at any rate, with the chip's gains
+    let mock_config = AdcConfig { sample_rate: 125, ..AdcConfig::default() };
+    let mock = DriverCapabilities::for_config(&mock_config);
+    assert!(mock.validate(&mock_config).is_ok());
+    assert_eq!(mock.timestamp_precision, TimestampPrecision::PerBatch);
+    let per_channel_gain = AdcConfig { channel_settings: vec![ChannelSettings::with_gain(5.0)], ..mock_config };
+    assert!(mock.validate(&per_channel_gain).is_err());
+}
+
+#[tokio::test]
+async fn test_ads1299_driver_configure_rewrites_registers() -> Result<(), DriverError> {
+    let chip = Ads1299Emulator::new();
+    let (mut driver, mut rx) = Ads1299Driver::with_bus(ads_config(), chip.clone(), 0)?;
+    assert_eq!(chip.sample_rate(), 500);
+
+    let config = AdcConfig { sample_rate: 1000, gain: 12.0, channels: vec![1], ..ads_config() };
+    driver.configure(config.clone()).await?;
+    assert_eq!(chip.sample_rate(), 1000);
+    assert_eq!(chip.register(reg::CH1SET + 1), 0x50);
+    assert_eq!(chip.register(reg::CH1SET), 0x81);
+    assert_eq!(driver.get_config().await?, config);
+
+    // Rejected configurations change nothing
+    assert!(driver.configure(AdcConfig { sample_rate: 300, ..config.clone() }).await.is_err());
+    assert!(driver.configure(AdcConfig { chip_count: 2, chip_select: ChipSelectMode::Separate, ..config.clone() }).await.is_err());
+    assert_eq!(chip.sample_rate(), 1000);
+    assert_eq!(driver.get_config().await?, config);
+
+    // The same event channel carries the new layout
+    driver.start_acquisition().await?;
+    assert!(driver.configure(ads_config()).await.is_err());
+    let batch = next_batch(&mut rx).await;
+    assert!(batch.iter().all(|sample| sample.samples.len() == 1));
+
+    let drain = tokio::spawn(async move { while rx.recv().await.is_some() {} });
+    driver.shutdown().await?;
+    drop(driver);
+    drain.await.unwrap();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_driver_configure() -> Result<(), DriverError> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let (mut driver, mut rx) = MockDriver::new(AdcConfig::default(), 0)?;
+    assert!(driver.configure(AdcConfig { channels: vec![8], ..AdcConfig::default() }).await.is_err());
+
+    let config = AdcConfig { channels: vec![0, 1, 2], sample_rate: 500, ..AdcConfig::default() };
+    driver.configure(config.clone()).await?;
+    assert_eq!(driver.get_config().await?, config);
+    driver.start_acquisition().await?;
+    assert!(driver.configure(AdcConfig::default()).await.is_err());
+    let batch = next_batch(&mut rx).await;
+    assert!(batch.iter().all(|sample| sample.samples.len() == 3));
+
+    let drain = tokio::spawn(async move { while rx.recv().await.is_some() {} });
+    driver.shutdown().await?;
+    drop(driver);
+    drain.await.unwrap();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_driver_numbers_samples() -> Result<(), DriverError> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let config = AdcConfig {
+        batch_size: 10,
+        sample_rate: 1000,
+        ..AdcConfig::default()
+    };
+    let settings = MockSettings { dropped_samples: vec![(15, 3), (30, 10)], ..Default::default() };
+    let (mut driver, mut rx) = MockDriver::with_settings(config, settings, 0)?;
+    driver.start_acquisition().await?;
+
+    // The fourth batch was lost whole and isn't sent at all
+    let mut indices = Vec::new();
+    for _ in 0..4 {
+        indices.extend(next_batch(&mut rx).await.iter().map(|sample| sample.sample_index));
+    }
+    let expected: Vec<u64> = (0..15).chain(18..30).chain(40..50).collect();
+    assert_eq!(indices, expected);
+
+    let drain = tokio::spawn(async move { while rx.recv().await.is_some() {} });
+    driver.shutdown().await?;
+    drop(driver);
+    drain.await.unwrap();
+    Ok(())
+}
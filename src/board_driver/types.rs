@@ -1,12 +1,11 @@
-use std::time::SystemTime;
-use std::error::Error;
-use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
 use tokio::sync::mpsc;
-use std::future::Future;
-use std::pin::Pin;
 use async_trait::async_trait;
 use serde::{Serialize, Deserialize};
-use super::mock_driver::MockDriver;
+use super::ads1299_driver::ChipSelectMode;
+use super::ads1299_driver::ADS1299_CHANNELS;
+use super::ads1299_registers::{test_signal_amplitude, InputMux, LeadOffCurrent, LeadOffFrequency, LeadOffThreshold, TestSignalFrequency};
+use super::montage::ElectrodeInfo;
+use super::capabilities::DriverCapabilities;
 
 // Driver events
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,6 +13,8 @@ pub enum DriverEvent {
     Data(Vec<AdcData>),
     Error(String),
     StatusChange(DriverStatus),
+    // Sent whenever the set of disconnected electrodes changes
+    LeadOff(LeadOffStatus),
 }
 
 // Driver status
@@ -34,33 +35,284 @@ pub enum DriverType {
 }
 
 // ADC configuration
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct AdcConfig {
     pub sample_rate: u32,
-    pub gain: f32,
+    #[serde(default = "default_gain")]
+    pub gain: f32,  // Gain for channels without explicit channel_settings
     pub channels: Vec<usize>,
     pub board_driver: DriverType,
     pub batch_size: usize,  // Number of samples to collect in a batch
+    // Per-channel hardware settings, parallel to `channels`. Empty means every channel uses `gain`.
+    #[serde(default)]
+    pub channel_settings: Vec<ChannelSettings>,
+    // Number of ADS1299 chips sharing the SPI bus; channel n lives on chip n / 8
+    #[serde(default = "default_chip_count")]
+    pub chip_count: usize,
+    #[serde(default)]
+    pub chip_select: ChipSelectMode,
+    // Lead-off detection on the configured electrode inputs; None leaves it disabled
+    #[serde(default)]
+    pub lead_off: Option<LeadOffConfig>,
+    // Reference voltage in volts, used to convert output codes to volts
+    #[serde(default = "default_vref")]
+    pub vref: f32,
+    // Per-channel calibration, parallel to `channels`. Empty means uncalibrated.
+    #[serde(default)]
+    pub calibration: Vec<ChannelCalibration>,
+    // Name, type and position of each electrode, parallel to `channels`. Empty means
+    // EEG electrodes named after their channel number.
+    #[serde(default)]
+    pub electrodes: Vec<ElectrodeInfo>,
+    // Internal test signal fed to channels whose input is InputMux::TestSignal
+    #[serde(default)]
+    pub test_signal: TestSignalConfig,
     // Add other configuration parameters as needed
 }
 
+fn default_gain() -> f32 {
+    1.0
+}
+
+fn default_chip_count() -> usize {
+    1
+}
+
+fn default_vref() -> f32 {
+    super::ads1299_driver::VREF_VOLTS as f32
+}
+
 impl Default for AdcConfig {
     fn default() -> Self {
         Self {
             sample_rate: 250,  // 250 Hz is a common EEG sampling rate
-            gain: 1.0,
+            gain: default_gain(),
             channels: vec![0],
             board_driver: DriverType::Mock,
             batch_size: 32,    // Default batch size (typical SPI buffer size)
+            channel_settings: Vec::new(),
+            chip_count: default_chip_count(),
+            chip_select: ChipSelectMode::default(),
+            lead_off: None,
+            vref: default_vref(),
+            calibration: Vec::new(),
+            electrodes: Vec::new(),
+            test_signal: TestSignalConfig::default(),
         }
     }
 }
 
+impl AdcConfig {
+    /// Settings for the channel at position `index` in `channels`,
+    /// falling back to the flat `gain` when no per-channel settings are given.
+    pub fn settings_for(&self, index: usize) -> ChannelSettings {
+        self.channel_settings.get(index)
+            .cloned()
+            .unwrap_or_else(|| ChannelSettings::with_gain(self.gain))
+    }
+
+    /// Calibration for the channel at position `index` in `channels`, identity if none is stored.
+    pub fn calibration_for(&self, index: usize) -> ChannelCalibration {
+        self.calibration.get(index).cloned().unwrap_or_default()
+    }
+
+    /// Electrode on the channel at position `index` in `channels`: an EEG electrode named
+    /// `Ch<n>` after its channel number if none is described.
+    pub fn electrode_for(&self, index: usize) -> ElectrodeInfo {
+        self.electrodes.get(index).cloned().unwrap_or_else(|| {
+            let channel = self.channels.get(index).copied().unwrap_or(index);
+            ElectrodeInfo::new(format!("Ch{}", channel), Default::default())
+        })
+    }
+
+    /// Check that per-channel settings, calibration and electrodes, if any, line up with `channels`.
+    pub fn validate_channel_settings(&self) -> Result<(), DriverError> {
+        if !self.channel_settings.is_empty() && self.channel_settings.len() != self.channels.len() {
+            return Err(DriverError::ConfigurationError(
+                format!("{} channel settings given for {} channels",
+                        self.channel_settings.len(), self.channels.len())
+            ));
+        }
+        if !self.calibration.is_empty() && self.calibration.len() != self.channels.len() {
+            return Err(DriverError::ConfigurationError(
+                format!("{} channel calibrations given for {} channels",
+                        self.calibration.len(), self.channels.len())
+            ));
+        }
+        if !self.electrodes.is_empty() && self.electrodes.len() != self.channels.len() {
+            return Err(DriverError::ConfigurationError(
+                format!("{} electrodes described for {} channels",
+                        self.electrodes.len(), self.channels.len())
+            ));
+        }
+        if self.vref <= 0.0 {
+            return Err(DriverError::ConfigurationError(
+                format!("Reference voltage must be positive, got {} V", self.vref)
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Hardware settings for one configured channel.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChannelSettings {
+    pub gain: f32,
+    #[serde(default)]
+    pub input: InputMux,
+    #[serde(default)]
+    pub power_down: bool,
+    /// Include this electrode in the bias drive derivation
+    #[serde(default)]
+    pub bias: bool,
+    /// Reference the positive input to SRB2
+    #[serde(default)]
+    pub srb2: bool,
+}
+
+impl ChannelSettings {
+    /// Powered-up electrode input at `gain`, no bias or SRB2 connection.
+    pub fn with_gain(gain: f32) -> Self {
+        Self {
+            gain,
+            input: InputMux::Normal,
+            power_down: false,
+            bias: false,
+            srb2: false,
+        }
+    }
+}
+
+/// Per-channel correction applied after converting codes to microvolts:
+/// `(microvolts - offset_uv) * gain_correction`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChannelCalibration {
+    pub offset_uv: f32,
+    pub gain_correction: f32,
+}
+
+impl Default for ChannelCalibration {
+    fn default() -> Self {
+        Self { offset_uv: 0.0, gain_correction: 1.0 }
+    }
+}
+
+/// Lead-off detection settings, applied to every configured channel on the normal electrode input.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LeadOffConfig {
+    pub current: LeadOffCurrent,
+    pub frequency: LeadOffFrequency,
+    pub threshold: LeadOffThreshold,
+    /// Sense the positive (electrode) input of each channel
+    pub positive: bool,
+    /// Sense the negative (reference) input of each channel
+    pub negative: bool,
+}
+
+impl Default for LeadOffConfig {
+    /// DC detection at 6 nA with the 95% threshold on both inputs.
+    fn default() -> Self {
+        Self {
+            current: LeadOffCurrent::default(),
+            frequency: LeadOffFrequency::default(),
+            threshold: LeadOffThreshold::default(),
+            positive: true,
+            negative: true,
+        }
+    }
+}
+
+/// Shape of the chip's internal square-wave test signal, shared by every channel on
+/// `InputMux::TestSignal`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TestSignalConfig {
+    pub frequency: TestSignalFrequency,
+    /// 2 x VREF / 2.4 mV instead of 1 x
+    pub double_amplitude: bool,
+}
+
+impl TestSignalConfig {
+    /// Peak amplitude in volts for the reference voltage `vref`.
+    pub fn amplitude_volts(&self, vref: f32) -> f64 {
+        test_signal_amplitude(vref as f64, self.double_amplitude)
+    }
+}
+
+/// Electrodes currently flagged by the lead-off comparators, as channel numbers.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LeadOffStatus {
+    /// Timestamp of the sample in which the change was seen
+    pub timestamp: u64,
+    /// Channels whose positive input is off
+    pub positive: Vec<usize>,
+    /// Channels whose negative input is off
+    pub negative: Vec<usize>,
+}
+
+impl LeadOffStatus {
+    /// Decode the LOFF_STATP/N bits of per-chip status words, keeping only `channels`.
+    pub fn from_status(status: &[u32], channels: &[usize], timestamp: u64) -> Self {
+        let flagged = |shift: u32, ch: usize| {
+            status.get(ch / ADS1299_CHANNELS)
+                .is_some_and(|word| (word >> shift) & (1 << (ch % ADS1299_CHANNELS)) != 0)
+        };
+        Self {
+            timestamp,
+            positive: channels.iter().copied().filter(|&ch| flagged(12, ch)).collect(),
+            negative: channels.iter().copied().filter(|&ch| flagged(4, ch)).collect(),
+        }
+    }
+
+    /// True when no electrode is flagged.
+    pub fn all_connected(&self) -> bool {
+        self.positive.is_empty() && self.negative.is_empty()
+    }
+}
+
+/// Tracks lead-off flags across samples so drivers only report changes.
+pub(crate) struct LeadOffMonitor {
+    channels: Vec<usize>,
+    last: LeadOffStatus,
+}
+
+impl LeadOffMonitor {
+    /// Returns `None` when lead-off detection is not configured.
+    pub(crate) fn new(config: &AdcConfig) -> Option<Self> {
+        config.lead_off.as_ref().map(|_| Self {
+            channels: config.channels.clone(),
+            last: LeadOffStatus::default(),
+        })
+    }
+
+    /// Lead-off changes within `batch`, in sample order. Electrodes start out assumed connected.
+    pub(crate) fn check(&mut self, batch: &[AdcData]) -> Vec<LeadOffStatus> {
+        let mut changes = Vec::new();
+        for sample in batch {
+            let status = LeadOffStatus::from_status(&sample.status, &self.channels, sample.timestamp);
+            if status.positive != self.last.positive || status.negative != self.last.negative {
+                self.last = status.clone();
+                changes.push(status);
+            }
+        }
+        changes
+    }
+}
+
 // ADC data point
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct AdcData {
     pub samples: Vec<Vec<f32>>,
     pub timestamp: u64,
+    // Number of the sample since acquisition started, one up per sample period; a jump
+    // means samples were lost on the way
+    #[serde(default)]
+    pub sample_index: u64,
+    // Raw 24-bit status word of every chip, in chip order
+    #[serde(default)]
+    pub status: Vec<u32>,
 }
 
 // Driver error
@@ -91,10 +343,10 @@ pub enum DriverError {
 // Remove the problematic From implementations that violate orphan rules
 // Instead, create wrapper types for external errors
 #[derive(Debug)]
-pub struct SpiError(rppal::spi::Error);
+pub struct SpiError(pub(crate) rppal::spi::Error);
 
 #[derive(Debug)]
-pub struct TimeError(std::time::SystemTimeError);
+pub struct TimeError(pub(crate) std::time::SystemTimeError);
 
 impl From<SpiError> for DriverError {
     fn from(err: SpiError) -> Self {
@@ -108,6 +360,13 @@ impl From<TimeError> for DriverError {
     }
 }
 
+/// Helper function to get current timestamp in microseconds
+pub(crate) fn current_timestamp_micros() -> Result<u64, DriverError> {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .map_err(|e| DriverError::Other(format!("Failed to get timestamp: {}", e)))
+}
 
 #[async_trait]
 pub trait AdcDriver: Send + Sync + 'static {
@@ -118,26 +377,33 @@ pub trait AdcDriver: Send + Sync + 'static {
 
     async fn get_config(&self) -> Result<AdcConfig, DriverError>;
     async fn get_status(&self) -> DriverStatus;
+
+    /// Apply a new configuration while acquisition is stopped, keeping the event channel.
+    /// Nothing changes if the configuration is rejected.
+    async fn configure(&mut self, config: AdcConfig) -> Result<(), DriverError>;
+
+    /// What the driver and its hardware support.
+    async fn capabilities(&self) -> DriverCapabilities;
 }
 
 // Factory function to create the appropriate driver and return the event channel
 pub async fn create_driver(config: AdcConfig)
     -> Result<(Box<dyn AdcDriver>, mpsc::Receiver<DriverEvent>), DriverError> {
     
+    // Reject what the driver can't do before touching any hardware
+    DriverCapabilities::for_config(&config).validate(&config)?;
+
     match config.board_driver {
-        // DriverType::Ads1299 => {
-        //     // Create the ADS1299 hardware driver
-        //     let (driver, events) = crate::adc::ads1299_driver::Ads1299Driver::new(config).map_err(|e| DriverError::Other(e.to_string()))?;
-            
-        //     // Check if the driver is in error state after creation
-        //     if driver.get_status() == DriverStatus::Error {
-        //         return Err(DriverError::HardwareNotFound("Failed to initialize ADS1299 hardware".to_string()));
-        //     }
-            
-        //     Ok((Box::new(driver), events))
-        // },
         DriverType::Ads1299 => {
-            panic!("no Ads1299 drver yet")
+            // Create the ADS1299 hardware driver on the default SPI bus and DRDY pin
+            let (driver, events) = super::ads1299_driver::Ads1299Driver::new(config, 0)?;
+
+            // Check if the driver is in error state after creation
+            if driver.get_status().await == DriverStatus::Error {
+                return Err(DriverError::HardwareNotFound("Failed to initialize ADS1299 hardware".to_string()));
+            }
+
+            Ok((Box::new(driver), events))
         }
         DriverType::Mock => {
             let (driver, events) = super::mock_driver::MockDriver::new(config, 0)?;
@@ -0,0 +1,271 @@
+use std::collections::VecDeque;
+use log::warn;
+use serde::{Serialize, Deserialize};
+
+use super::error::DspError;
+use super::filters::{FilterChainSpec, FilterStage, SignalProcessor};
+
+/// Order of the high-pass isolating muscle activity.
+const MUSCLE_FILTER_ORDER: usize = 4;
+
+/// Kinds of artifact the detector flags.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Artifact {
+    /// The input is at the ADC's rails
+    Clipping,
+    /// The signal barely moves, as when an electrode is disconnected or shorted
+    Flatline,
+    /// Peak-to-peak amplitude too large, e.g. blinks and movement
+    Amplitude,
+    /// Abrupt jump from one sample to the next, e.g. electrode pops
+    Step,
+    /// Too much high-frequency power, typically from muscle (EMG)
+    Muscle,
+}
+
+impl Artifact {
+    pub const ALL: [Artifact; 5] = [Artifact::Clipping, Artifact::Flatline, Artifact::Amplitude, Artifact::Step, Artifact::Muscle];
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Set of [`Artifact`]s found in a sample or a stretch of samples.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ArtifactMask(u8);
+
+impl ArtifactMask {
+    pub fn contains(self, artifact: Artifact) -> bool {
+        self.0 & artifact.bit() != 0
+    }
+
+    pub fn insert(&mut self, artifact: Artifact) {
+        self.0 |= artifact.bit();
+    }
+
+    /// No artifact at all.
+    pub fn is_clean(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Artifacts found in either mask.
+    pub fn union(self, other: ArtifactMask) -> ArtifactMask {
+        ArtifactMask(self.0 | other.0)
+    }
+
+    pub fn artifacts(self) -> Vec<Artifact> {
+        Artifact::ALL.into_iter().filter(|&artifact| self.contains(artifact)).collect()
+    }
+}
+
+/// Thresholds of the artifact detectors, in microvolts. `None` turns a detector off.
+///
+/// The windowed detectors look at the `window_secs` leading up to each sample, so a
+/// sample is flagged once the window ending on it crosses the threshold. Muscle detection
+/// starts after a second window, once its high-pass has settled.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArtifactSettings {
+    /// Samples within this of either ADC rail count as clipped
+    pub clipping_margin_uv: Option<f32>,
+    /// Peak-to-peak amplitude over the window below which the signal is flat
+    pub flatline_uv: Option<f32>,
+    /// Peak-to-peak amplitude over the window above which it is an artifact
+    pub amplitude_uv: Option<f32>,
+    /// Largest change allowed between consecutive samples
+    pub step_uv: Option<f32>,
+    /// RMS over the window, above `muscle_cutoff_hz`, beyond which muscle is flagged
+    pub muscle_uv: Option<f32>,
+    pub muscle_cutoff_hz: f32,
+    pub window_secs: f32,
+}
+
+impl Default for ArtifactSettings {
+    /// Conventional thresholds for scalp EEG over half-second windows.
+    fn default() -> Self {
+        Self {
+            clipping_margin_uv: Some(1.0),
+            flatline_uv: Some(0.5),
+            amplitude_uv: Some(200.0),
+            step_uv: Some(100.0),
+            muscle_uv: Some(20.0),
+            muscle_cutoff_hz: 30.0,
+            window_secs: 0.5,
+        }
+    }
+}
+
+/// Running maximum and minimum over a sliding window of samples.
+#[derive(Debug, Default)]
+struct SlidingRange {
+    maxima: VecDeque<(u64, f32)>,
+    minima: VecDeque<(u64, f32)>,
+}
+
+impl SlidingRange {
+    /// Add sample number `index` and return the range of the `window` samples ending on it.
+    fn push(&mut self, index: u64, x: f32, window: u64) -> f32 {
+        while self.maxima.back().is_some_and(|&(_, m)| m <= x) {
+            self.maxima.pop_back();
+        }
+        while self.minima.back().is_some_and(|&(_, m)| m >= x) {
+            self.minima.pop_back();
+        }
+        self.maxima.push_back((index, x));
+        self.minima.push_back((index, x));
+        for queue in [&mut self.maxima, &mut self.minima] {
+            while queue.front().is_some_and(|&(i, _)| i + window <= index) {
+                queue.pop_front();
+            }
+        }
+        self.maxima[0].1 - self.minima[0].1
+    }
+}
+
+/// Detector state of one channel.
+#[derive(Debug, Default)]
+struct ChannelState {
+    range: SlidingRange,
+    /// Squared high-passed samples in the window, and their sum
+    muscle: VecDeque<f64>,
+    muscle_energy: f64,
+    previous: Option<f32>,
+}
+
+/// Real-time artifact detector: flags every sample of every channel with the artifacts
+/// found in it.
+pub struct ArtifactDetector {
+    settings: ArtifactSettings,
+    window: usize,
+    /// Lowest and highest value each channel can read, in microvolts
+    rails: Vec<(f32, f32)>,
+    /// High-pass isolating muscle activity; `None` when the cutoff is past Nyquist
+    muscle_filter: Option<SignalProcessor>,
+    channels: Vec<ChannelState>,
+    samples: u64,
+}
+
+impl std::fmt::Debug for ArtifactDetector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ArtifactDetector")
+            .field("settings", &self.settings)
+            .field("rails", &self.rails)
+            .finish()
+    }
+}
+
+impl ArtifactDetector {
+    /// Detector for a stream at `sample_rate` whose channels read between the `rails`
+    /// given per channel, in microvolts.
+    pub fn new(sample_rate: u32, rails: &[(f32, f32)], settings: ArtifactSettings) -> Result<Self, DspError> {
+        if sample_rate == 0 {
+            return Err(DspError::InvalidSampleRate);
+        }
+        let window = (settings.window_secs * sample_rate as f32).round() as usize;
+        if settings.window_secs.is_nan() || window < 2 {
+            return Err(DspError::InvalidArtifactSettings(format!(
+                "Window must hold at least 2 samples at {} Hz", sample_rate
+            )));
+        }
+        let thresholds = [settings.clipping_margin_uv, settings.flatline_uv, settings.amplitude_uv, settings.step_uv, settings.muscle_uv];
+        if thresholds.iter().flatten().any(|t| t.is_nan() || *t < 0.0) {
+            return Err(DspError::InvalidArtifactSettings(format!("Thresholds must not be negative: {:?}", settings)));
+        }
+
+        let muscle_filter = match settings.muscle_uv {
+            Some(_) => {
+                let stage = FilterStage::highpass(settings.muscle_cutoff_hz).with_order(MUSCLE_FILTER_ORDER);
+                let filter = SignalProcessor::with_chain(sample_rate, rails.len(), FilterChainSpec::new(vec![stage]))?;
+                if filter.adjustments().is_empty() {
+                    Some(filter)
+                } else {
+                    warn!("Muscle detection off: {} Hz is past Nyquist at {} Hz", settings.muscle_cutoff_hz, sample_rate);
+                    None
+                }
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            settings,
+            window,
+            rails: rails.to_vec(),
+            muscle_filter,
+            channels: rails.iter().map(|_| ChannelState::default()).collect(),
+            samples: 0,
+        })
+    }
+
+    pub fn settings(&self) -> &ArtifactSettings {
+        &self.settings
+    }
+
+    /// Check a block of samples per channel, all of the same length, in microvolts.
+    /// Returns a mask per channel and sample.
+    pub fn push(&mut self, samples: &[Vec<f32>]) -> Vec<Vec<ArtifactMask>> {
+        let len = samples.iter().map(Vec::len).min().unwrap_or(0);
+        let window = self.window as u64;
+        let settings = &self.settings;
+        let mut masks = Vec::with_capacity(samples.len());
+        for (channel, (block, state)) in samples.iter().zip(self.channels.iter_mut()).enumerate() {
+            let high_passed = self.muscle_filter.as_mut().map(|filter| filter.process_block(channel, &block[..len]));
+            let (low, high) = self.rails[channel];
+            let mut channel_masks = Vec::with_capacity(len);
+            for (i, &x) in block[..len].iter().enumerate() {
+                let index = self.samples + i as u64;
+                let full = index + 1 >= window;
+                let mut mask = ArtifactMask::default();
+
+                if settings.clipping_margin_uv.is_some_and(|margin| x <= low + margin || x >= high - margin) {
+                    mask.insert(Artifact::Clipping);
+                }
+                let range = state.range.push(index, x, window);
+                if full && settings.flatline_uv.is_some_and(|flat| range < flat) {
+                    mask.insert(Artifact::Flatline);
+                }
+                if full && settings.amplitude_uv.is_some_and(|limit| range > limit) {
+                    mask.insert(Artifact::Amplitude);
+                }
+                if let (Some(limit), Some(previous)) = (settings.step_uv, state.previous) {
+                    if (x - previous).abs() > limit {
+                        mask.insert(Artifact::Step);
+                    }
+                }
+                state.previous = Some(x);
+
+                if let (Some(limit), Some(high_passed)) = (settings.muscle_uv, &high_passed) {
+                    let energy = (high_passed[i] as f64).powi(2);
+                    state.muscle.push_back(energy);
+                    state.muscle_energy += energy;
+                    if state.muscle.len() > self.window {
+                        state.muscle_energy -= state.muscle.pop_front().unwrap_or(0.0);
+                    }
+                    // The first window lets the high-pass settle from any DC offset
+                    let settled = index + 1 >= 2 * window;
+                    let rms = (state.muscle_energy.max(0.0) / self.window as f64).sqrt();
+                    if settled && rms > limit as f64 {
+                        mask.insert(Artifact::Muscle);
+                    }
+                }
+                channel_masks.push(mask);
+            }
+            masks.push(channel_masks);
+        }
+        self.samples += len as u64;
+        masks
+    }
+}
+
+/// Masks of channels derived from others, such as re-referenced ones: each derived
+/// channel carries every artifact of the `contributors` it is computed from.
+pub fn derive_masks(masks: &[Vec<ArtifactMask>], contributors: &[Vec<usize>]) -> Vec<Vec<ArtifactMask>> {
+    let len = masks.iter().map(Vec::len).min().unwrap_or(0);
+    contributors.iter()
+        .map(|inputs| (0..len)
+            .map(|i| inputs.iter().fold(ArtifactMask::default(), |mask, &input| mask.union(masks[input][i])))
+            .collect())
+        .collect()
+}
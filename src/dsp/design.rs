@@ -0,0 +1,336 @@
+use std::f64::consts::PI;
+use biquad::Coefficients;
+use rustfft::num_complex::Complex64;
+use serde::{Serialize, Deserialize};
+
+use super::error::DspError;
+use super::filters::{FilterKind, MAX_FILTER_ORDER};
+use super::fir::FirWindow;
+
+/// How a filter is designed: from an analog prototype as IIR sections, or as a
+/// linear-phase FIR kernel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterFamily {
+    /// Maximally flat passband; cutoffs are the -3 dB points
+    #[default]
+    Butterworth,
+    /// Passband ripple of `ripple_db`; cutoffs are the passband edges, where the ripple ends
+    ChebyshevI { ripple_db: f32 },
+    /// Flat passband and a stopband at least `stopband_db` down; cutoffs are the stopband edges
+    ChebyshevII { stopband_db: f32 },
+    /// Maximally flat group delay; cutoffs are the -3 dB points
+    Bessel,
+    /// Linear-phase FIR from a windowed ideal response; see `fir::windowed_sinc`
+    WindowedSinc { window: FirWindow },
+    /// Linear-phase equiripple FIR with transition bands `transition_hz` wide around each
+    /// cutoff; see `fir::equiripple_for`
+    Equiripple { transition_hz: f32 },
+}
+
+impl FilterFamily {
+    /// Whether the family yields an FIR kernel rather than IIR sections.
+    pub fn is_fir(&self) -> bool {
+        matches!(self, FilterFamily::WindowedSinc { .. } | FilterFamily::Equiripple { .. })
+    }
+}
+
+/// Design an IIR filter of the given `order` as a cascade of second-order sections, in
+/// processing order, ready for `biquad::DirectForm2Transposed`.
+///
+/// The analog prototype is shifted to the requested band with prewarped edges and mapped
+/// through the bilinear transform, so the cutoffs land exactly where asked. Band-pass and
+/// band-stop filters have twice the prototype `order` and `order` sections; low- and
+/// high-pass ones need `(order + 1) / 2`. Notches are single resonators and are not
+/// designed here.
+pub fn design(
+    family: FilterFamily,
+    kind: &FilterKind,
+    order: usize,
+    sample_rate: f32,
+) -> Result<Vec<Coefficients<f32>>, DspError> {
+    if order == 0 || order > MAX_FILTER_ORDER {
+        return Err(DspError::InvalidFilter(
+            format!("Filter order must be between 1 and {}", MAX_FILTER_ORDER)
+        ));
+    }
+    let nyquist = sample_rate as f64 / 2.0;
+    let edges = kind.frequencies();
+    if edges.iter().any(|&f| !(f > 0.0 && (f as f64) < nyquist)) || edges.windows(2).any(|w| w[0] >= w[1]) {
+        return Err(DspError::InvalidFilter(format!(
+            "{:?} filter edges must be increasing and between 0 and {} Hz (half the sample rate)",
+            kind, nyquist
+        )));
+    }
+
+    // Prewarp for a bilinear transform with fs = 2, i.e. s = 4 (z - 1) / (z + 1)
+    let warp = |f: f32| 4.0 * (PI * f as f64 / sample_rate as f64).tan();
+    let prototype = prototype(family, order)?;
+    let analog = match *kind {
+        FilterKind::Lowpass { cutoff_hz } => prototype.lowpass(warp(cutoff_hz)),
+        FilterKind::Highpass { cutoff_hz } => prototype.highpass(warp(cutoff_hz)),
+        FilterKind::Bandpass { low_hz, high_hz } => {
+            let (low, high) = (warp(low_hz), warp(high_hz));
+            prototype.bandpass((low * high).sqrt(), high - low)
+        }
+        FilterKind::Bandstop { low_hz, high_hz } => {
+            let (low, high) = (warp(low_hz), warp(high_hz));
+            prototype.bandstop((low * high).sqrt(), high - low)
+        }
+        FilterKind::Notch { .. } => return Err(DspError::InvalidFilter(
+            "Notch filters are single resonators, not designed from a prototype".to_string()
+        )),
+    };
+    Ok(analog.bilinear().sections())
+}
+
+/// Magnitude of the frequency response of a section cascade at `frequency`.
+pub fn magnitude_response(sections: &[Coefficients<f32>], frequency: f32, sample_rate: f32) -> f64 {
+    let z_inv = Complex64::from_polar(1.0, -2.0 * PI * frequency as f64 / sample_rate as f64);
+    sections.iter()
+        .map(|c| {
+            let numerator = c.b0 as f64 + z_inv * (c.b1 as f64 + z_inv * c.b2 as f64);
+            let denominator = 1.0 + z_inv * (c.a1 as f64 + z_inv * c.a2 as f64);
+            numerator / denominator
+        })
+        .product::<Complex64>()
+        .norm()
+}
+
+/// Zeros, poles and gain of an analog or digital transfer function.
+#[derive(Debug, Clone)]
+struct Zpk {
+    zeros: Vec<Complex64>,
+    poles: Vec<Complex64>,
+    gain: f64,
+}
+
+/// Normalised analog low-pass prototype with its cutoff at 1 rad/s.
+fn prototype(family: FilterFamily, order: usize) -> Result<Zpk, DspError> {
+    let n = order as f64;
+    match family {
+        FilterFamily::Butterworth => {
+            let poles = (0..order)
+                .map(|k| Complex64::from_polar(1.0, PI * (2 * k + order + 1) as f64 / (2.0 * n)))
+                .collect();
+            Ok(Zpk::all_pole(poles))
+        }
+        FilterFamily::ChebyshevI { ripple_db } => {
+            if ripple_db.is_nan() || ripple_db <= 0.0 {
+                return Err(DspError::InvalidFilter("Chebyshev I ripple must be positive".to_string()));
+            }
+            let eps = (10f64.powf(ripple_db as f64 / 10.0) - 1.0).sqrt();
+            let mu = (1.0 / eps).asinh() / n;
+            let poles = (0..order)
+                .map(|k| {
+                    let theta = PI * (2 * k + 1) as f64 / (2.0 * n);
+                    Complex64::new(-mu.sinh() * theta.sin(), mu.cosh() * theta.cos())
+                })
+                .collect();
+            let mut zpk = Zpk::all_pole(poles);
+            if order.is_multiple_of(2) {
+                // Even orders start the passband at the bottom of the ripple
+                zpk.gain /= (1.0 + eps * eps).sqrt();
+            }
+            Ok(zpk)
+        }
+        FilterFamily::ChebyshevII { stopband_db } => {
+            if stopband_db.is_nan() || stopband_db <= 0.0 {
+                return Err(DspError::InvalidFilter("Chebyshev II stopband attenuation must be positive".to_string()));
+            }
+            let de = 1.0 / (10f64.powf(stopband_db as f64 / 10.0) - 1.0).sqrt();
+            let mu = (1.0 / de).asinh() / n;
+            // Odd orders have one zero fewer: the one at infinity
+            let zeros = (0..order)
+                .map(|k| (2 * k + 1) as f64 - n)
+                .filter(|&m| m != 0.0)
+                .map(|m| Complex64::new(0.0, 1.0 / (m * PI / (2.0 * n)).sin()))
+                .collect();
+            let poles = (0..order)
+                .map(|k| {
+                    let p = -Complex64::from_polar(1.0, PI * ((2 * k + 1) as f64 - n) / (2.0 * n));
+                    1.0 / Complex64::new(mu.sinh() * p.re, mu.cosh() * p.im)
+                })
+                .collect();
+            let mut zpk = Zpk { zeros, poles, gain: 1.0 };
+            zpk.gain = (product(&zpk.poles, |p| -p) / product(&zpk.zeros, |z| -z)).re;
+            Ok(zpk)
+        }
+        FilterFamily::Bessel => {
+            let poles = bessel_poles(order);
+            Ok(Zpk::all_pole(poles))
+        }
+        FilterFamily::WindowedSinc { .. } | FilterFamily::Equiripple { .. } => Err(DspError::InvalidFilter(
+            "FIR families have no analog prototype; design them with dsp::fir".to_string()
+        )),
+    }
+}
+
+/// Poles of the Bessel filter of `order`, scaled so its magnitude is -3 dB at 1 rad/s.
+fn bessel_poles(order: usize) -> Vec<Complex64> {
+    // Reverse Bessel polynomial: a_k = (2n - k)! / (2^(n - k) k! (n - k)!), monic
+    let factorial = |m: usize| (1..=m).map(|i| i as f64).product::<f64>();
+    let coefficients: Vec<f64> = (0..=order)
+        .map(|k| factorial(2 * order - k) / (2f64.powi((order - k) as i32) * factorial(k) * factorial(order - k)))
+        .collect();
+    let evaluate = |s: Complex64| coefficients.iter().rev().fold(Complex64::new(0.0, 0.0), |acc, &a| acc * s + a);
+
+    // Durand-Kerner iteration; the roots are simple and well separated for these orders
+    let mut roots: Vec<Complex64> = (0..order).map(|i| Complex64::new(0.4, 0.9).powu(i as u32)).collect();
+    for _ in 0..500 {
+        for i in 0..order {
+            let others = (0..order).filter(|&j| j != i).map(|j| roots[i] - roots[j]).product::<Complex64>();
+            let step = evaluate(roots[i]) / others;
+            roots[i] -= step;
+        }
+    }
+
+    // |H(jw)| = a_0 / |theta(jw)| falls monotonically; find where it crosses 1/sqrt(2)
+    let magnitude = |w: f64| coefficients[0] / evaluate(Complex64::new(0.0, w)).norm();
+    let (mut low, mut high) = (0.0, 10.0);
+    for _ in 0..100 {
+        let mid = 0.5 * (low + high);
+        if magnitude(mid) > std::f64::consts::FRAC_1_SQRT_2 { low = mid } else { high = mid }
+    }
+    roots.iter().map(|p| p / low).collect()
+}
+
+fn product(roots: &[Complex64], f: impl Fn(Complex64) -> Complex64) -> Complex64 {
+    roots.iter().map(|&r| f(r)).product()
+}
+
+impl Zpk {
+    /// All-pole filter with unity gain at DC.
+    fn all_pole(poles: Vec<Complex64>) -> Self {
+        let gain = product(&poles, |p| -p).re;
+        Self { zeros: Vec::new(), poles, gain }
+    }
+
+    fn degree(&self) -> usize {
+        self.poles.len() - self.zeros.len()
+    }
+
+    fn lowpass(self, wo: f64) -> Self {
+        Self {
+            gain: self.gain * wo.powi(self.degree() as i32),
+            zeros: self.zeros.iter().map(|z| z * wo).collect(),
+            poles: self.poles.iter().map(|p| p * wo).collect(),
+        }
+    }
+
+    fn highpass(self, wo: f64) -> Self {
+        let mut zeros: Vec<Complex64> = self.zeros.iter().map(|z| wo / z).collect();
+        zeros.extend(std::iter::repeat_n(Complex64::new(0.0, 0.0), self.degree()));
+        Self {
+            gain: self.gain * (product(&self.zeros, |z| -z) / product(&self.poles, |p| -p)).re,
+            zeros,
+            poles: self.poles.iter().map(|p| wo / p).collect(),
+        }
+    }
+
+    fn bandpass(self, wo: f64, bw: f64) -> Self {
+        let split = |r: &Complex64| {
+            let r = r * bw / 2.0;
+            let offset = (r * r - wo * wo).sqrt();
+            [r + offset, r - offset]
+        };
+        let mut zeros: Vec<Complex64> = self.zeros.iter().flat_map(split).collect();
+        zeros.extend(std::iter::repeat_n(Complex64::new(0.0, 0.0), self.degree()));
+        Self {
+            gain: self.gain * bw.powi(self.degree() as i32),
+            zeros,
+            poles: self.poles.iter().flat_map(split).collect(),
+        }
+    }
+
+    fn bandstop(self, wo: f64, bw: f64) -> Self {
+        let split = |r: &Complex64| {
+            let r = bw / 2.0 / r;
+            let offset = (r * r - wo * wo).sqrt();
+            [r + offset, r - offset]
+        };
+        let mut zeros: Vec<Complex64> = self.zeros.iter().flat_map(split).collect();
+        for _ in 0..self.degree() {
+            zeros.extend([Complex64::new(0.0, wo), Complex64::new(0.0, -wo)]);
+        }
+        Self {
+            gain: self.gain * (product(&self.zeros, |z| -z) / product(&self.poles, |p| -p)).re,
+            zeros,
+            poles: self.poles.iter().flat_map(split).collect(),
+        }
+    }
+
+    /// Bilinear transform with fs = 2; zeros at infinity move to Nyquist.
+    fn bilinear(self) -> Self {
+        const FS2: f64 = 4.0;
+        let map = |r: &Complex64| (FS2 + r) / (FS2 - r);
+        let mut zeros: Vec<Complex64> = self.zeros.iter().map(map).collect();
+        zeros.extend(std::iter::repeat_n(Complex64::new(-1.0, 0.0), self.degree()));
+        Self {
+            gain: self.gain * (product(&self.zeros, |z| FS2 - z) / product(&self.poles, |p| FS2 - p)).re,
+            zeros,
+            poles: self.poles.iter().map(map).collect(),
+        }
+    }
+
+    /// Split a digital filter with as many zeros as poles into second-order sections.
+    /// Sections are ordered from the poles furthest from the unit circle to the closest,
+    /// each pole pair taking the nearest remaining zeros; the gain goes into the first.
+    fn sections(self) -> Vec<Coefficients<f32>> {
+        let mut poles = root_groups(&self.poles);
+        let mut zeros = root_groups(&self.zeros);
+        poles.sort_by(|a, b| radius(a).total_cmp(&radius(b)));
+
+        let mut sections = Vec::with_capacity(poles.len());
+        for pole_group in &poles {
+            let nearest = zeros.iter().enumerate()
+                .filter(|(_, group)| group.len() == pole_group.len())
+                .min_by(|(_, a), (_, b)| distance(pole_group, a).total_cmp(&distance(pole_group, b)))
+                .map(|(i, _)| i)
+                .expect("a lone real zero for the lone real pole, pairs otherwise");
+            let zero_group = zeros.remove(nearest);
+            let (b1, b2) = polynomial(&zero_group);
+            let (a1, a2) = polynomial(pole_group);
+            let gain = if sections.is_empty() { self.gain } else { 1.0 };
+            sections.push(Coefficients {
+                a1: a1 as f32,
+                a2: a2 as f32,
+                b0: gain as f32,
+                b1: (gain * b1) as f32,
+                b2: (gain * b2) as f32,
+            });
+        }
+        sections
+    }
+}
+
+/// Group roots into the factors of second-order sections: conjugate pairs, pairs of real
+/// roots, and at most one lone real root.
+fn root_groups(roots: &[Complex64]) -> Vec<Vec<Complex64>> {
+    let is_real = |r: &Complex64| r.im.abs() <= 1e-9 * (1.0 + r.norm());
+    let mut groups: Vec<Vec<Complex64>> = roots.iter()
+        .filter(|r| !is_real(r) && r.im > 0.0)
+        .map(|&r| vec![r, r.conj()])
+        .collect();
+    let mut reals: Vec<Complex64> = roots.iter().filter(|r| is_real(r)).map(|r| Complex64::new(r.re, 0.0)).collect();
+    reals.sort_by(|a, b| a.re.total_cmp(&b.re));
+    groups.extend(reals.chunks(2).map(|chunk| chunk.to_vec()));
+    groups
+}
+
+fn radius(group: &[Complex64]) -> f64 {
+    group.iter().map(|r| r.norm()).fold(0.0, f64::max)
+}
+
+fn distance(poles: &[Complex64], zeros: &[Complex64]) -> f64 {
+    zeros.iter().map(|z| (z - poles[0]).norm()).fold(f64::MAX, f64::min)
+}
+
+/// (c1, c2) of (1 - r1 z^-1)(1 - r2 z^-1) = 1 + c1 z^-1 + c2 z^-2; a lone root has c2 = 0.
+fn polynomial(group: &[Complex64]) -> (f64, f64) {
+    match group {
+        [r] => (-r.re, 0.0),
+        [r1, r2] => (-(r1 + r2).re, (r1 * r2).re),
+        _ => (0.0, 0.0),
+    }
+}
@@ -0,0 +1,21 @@
+/// Errors from building or running the signal-processing chain.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum DspError {
+    #[error("Sample rate must be greater than 0")]
+    InvalidSampleRate,
+
+    #[error("Invalid filter: {0}")]
+    InvalidFilter(String),
+
+    #[error("Invalid spectral analysis settings: {0}")]
+    InvalidSpectrum(String),
+
+    #[error("Buffers hold {expected} and {found} channels")]
+    ChannelCountMismatch { expected: usize, found: usize },
+
+    #[error("Invalid reference: {0}")]
+    InvalidReference(String),
+
+    #[error("Invalid artifact detection settings: {0}")]
+    InvalidArtifactSettings(String),
+}
@@ -1,194 +1,527 @@
-use biquad::{Biquad, DirectForm2Transposed, Coefficients, Type, Q_BUTTERWORTH_F32, ToHertz};
-use rustfft::{FftPlanner, num_complex::Complex};
-use std::sync::Arc;
-use std::time::Instant;
-use std::f32::consts::PI;
-// TODO add ADS1299 constants
-use std::error::Error;
-use std::thread::sleep;
-use std::time::{Duration, SystemTime};
-use tokio;
-use crate::board_driver::types::DriverError;
-use crate::board_driver::types::AdcDriver;
-use crate::board_driver::types::DriverStatus;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc;
+use biquad::{Biquad, DirectForm2Transposed, Coefficients, Type};
+use log::warn;
+use serde::{Serialize, Deserialize};
+
+use super::error::DspError;
+use super::design::{design, FilterFamily};
+use super::fir::{equiripple_for, forward_backward, windowed_sinc, FirFilter};
+use super::reference::{ReReferencer, Reference};
+
+pub use super::spectrum::FrequencyBins;
+
+/// Q of the default notch stages: narrow enough to leave neighbouring EEG bands alone.
+pub const DEFAULT_NOTCH_Q: f32 = 30.0;
+
+/// Highest order accepted for one stage.
+pub const MAX_FILTER_ORDER: usize = 8;
+
+/// Frequency response of one filter stage.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum FilterKind {
+    Highpass { cutoff_hz: f32 },
+    Lowpass { cutoff_hz: f32 },
+    Bandpass { low_hz: f32, high_hz: f32 },
+    Bandstop { low_hz: f32, high_hz: f32 },
+    /// Narrow second-order resonator whose width is set by the stage's Q
+    Notch { center_hz: f32 },
+}
+
+impl FilterKind {
+    /// Edge or center frequencies, in increasing order for a valid band.
+    pub fn frequencies(&self) -> Vec<f32> {
+        match *self {
+            FilterKind::Highpass { cutoff_hz } | FilterKind::Lowpass { cutoff_hz } => vec![cutoff_hz],
+            FilterKind::Bandpass { low_hz, high_hz } | FilterKind::Bandstop { low_hz, high_hz } => vec![low_hz, high_hz],
+            FilterKind::Notch { center_hz } => vec![center_hz],
+        }
+    }
+
+    /// The response that can actually be built below `nyquist`: a band reaching past it is
+    /// clipped to the edge that remains, and `None` means nothing is left to filter there.
+    fn fit_below(&self, nyquist: f32) -> Option<FilterKind> {
+        match *self {
+            FilterKind::Highpass { cutoff_hz } | FilterKind::Lowpass { cutoff_hz } | FilterKind::Notch { center_hz: cutoff_hz }
+                if cutoff_hz >= nyquist => None,
+            FilterKind::Bandpass { low_hz, .. } | FilterKind::Bandstop { low_hz, .. } if low_hz >= nyquist => None,
+            FilterKind::Bandpass { low_hz, high_hz } if high_hz >= nyquist => Some(FilterKind::Highpass { cutoff_hz: low_hz }),
+            FilterKind::Bandstop { low_hz, high_hz } if high_hz >= nyquist => Some(FilterKind::Lowpass { cutoff_hz: low_hz }),
+            _ => Some(self.clone()),
+        }
+    }
+}
+
+/// One stage of a [`FilterChainSpec`].
+///
+/// High-, low-, band-pass and band-stop stages are designed from `family` at `order` by
+/// [`design`]; a second-order Butterworth high- or low-pass may have its Q replaced by
+/// `q`. Notch stages cascade `order / 2` identical resonators with quality factor `q`, so
+/// their order must be even. The FIR families give a linear-phase kernel of `order + 1`
+/// taps, so their order must be even too; it may go well beyond [`MAX_FILTER_ORDER`].
+///
+/// FIR stages filter each block as it arrives, with no latency beyond their group delay.
+/// Kernels longer than the blocks are then convolved directly, costing a multiply per
+/// tap and sample. `block_fft` trades latency for that cost; see
+/// [`FirFilter::streaming`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FilterStage {
+    #[serde(flatten)]
+    pub kind: FilterKind,
+    #[serde(default)]
+    pub family: FilterFamily,
+    /// Quality factor; `None` uses the family's response and [`DEFAULT_NOTCH_Q`] for notches
+    #[serde(default)]
+    pub q: Option<f32>,
+    #[serde(default = "default_order")]
+    pub order: usize,
+    /// Disabled stages stay in the spec but are skipped
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Let a long FIR kernel gather samples across blocks until they fill an FFT, adding
+    /// a little under its length in latency. Ignored by IIR stages and by
+    /// [`FilterChainSpec::filtfilt`]
+    #[serde(default)]
+    pub block_fft: bool,
+}
+
+fn default_order() -> usize {
+    2
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl FilterStage {
+    pub fn new(kind: FilterKind) -> Self {
+        Self { kind, family: FilterFamily::default(), q: None, order: default_order(), enabled: true, block_fft: false }
+    }
+
+    pub fn highpass(cutoff_hz: f32) -> Self {
+        Self::new(FilterKind::Highpass { cutoff_hz })
+    }
+
+    pub fn lowpass(cutoff_hz: f32) -> Self {
+        Self::new(FilterKind::Lowpass { cutoff_hz })
+    }
+
+    pub fn bandpass(low_hz: f32, high_hz: f32) -> Self {
+        Self::new(FilterKind::Bandpass { low_hz, high_hz })
+    }
+
+    pub fn bandstop(low_hz: f32, high_hz: f32) -> Self {
+        Self::new(FilterKind::Bandstop { low_hz, high_hz })
+    }
+
+    pub fn notch(center_hz: f32) -> Self {
+        Self::new(FilterKind::Notch { center_hz })
+    }
+
+    pub fn with_family(self, family: FilterFamily) -> Self {
+        Self { family, ..self }
+    }
+
+    pub fn with_q(self, q: f32) -> Self {
+        Self { q: Some(q), ..self }
+    }
+
+    pub fn with_order(self, order: usize) -> Self {
+        Self { order, ..self }
+    }
+
+    pub fn with_enabled(self, enabled: bool) -> Self {
+        Self { enabled, ..self }
+    }
+
+    pub fn with_block_fft(self, block_fft: bool) -> Self {
+        Self { block_fft, ..self }
+    }
+
+    /// Check that the stage can be built at `sample_rate`.
+    pub fn validate(&self, sample_rate: u32) -> Result<(), DspError> {
+        self.design(sample_rate).map(|_| ())
+    }
+
+    /// Biquad sections in processing order, or the FIR kernel, making up this stage.
+    fn design(&self, sample_rate: u32) -> Result<StageDesign, DspError> {
+        if self.q.is_some_and(|q| q.is_nan() || q <= 0.0) {
+            return Err(DspError::InvalidFilter(
+                format!("{:?} filter Q must be positive", self.kind)
+            ));
+        }
+        let fs = sample_rate as f32;
+        // biquad's from_params divides by 2 fs instead of fs / 2, designing every filter at a
+        // quarter of the requested frequency, so normalise to Nyquist here
+        let biquad = |filter: Type<f32>, f0: f32, q: f32| {
+            Coefficients::<f32>::from_normalized_params(filter, f0 / (fs / 2.0), q).map_err(|e| DspError::InvalidFilter(
+                format!("Cannot build {:?} filter at {} Hz: {:?}", self.kind, sample_rate, e)
+            ))
+        };
+
+        match (self.kind.clone(), self.q) {
+            (FilterKind::Notch { center_hz }, q) => {
+                if self.order == 0 || self.order > MAX_FILTER_ORDER || !self.order.is_multiple_of(2) {
+                    return Err(DspError::InvalidFilter(format!(
+                        "{:?} filter order must be even and at most {}", self.kind, MAX_FILTER_ORDER
+                    )));
+                }
+                let section = biquad(Type::Notch, center_hz, q.unwrap_or(DEFAULT_NOTCH_Q))?;
+                Ok(StageDesign::Sections(vec![section; self.order / 2]))
+            }
+            (kind, _) if self.family.is_fir() => {
+                let taps = match self.family {
+                    FilterFamily::Equiripple { transition_hz } => equiripple_for(&kind, self.order + 1, transition_hz, fs)?,
+                    FilterFamily::WindowedSinc { window } => windowed_sinc(&kind, self.order + 1, window, fs)?,
+                    _ => unreachable!("checked by is_fir"),
+                };
+                Ok(StageDesign::Taps(taps))
+            }
+            (FilterKind::Highpass { cutoff_hz }, Some(q)) if self.order == 2 && self.family == FilterFamily::Butterworth => {
+                Ok(StageDesign::Sections(vec![biquad(Type::HighPass, cutoff_hz, q)?]))
+            }
+            (FilterKind::Lowpass { cutoff_hz }, Some(q)) if self.order == 2 && self.family == FilterFamily::Butterworth => {
+                Ok(StageDesign::Sections(vec![biquad(Type::LowPass, cutoff_hz, q)?]))
+            }
+            (kind, _) => design(self.family, &kind, self.order, fs).map(StageDesign::Sections),
+        }
+    }
+}
+
+enum StageDesign {
+    Sections(Vec<Coefficients<f32>>),
+    Taps(Vec<f32>),
+}
+
+/// A stage of a chain that cannot be built as specified at the sample rate in use, because
+/// part of its response lies at or above Nyquist.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StageAdjustment {
+    /// Position of the stage in [`FilterChainSpec::stages`]
+    pub index: usize,
+    pub requested: FilterKind,
+    /// Response built instead, `None` if the stage was dropped
+    pub realized: Option<FilterKind>,
+}
+
+/// Ordered list of filter stages applied to every channel.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FilterChainSpec {
+    #[serde(default)]
+    pub stages: Vec<FilterStage>,
+}
+
+impl Default for FilterChainSpec {
+    /// 0.1 Hz high-pass, 50 and 60 Hz notches and a 100 Hz low-pass.
+    fn default() -> Self {
+        Self {
+            stages: vec![
+                FilterStage::highpass(0.1),
+                FilterStage::notch(50.0),
+                FilterStage::notch(60.0),
+                FilterStage::lowpass(100.0),
+            ],
+        }
+    }
+}
+
+impl FilterChainSpec {
+    pub fn new(stages: Vec<FilterStage>) -> Self {
+        Self { stages }
+    }
+
+    /// A chain that passes samples through unchanged.
+    pub fn passthrough() -> Self {
+        Self::new(Vec::new())
+    }
+
+    /// The default chain with a single notch at the local mains frequency.
+    pub fn with_mains(mains_hz: f32) -> Self {
+        Self::new(vec![
+            FilterStage::highpass(0.1),
+            FilterStage::notch(mains_hz),
+            FilterStage::lowpass(100.0),
+        ])
+    }
+
+    /// Check that the chain can be built at `sample_rate`, after fitting it below Nyquist.
+    pub fn validate(&self, sample_rate: u32) -> Result<(), DspError> {
+        self.realize(sample_rate).map(|_| ())
+    }
+
+    /// The enabled stages as they can be built at `sample_rate`, with every stage that had to
+    /// be clipped or dropped to fit below Nyquist reported. The stages left must be valid.
+    pub fn realize(&self, sample_rate: u32) -> Result<(FilterChainSpec, Vec<StageAdjustment>), DspError> {
+        if sample_rate == 0 {
+            return Err(DspError::InvalidSampleRate);
+        }
+        let nyquist = sample_rate as f32 / 2.0;
+        let mut stages = Vec::new();
+        let mut adjustments = Vec::new();
+        for (index, stage) in self.stages.iter().enumerate().filter(|(_, stage)| stage.enabled) {
+            let realized = stage.kind.fit_below(nyquist);
+            if realized.as_ref() != Some(&stage.kind) {
+                adjustments.push(StageAdjustment { index, requested: stage.kind.clone(), realized: realized.clone() });
+            }
+            if let Some(kind) = realized {
+                let stage = FilterStage { kind, ..stage.clone() };
+                stage.validate(sample_rate)?;
+                stages.push(stage);
+            }
+        }
+        Ok((FilterChainSpec::new(stages), adjustments))
+    }
+
+    /// Zero-phase filtering of a recorded buffer: the chain runs forwards and then backwards,
+    /// so nothing is delayed and the magnitude response is squared. The ends are padded
+    /// with three times the summed stage lengths to keep start-up transients out.
+    pub fn filtfilt(&self, sample_rate: u32, data: &[f32]) -> Result<Vec<f32>, DspError> {
+        // Nothing is held back, so each pass lines up with its input
+        let mut processor = SignalProcessor::build_processor(sample_rate, 1, self.clone(), false)?;
+        let padding = 3 * self.stages.iter().filter(|stage| stage.enabled).map(|stage| stage.order + 1).sum::<usize>();
+        Ok(forward_backward(data, padding, |pass| {
+            processor.clear();
+            processor.process_block(0, pass)
+        }))
+    }
+}
 
-#[derive(Debug)]
-pub struct FrequencyBins {
-    // Delta (0.5-4 Hz) - 7 bins
-    delta: Vec<f32>,  
-    // Theta (4-8 Hz) - 8 bins
-    theta: Vec<f32>,  
-    // Alpha (8-13 Hz) - 10 bins
-    alpha: Vec<f32>,  
-    // Beta (13-30 Hz) - 17 bins
-    beta: Vec<f32>,   
-    // Gamma (30-150 Hz) - 32 bins
-    gamma: Vec<f32>,
-    line_noise_50hz: f32,  // Around 50Hz
-    line_noise_60hz: f32,  // Around 60Hz
-}
-
-// Update the FilterCoefficients to use biquad's Coefficients
-#[derive(Clone, Debug)]
-struct FilterCoefficients {
-    coeffs: Coefficients<f32>
-}
-
-// Simplify DigitalFilter to use biquad's DirectForm2Transposed
 #[derive(Debug)]
 struct DigitalFilter {
     filter: DirectForm2Transposed<f32>
 }
 
 impl DigitalFilter {
-    fn new(coeffs: FilterCoefficients) -> Self {
+    fn new(coeffs: Coefficients<f32>) -> Self {
         Self {
-            filter: DirectForm2Transposed::new(coeffs.coeffs)
+            filter: DirectForm2Transposed::new(coeffs)
         }
     }
 
     fn process(&mut self, x: f32) -> f32 {
-        // Clamp input to prevent extreme values
-        let x = x.clamp(-8192.0, 8191.0);
-        let y = self.filter.run(x);
-        // Clamp output to prevent instability
-        y.clamp(-8192.0, 8191.0)
+        self.filter.run(x)
     }
-}
 
-// Add struct definitions for each filter type
-#[derive(Debug)]
-struct NotchFilter(DigitalFilter);
+    fn reset(&mut self) {
+        self.filter.reset_state();
+    }
+}
 
+/// One channel's filter for a stage.
 #[derive(Debug)]
-struct HighpassFilter(DigitalFilter);
+enum ChannelFilter {
+    Iir(Vec<DigitalFilter>),
+    Fir(FirFilter),
+}
 
-#[derive(Debug)]
-struct LowpassFilter(DigitalFilter);
-
-// Update NotchFilter implementation to use BandPass instead of NotchFilter
-impl NotchFilter {
-    fn new(sample_rate: f32, notch_freq: f32) -> Self {
-        let q_factor = 30.0;  // High Q for narrow notch
-        let coeffs = Coefficients::<f32>::from_params(
-            Type::Notch,  // <-- Changed from BandPass to Notch
-            sample_rate.hz(),
-            notch_freq.hz(),
-            q_factor
-        ).unwrap();
-        
-        NotchFilter(DigitalFilter::new(FilterCoefficients { coeffs }))
-    }
-    
+impl ChannelFilter {
     fn process(&mut self, x: f32) -> f32 {
-        self.0.process(x)
+        match self {
+            ChannelFilter::Iir(sections) => sections.iter_mut().fold(x, |x, section| section.process(x)),
+            ChannelFilter::Fir(fir) => fir.process(x),
+        }
     }
-}
 
-// Similar updates for HighpassFilter
-impl HighpassFilter {
-    fn new(sample_rate: f32) -> Self {
-        // Lower the cutoff frequency to reduce impact on low frequency signals
-        let cutoff_freq = 0.1; // Changed from 0.5 to 0.1 Hz
-        let coeffs = Coefficients::<f32>::from_params(
-            Type::HighPass,
-            sample_rate.hz(),
-            cutoff_freq.hz(),
-            Q_BUTTERWORTH_F32
-        ).unwrap();
-        
-        Self(DigitalFilter::new(FilterCoefficients { coeffs }))
+    fn process_block(&mut self, samples: &[f32]) -> Vec<f32> {
+        match self {
+            ChannelFilter::Iir(_) => samples.iter().map(|&x| self.process(x)).collect(),
+            ChannelFilter::Fir(fir) => fir.process_block(samples),
+        }
     }
-    
-    fn process(&mut self, x: f32) -> f32 {
-        self.0.process(x)
+
+    fn reset(&mut self) {
+        match self {
+            ChannelFilter::Iir(sections) => sections.iter_mut().for_each(DigitalFilter::reset),
+            ChannelFilter::Fir(fir) => fir.reset(),
+        }
     }
 }
 
-// And LowpassFilter
-impl LowpassFilter {
-    fn new(sample_rate: f32) -> Self {
-        let cutoff_freq = 100.0;
-        let coeffs = Coefficients::<f32>::from_params(
-            Type::LowPass,
-            sample_rate.hz(),
-            cutoff_freq.hz(),
-            Q_BUTTERWORTH_F32
-        ).unwrap();
-        
-        Self(DigitalFilter::new(FilterCoefficients { coeffs }))
+/// A built stage: its spec and the filter of every channel.
+#[derive(Debug)]
+struct ChainStage {
+    spec: FilterStage,
+    /// Group delay in samples; only linear-phase FIR stages have a constant one
+    delay_samples: f32,
+    channels: Vec<ChannelFilter>,
+}
+
+impl ChainStage {
+    /// Stage for `num_channels` channels. When `streaming`, FIR stages asking for
+    /// `block_fft` hold samples back to run FFTs on short blocks; see
+    /// [`FirFilter::streaming`].
+    fn new(spec: &FilterStage, sample_rate: u32, num_channels: usize, streaming: bool) -> Result<Self, DspError> {
+        let design = spec.design(sample_rate)?;
+        let channels: Vec<ChannelFilter> = (0..num_channels)
+            .map(|_| match &design {
+                StageDesign::Sections(sections) => ChannelFilter::Iir(sections.iter().map(|&c| DigitalFilter::new(c)).collect()),
+                StageDesign::Taps(taps) if streaming && spec.block_fft => ChannelFilter::Fir(FirFilter::streaming(taps.clone())),
+                StageDesign::Taps(taps) => ChannelFilter::Fir(FirFilter::new(taps.clone())),
+            })
+            .collect();
+        let delay_samples = match channels.first() {
+            Some(ChannelFilter::Fir(fir)) => fir.group_delay_samples(),
+            _ => 0.0,
+        };
+        Ok(Self { spec: spec.clone(), delay_samples, channels })
     }
-    
-    fn process(&mut self, x: f32) -> f32 {
-        self.0.process(x)
+
+    fn process(&mut self, channel: usize, x: f32) -> f32 {
+        self.channels[channel].process(x)
     }
 }
 
 pub struct SignalProcessor {
     sample_rate: u32,
     num_channels: usize,
-    notch_filters_50hz: Vec<NotchFilter>,
-    notch_filters_60hz: Vec<NotchFilter>,
-    highpass_filters: Vec<HighpassFilter>,
-    lowpass_filters: Vec<LowpassFilter>,
+    spec: FilterChainSpec,
+    stages: Vec<ChainStage>,
+    adjustments: Vec<StageAdjustment>,
+    referencer: ReReferencer,
+    /// Whether FIR stages may hold samples back for FFTs; off to filter recorded buffers
+    streaming: bool,
 }
 
 impl SignalProcessor {
-    pub fn new(sample_rate: u32, num_channels: usize) -> Self {
-        // Add validation for sample rate
-        assert!(sample_rate > 0, "Sample rate must be positive");
-        assert!(sample_rate >= 200, "Sample rate should be at least 200Hz for proper filter operation");
-        
-        let sample_rate_f32 = sample_rate as f32;
-        
-        Self {
-            sample_rate,
-            num_channels,
-            notch_filters_50hz: (0..num_channels)
-                .map(|_| NotchFilter::new(sample_rate_f32, 50.0))
-                .collect(),
-            notch_filters_60hz: (0..num_channels)
-                .map(|_| NotchFilter::new(sample_rate_f32, 60.0))
-                .collect(),
-            highpass_filters: (0..num_channels)
-                .map(|_| HighpassFilter::new(sample_rate_f32))
-                .collect(),
-            lowpass_filters: (0..num_channels)
-                .map(|_| LowpassFilter::new(sample_rate_f32))
-                .collect(),
-        }
+    /// Processor running the default filter chain.
+    pub fn new(sample_rate: u32, num_channels: usize) -> Result<Self, DspError> {
+        Self::with_chain(sample_rate, num_channels, FilterChainSpec::default())
+    }
+
+    /// Processor running `spec` on every channel. Stages reaching past Nyquist are fitted
+    /// below it; see [`SignalProcessor::adjustments`].
+    pub fn with_chain(sample_rate: u32, num_channels: usize, spec: FilterChainSpec) -> Result<Self, DspError> {
+        Self::build_processor(sample_rate, num_channels, spec, true)
+    }
+
+    fn build_processor(sample_rate: u32, num_channels: usize, spec: FilterChainSpec, streaming: bool) -> Result<Self, DspError> {
+        let (stages, adjustments) = Self::build(&spec, sample_rate, num_channels, Vec::new(), streaming)?;
+        let referencer = ReReferencer::new(Reference::AsRecorded, num_channels)?;
+        Ok(Self { sample_rate, num_channels, spec, stages, adjustments, referencer, streaming })
     }
 
     pub fn process_sample(&mut self, channel: usize, sample: f32) -> f32 {
         // Add channel bounds check
         assert!(channel < self.num_channels, "Channel index out of bounds");
-        
-        let mut processed = sample;
-        processed = self.highpass_filters[channel].process(processed);  // Move highpass first
-        processed = self.notch_filters_50hz[channel].process(processed);
-        processed = self.notch_filters_60hz[channel].process(processed);
-        processed = self.lowpass_filters[channel].process(processed);
-        processed
+
+        self.stages.iter_mut().fold(sample, |x, stage| stage.process(channel, x))
     }
 
-    pub fn reset(&mut self, new_sample_rate: u32, new_num_channels: usize) {
+    /// Filter a run of consecutive samples of one channel. Same output as feeding them to
+    /// [`SignalProcessor::process_sample`] one by one, but long FIR stages run through FFTs
+    /// for long enough blocks, or once they have gathered one with
+    /// [`FilterStage::block_fft`].
+    pub fn process_block(&mut self, channel: usize, samples: &[f32]) -> Vec<f32> {
+        assert!(channel < self.num_channels, "Channel index out of bounds");
+
+        self.stages.iter_mut().fold(samples.to_vec(), |block, stage| stage.channels[channel].process_block(&block))
+    }
+
+    /// Filter a block holding the same run of samples of every channel, then re-reference
+    /// it. The output has [`SignalProcessor::output_count`] channels.
+    pub fn process_channels(&mut self, channels: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        let filtered: Vec<Vec<f32>> = channels.iter().enumerate()
+            .map(|(channel, samples)| self.process_block(channel, samples))
+            .collect();
+        self.referencer.apply(&filtered)
+    }
+
+    /// Delay the FIR stages add to the signal, in seconds, including the samples
+    /// [`FilterStage::block_fft`] stages hold back for their FFTs. IIR stages have no single group delay and are not
+    /// counted.
+    pub fn group_delay_secs(&self) -> f32 {
+        self.stages.iter().map(|stage| stage.delay_samples).sum::<f32>() / self.sample_rate as f32
+    }
+
+    /// Clear the state of every stage without rebuilding it.
+    fn clear(&mut self) {
+        self.stages.iter_mut().flat_map(|stage| stage.channels.iter_mut()).for_each(ChannelFilter::reset);
+    }
+
+    /// The filter chain currently applied, as requested.
+    pub fn chain(&self) -> &FilterChainSpec {
+        &self.spec
+    }
+
+    /// Stages of the chain that were clipped or dropped to fit below Nyquist.
+    pub fn adjustments(&self) -> &[StageAdjustment] {
+        &self.adjustments
+    }
+
+    /// The re-referencing applied by [`SignalProcessor::process_channels`].
+    pub fn reference(&self) -> &Reference {
+        self.referencer.reference()
+    }
+
+    /// Switch to a new re-referencing between blocks. The filters are unaffected.
+    pub fn set_reference(&mut self, reference: Reference) -> Result<(), DspError> {
+        self.referencer = ReReferencer::new(reference, self.num_channels)?;
+        Ok(())
+    }
+
+    /// Channels [`SignalProcessor::process_channels`] puts out.
+    pub fn output_count(&self) -> usize {
+        self.referencer.output_count()
+    }
+
+    /// Input channels each output channel is derived from, in output order.
+    pub fn output_contributors(&self) -> Vec<Vec<usize>> {
+        self.referencer.contributors()
+    }
+
+    /// Labels of the output channels, given those of the input.
+    pub fn output_labels(&self, labels: &[String]) -> Vec<String> {
+        self.referencer.labels(labels)
+    }
+
+    /// Input channel each output channel is centred on, in output order.
+    pub fn output_sources(&self) -> Vec<usize> {
+        self.referencer.sources()
+    }
+
+    /// Switch to a new filter chain between samples. Stages that appear unchanged in the
+    /// new chain keep their state, so the output stays continuous through them.
+    pub fn set_chain(&mut self, spec: FilterChainSpec) -> Result<(), DspError> {
+        spec.validate(self.sample_rate)?;
+        let previous = std::mem::take(&mut self.stages);
+        (self.stages, self.adjustments) = Self::build(&spec, self.sample_rate, self.num_channels, previous, self.streaming)?;
+        self.spec = spec;
+        Ok(())
+    }
+
+    /// Rebuild every stage with fresh state for a new sample rate and channel count. The
+    /// current chain is fitted below the new Nyquist frequency again, and the reference
+    /// must still fit the new channel count.
+    pub fn reset(&mut self, new_sample_rate: u32, new_num_channels: usize) -> Result<(), DspError> {
+        let referencer = ReReferencer::new(self.reference().clone(), new_num_channels)?;
+        (self.stages, self.adjustments) = Self::build(&self.spec, new_sample_rate, new_num_channels, Vec::new(), self.streaming)?;
+        self.referencer = referencer;
         self.sample_rate = new_sample_rate;
         self.num_channels = new_num_channels;
-        // Recreate all filters
-        let sample_rate = self.sample_rate as f32;
-        self.notch_filters_50hz = (0..self.num_channels)
-            .map(|_| NotchFilter::new(sample_rate, 50.0))
-            .collect();
-        self.notch_filters_60hz = (0..self.num_channels)
-            .map(|_| NotchFilter::new(sample_rate, 60.0))
-            .collect();
-        self.highpass_filters = (0..self.num_channels)
-            .map(|_| HighpassFilter::new(sample_rate))
-            .collect();
-        self.lowpass_filters = (0..self.num_channels)
-            .map(|_| LowpassFilter::new(sample_rate))
-            .collect();
+        Ok(())
     }
-}
\ No newline at end of file
+
+    /// Build the enabled stages of `spec` as realizable at `sample_rate`, taking over any
+    /// identical stage from `previous`.
+    fn build(
+        spec: &FilterChainSpec,
+        sample_rate: u32,
+        num_channels: usize,
+        mut previous: Vec<ChainStage>,
+        streaming: bool,
+    ) -> Result<(Vec<ChainStage>, Vec<StageAdjustment>), DspError> {
+        let (realized, adjustments) = spec.realize(sample_rate)?;
+        for adjustment in &adjustments {
+            match &adjustment.realized {
+                Some(kind) => warn!("{:?} clipped to {:?} below Nyquist at {} Hz", adjustment.requested, kind, sample_rate),
+                None => warn!("{:?} dropped, nothing to filter below Nyquist at {} Hz", adjustment.requested, sample_rate),
+            }
+        }
+        let stages = realized.stages.iter()
+            .map(|stage| match previous.iter().position(|old| old.spec == *stage) {
+                Some(i) => Ok(previous.remove(i)),
+                None => ChainStage::new(stage, sample_rate, num_channels, streaming),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok((stages, adjustments))
+    }
+}
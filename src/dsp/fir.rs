@@ -0,0 +1,523 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::sync::Arc;
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use serde::{Serialize, Deserialize};
+
+use super::error::DspError;
+use super::filters::FilterKind;
+
+/// Highest FIR order (taps - 1) accepted.
+pub const MAX_FIR_ORDER: usize = 4096;
+
+/// Highest Parks-McClellan order; the exchange loses precision on longer filters.
+pub const MAX_EQUIRIPPLE_ORDER: usize = 256;
+
+/// Kernels with more taps than this are run through FFT overlap-save.
+const DIRECT_FORM_MAX_TAPS: usize = 64;
+
+/// Window applied to the ideal impulse response in windowed-sinc design.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FirWindow {
+    Rectangular,
+    Hann,
+    /// About 53 dB stopband attenuation
+    #[default]
+    Hamming,
+    /// About 74 dB stopband attenuation with a wider transition
+    Blackman,
+    /// Trade-off set by `beta`: larger is more attenuation and a wider transition
+    Kaiser { beta: f32 },
+}
+
+impl FirWindow {
+    pub(crate) fn coefficients(self, len: usize) -> Vec<f64> {
+        if len == 1 {
+            return vec![1.0];
+        }
+        let span = (len - 1) as f64;
+        (0..len).map(|n| {
+            let phase = 2.0 * PI * n as f64 / span;
+            match self {
+                FirWindow::Rectangular => 1.0,
+                FirWindow::Hann => 0.5 - 0.5 * phase.cos(),
+                FirWindow::Hamming => 0.54 - 0.46 * phase.cos(),
+                FirWindow::Blackman => 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos(),
+                FirWindow::Kaiser { beta } => {
+                    let ratio = 2.0 * n as f64 / span - 1.0;
+                    bessel_i0(beta as f64 * (1.0 - ratio * ratio).sqrt()) / bessel_i0(beta as f64)
+                }
+            }
+        }).collect()
+    }
+}
+
+/// Modified Bessel function of the first kind, order zero.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    for k in 1..50 {
+        term *= (x / (2.0 * k as f64)).powi(2);
+        sum += term;
+        if term < sum * 1e-16 {
+            break;
+        }
+    }
+    sum
+}
+
+/// Windowed-sinc design of a linear-phase FIR filter with an odd number of taps.
+///
+/// The ideal response is truncated by `window` and scaled to unity gain in the middle of
+/// the passband (DC for low-pass and band-stop, Nyquist for high-pass).
+pub fn windowed_sinc(kind: &FilterKind, num_taps: usize, window: FirWindow, sample_rate: f32) -> Result<Vec<f32>, DspError> {
+    check_taps(num_taps, MAX_FIR_ORDER)?;
+    let edges = check_edges(kind, sample_rate)?;
+    let middle = (num_taps / 2) as f64;
+    // Ideal low-pass with cutoff `f` (cycles per sample)
+    let lowpass = |f: f64, n: usize| {
+        let t = n as f64 - middle;
+        if t == 0.0 { 2.0 * f } else { (2.0 * PI * f * t).sin() / (PI * t) }
+    };
+    let impulse = |n: usize| if n as f64 == middle { 1.0 } else { 0.0 };
+
+    let (ideal, unity_at): (Vec<f64>, f64) = match (kind, edges.as_slice()) {
+        (FilterKind::Lowpass { .. }, &[fc]) => ((0..num_taps).map(|n| lowpass(fc, n)).collect(), 0.0),
+        (FilterKind::Highpass { .. }, &[fc]) => ((0..num_taps).map(|n| impulse(n) - lowpass(fc, n)).collect(), 0.5),
+        (FilterKind::Bandpass { .. }, &[low, high]) => {
+            ((0..num_taps).map(|n| lowpass(high, n) - lowpass(low, n)).collect(), (low + high) / 2.0)
+        }
+        (FilterKind::Bandstop { .. }, &[low, high]) => {
+            ((0..num_taps).map(|n| impulse(n) - lowpass(high, n) + lowpass(low, n)).collect(), 0.0)
+        }
+        _ => return Err(DspError::InvalidFilter(
+            format!("{:?} cannot be designed as an FIR filter", kind)
+        )),
+    };
+
+    let mut taps: Vec<f64> = ideal.iter().zip(window.coefficients(num_taps)).map(|(h, w)| h * w).collect();
+    let gain = response(&taps, unity_at).norm();
+    taps.iter_mut().for_each(|h| *h /= gain);
+    Ok(taps.into_iter().map(|h| h as f32).collect())
+}
+
+/// Equiripple design of `kind` with transition bands `transition_hz` wide, centred on each
+/// cutoff, and equal weight on every band.
+pub fn equiripple_for(kind: &FilterKind, num_taps: usize, transition_hz: f32, sample_rate: f32) -> Result<Vec<f32>, DspError> {
+    check_edges(kind, sample_rate)?;
+    if transition_hz.is_nan() || transition_hz <= 0.0 {
+        return Err(DspError::InvalidFilter("Equiripple transition width must be positive".to_string()));
+    }
+    let half = transition_hz / 2.0;
+    let nyquist = sample_rate / 2.0;
+    let (bands, desired) = match *kind {
+        FilterKind::Lowpass { cutoff_hz } => (vec![(0.0, cutoff_hz - half), (cutoff_hz + half, nyquist)], vec![1.0, 0.0]),
+        FilterKind::Highpass { cutoff_hz } => (vec![(0.0, cutoff_hz - half), (cutoff_hz + half, nyquist)], vec![0.0, 1.0]),
+        FilterKind::Bandpass { low_hz, high_hz } => (
+            vec![(0.0, low_hz - half), (low_hz + half, high_hz - half), (high_hz + half, nyquist)],
+            vec![0.0, 1.0, 0.0],
+        ),
+        FilterKind::Bandstop { low_hz, high_hz } => (
+            vec![(0.0, low_hz - half), (low_hz + half, high_hz - half), (high_hz + half, nyquist)],
+            vec![1.0, 0.0, 1.0],
+        ),
+        FilterKind::Notch { .. } => return Err(DspError::InvalidFilter(
+            "Notch filters are single resonators, not designed as FIR filters".to_string()
+        )),
+    };
+    equiripple(num_taps, &bands, &desired, &vec![1.0; bands.len()], sample_rate)
+}
+
+/// Parks-McClellan (Remez exchange) design of an equiripple linear-phase FIR filter with an
+/// odd number of taps.
+///
+/// `bands` are `(low_hz, high_hz)` pairs in increasing order with `desired` gain and error
+/// `weight` per band; frequencies between bands are don't-care transitions.
+pub fn equiripple(
+    num_taps: usize,
+    bands: &[(f32, f32)],
+    desired: &[f32],
+    weights: &[f32],
+    sample_rate: f32,
+) -> Result<Vec<f32>, DspError> {
+    check_taps(num_taps, MAX_EQUIRIPPLE_ORDER)?;
+    let nyquist = sample_rate / 2.0;
+    let ordered = bands.iter().all(|&(low, high)| low >= 0.0 && low < high && high <= nyquist)
+        && bands.windows(2).all(|w| w[0].1 < w[1].0);
+    if bands.is_empty() || !ordered || desired.len() != bands.len() || weights.len() != bands.len() {
+        return Err(DspError::InvalidFilter(format!(
+            "Equiripple bands must be increasing, non-overlapping and within 0..{} Hz, with one gain and weight each",
+            nyquist
+        )));
+    }
+    if weights.iter().any(|&w| w.is_nan() || w <= 0.0) {
+        return Err(DspError::InvalidFilter("Equiripple weights must be positive".to_string()));
+    }
+
+    let m = num_taps / 2;
+    let extremals = m + 2;
+    // Dense grid in cycles per sample, as in the original algorithm: 16 points per extremal
+    let spacing = 0.5 / (16 * extremals) as f64;
+    let mut grid: Vec<(f64, f64, f64)> = Vec::new();
+    // Band of each grid point; extremals are only searched within a band
+    let mut band_of: Vec<usize> = Vec::new();
+    for (band, (&(low, high), (&d, &w))) in bands.iter().zip(desired.iter().zip(weights)).enumerate() {
+        let (low, high) = (low as f64 / sample_rate as f64, high as f64 / sample_rate as f64);
+        let points = ((high - low) / spacing).ceil().max(1.0) as usize;
+        grid.extend((0..=points).map(|i| (low + (high - low) * i as f64 / points as f64, d as f64, w as f64)));
+        band_of.extend(std::iter::repeat_n(band, points + 1));
+    }
+    if grid.len() < extremals {
+        return Err(DspError::InvalidFilter("Equiripple bands are too narrow for this many taps".to_string()));
+    }
+
+    let mut ext: Vec<usize> = (0..extremals).map(|i| i * (grid.len() - 1) / (extremals - 1)).collect();
+    let mut amplitude = Interpolant::new(&grid, &ext);
+    for _ in 0..100 {
+        let error: Vec<f64> = grid.iter().map(|&(f, d, w)| w * (d - amplitude.at(f))).collect();
+        let next = select_extremals(&error, &band_of, extremals);
+        if next.len() < extremals || next == ext {
+            break;
+        }
+        ext = next;
+        amplitude = Interpolant::new(&grid, &ext);
+    }
+
+    // Invert the cosine series from samples of the amplitude at the DFT frequencies
+    let n = num_taps as f64;
+    let samples: Vec<f64> = (0..=m).map(|k| amplitude.at(k as f64 / n)).collect();
+    Ok((0..num_taps).map(|i| {
+        let t = i as f64 - m as f64;
+        let sum: f64 = samples.iter().enumerate().skip(1).map(|(k, a)| 2.0 * a * (2.0 * PI * k as f64 * t / n).cos()).sum();
+        ((samples[0] + sum) / n) as f32
+    }).collect())
+}
+
+/// Amplitude of the best approximation on the current extremal set, by barycentric
+/// Lagrange interpolation in x = cos(2 pi f).
+struct Interpolant {
+    x: Vec<f64>,
+    values: Vec<f64>,
+    weights: Vec<f64>,
+}
+
+impl Interpolant {
+    fn new(grid: &[(f64, f64, f64)], ext: &[usize]) -> Self {
+        let x: Vec<f64> = ext.iter().map(|&i| (2.0 * PI * grid[i].0).cos()).collect();
+        let all = barycentric_weights(&x);
+        let sign = |k: usize| if k.is_multiple_of(2) { 1.0 } else { -1.0 };
+        let numerator: f64 = ext.iter().zip(&all).map(|(&i, b)| b * grid[i].1).sum();
+        let denominator: f64 = ext.iter().zip(&all).enumerate().map(|(k, (&i, b))| sign(k) * b / grid[i].2).sum();
+        let delta = numerator / denominator;
+
+        // The last extremal is implied by the alternation, so interpolate through the rest
+        let last = ext.len() - 1;
+        let values = ext[..last].iter().enumerate().map(|(k, &i)| grid[i].1 - sign(k) * delta / grid[i].2).collect();
+        let x = x[..last].to_vec();
+        let weights = barycentric_weights(&x);
+        Self { x, values, weights }
+    }
+
+    fn at(&self, f: f64) -> f64 {
+        let x = (2.0 * PI * f).cos();
+        let mut numerator = 0.0;
+        let mut denominator = 0.0;
+        for ((&xk, &value), &weight) in self.x.iter().zip(&self.values).zip(&self.weights) {
+            let dx = x - xk;
+            if dx.abs() < 1e-14 {
+                return value;
+            }
+            numerator += weight * value / dx;
+            denominator += weight / dx;
+        }
+        numerator / denominator
+    }
+}
+
+/// 1 / prod(2 (x_k - x_j)); the factor 2 keeps the products clear of underflow.
+fn barycentric_weights(x: &[f64]) -> Vec<f64> {
+    (0..x.len())
+        .map(|k| 1.0 / (0..x.len()).filter(|&j| j != k).map(|j| 2.0 * (x[k] - x[j])).product::<f64>())
+        .collect()
+}
+
+/// Local maxima of |error| within each band, reduced to an alternating set of `count`.
+fn select_extremals(error: &[f64], band_of: &[usize], count: usize) -> Vec<usize> {
+    let mut candidates: Vec<usize> = Vec::new();
+    for i in 0..error.len() {
+        // Extrema of the signed error; a neighbour of the other sign never hides one
+        let sign = if error[i] >= 0.0 { 1.0 } else { -1.0 };
+        let e = sign * error[i];
+        let left = i == 0 || band_of[i - 1] != band_of[i] || e >= sign * error[i - 1];
+        let right = i + 1 == error.len() || band_of[i + 1] != band_of[i] || e > sign * error[i + 1];
+        if !(left && right) {
+            continue;
+        }
+        // Neighbours of the same sign are one extremum; keep the larger
+        match candidates.last() {
+            Some(&last) if (error[last] >= 0.0) == (error[i] >= 0.0) => {
+                if e > error[last].abs() {
+                    *candidates.last_mut().unwrap() = i;
+                }
+            }
+            _ => candidates.push(i),
+        }
+    }
+    while candidates.len() > count {
+        if error[candidates[0]].abs() < error[*candidates.last().unwrap()].abs() {
+            candidates.remove(0);
+        } else {
+            candidates.pop();
+        }
+    }
+    candidates
+}
+
+fn check_taps(num_taps: usize, max_order: usize) -> Result<(), DspError> {
+    if num_taps < 3 || num_taps.is_multiple_of(2) || num_taps > max_order + 1 {
+        return Err(DspError::InvalidFilter(format!(
+            "Linear-phase FIR filters need an odd number of taps between 3 and {}", max_order + 1
+        )));
+    }
+    Ok(())
+}
+
+/// Band edges of `kind` in cycles per sample, checked to lie strictly inside 0..Nyquist.
+fn check_edges(kind: &FilterKind, sample_rate: f32) -> Result<Vec<f64>, DspError> {
+    let edges = kind.frequencies();
+    let nyquist = sample_rate / 2.0;
+    if edges.iter().any(|&f| !(f > 0.0 && f < nyquist)) || edges.windows(2).any(|w| w[0] >= w[1]) {
+        return Err(DspError::InvalidFilter(format!(
+            "{:?} filter edges must be increasing and between 0 and {} Hz (half the sample rate)",
+            kind, nyquist
+        )));
+    }
+    Ok(edges.iter().map(|&f| f as f64 / sample_rate as f64).collect())
+}
+
+/// Frequency response of `taps` at `f` cycles per sample.
+fn response(taps: &[f64], f: f64) -> Complex<f64> {
+    taps.iter().enumerate().map(|(n, &h)| Complex::from_polar(h, -2.0 * PI * f * n as f64)).sum()
+}
+
+/// Magnitude of the frequency response of an FIR kernel at `frequency`.
+pub fn magnitude_response(taps: &[f32], frequency: f32, sample_rate: f32) -> f64 {
+    let taps: Vec<f64> = taps.iter().map(|&h| h as f64).collect();
+    response(&taps, frequency as f64 / sample_rate as f64).norm()
+}
+
+/// Streaming FIR filter. Long kernels run through FFT overlap-save.
+///
+/// Built with [`FirFilter::new`], the output is sample-for-sample identical to direct
+/// convolution, and FFTs run whenever a single block holds enough samples. Built with
+/// [`FirFilter::streaming`] for the short blocks of an acquisition, long kernels hold
+/// inputs back across calls until they fill an FFT block, which delays the output by
+/// [`FirFilter::latency_samples`] more.
+pub struct FirFilter {
+    taps: Vec<f32>,
+    /// The last `taps.len() - 1` inputs, oldest first
+    history: VecDeque<f32>,
+    overlap_save: Option<OverlapSave>,
+    /// Set on streaming filters with an FFT kernel
+    pending: Option<Pending>,
+}
+
+struct OverlapSave {
+    /// Outputs produced per FFT
+    block: usize,
+    kernel: Vec<Complex<f32>>,
+    forward: Arc<dyn Fft<f32>>,
+    inverse: Arc<dyn Fft<f32>>,
+    /// FFT blocks run so far
+    runs: u64,
+}
+
+impl OverlapSave {
+    fn new(taps: &[f32], size: usize) -> Self {
+        let mut planner = FftPlanner::new();
+        let forward = planner.plan_fft_forward(size);
+        let mut kernel: Vec<Complex<f32>> = taps.iter().map(|&h| Complex::new(h, 0.0)).collect();
+        kernel.resize(size, Complex::new(0.0, 0.0));
+        forward.process(&mut kernel);
+        Self { block: size - taps.len() + 1, kernel, forward, inverse: planner.plan_fft_inverse(size), runs: 0 }
+    }
+}
+
+/// Inputs a streaming filter holds back until they fill an FFT block, and outputs not
+/// handed out yet.
+struct Pending {
+    inputs: Vec<f32>,
+    /// Starts with `latency` zeros, so there are always as many as were asked for
+    outputs: VecDeque<f32>,
+    latency: usize,
+}
+
+impl Pending {
+    fn new(latency: usize) -> Self {
+        Self { inputs: Vec::new(), outputs: VecDeque::from(vec![0.0; latency]), latency }
+    }
+}
+
+impl std::fmt::Debug for FirFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FirFilter")
+            .field("taps", &self.taps.len())
+            .field("overlap_save", &self.overlap_save.as_ref().map(|o| o.block))
+            .field("latency", &self.latency_samples())
+            .finish()
+    }
+}
+
+impl FirFilter {
+    pub fn new(taps: Vec<f32>) -> Self {
+        let overlap_save = (taps.len() > DIRECT_FORM_MAX_TAPS)
+            .then(|| OverlapSave::new(&taps, (4 * taps.len()).next_power_of_two()));
+        Self { history: VecDeque::from(vec![0.0; taps.len().saturating_sub(1)]), taps, overlap_save, pending: None }
+    }
+
+    /// Filter for blocks too short to fill an FFT on their own. Long kernels get an FFT
+    /// of about twice their length, so they add a little less than their own length of
+    /// latency; short ones are convolved directly and add none.
+    pub fn streaming(taps: Vec<f32>) -> Self {
+        if taps.len() <= DIRECT_FORM_MAX_TAPS {
+            return Self::new(taps);
+        }
+        let overlap_save = OverlapSave::new(&taps, (2 * taps.len()).next_power_of_two());
+        let pending = Pending::new(Self::streaming_latency(taps.len()));
+        Self {
+            history: VecDeque::from(vec![0.0; taps.len() - 1]),
+            taps,
+            overlap_save: Some(overlap_save),
+            pending: Some(pending),
+        }
+    }
+
+    pub fn taps(&self) -> &[f32] {
+        &self.taps
+    }
+
+    /// Delay of every frequency component through a linear-phase kernel, in samples,
+    /// including [`FirFilter::latency_samples`].
+    pub fn group_delay_samples(&self) -> f32 {
+        self.taps.len().saturating_sub(1) as f32 / 2.0 + self.latency_samples() as f32
+    }
+
+    /// Samples a streaming filter holds its output back by to fill FFT blocks.
+    pub fn latency_samples(&self) -> usize {
+        self.pending.as_ref().map_or(0, |pending| pending.latency)
+    }
+
+    /// Latency [`FirFilter::streaming`] gives a kernel of `num_taps`, for lining up
+    /// filters of different lengths before building them.
+    pub fn streaming_latency(num_taps: usize) -> usize {
+        if num_taps <= DIRECT_FORM_MAX_TAPS {
+            return 0;
+        }
+        // One less than the outputs per FFT
+        (2 * num_taps).next_power_of_two() - num_taps
+    }
+
+    /// FFT blocks run so far, to tell whether the blocks are long enough for them.
+    pub fn fft_runs(&self) -> u64 {
+        self.overlap_save.as_ref().map_or(0, |os| os.runs)
+    }
+
+    pub fn process(&mut self, x: f32) -> f32 {
+        if self.pending.is_some() {
+            return self.process_block(&[x])[0];
+        }
+        let Some((&first, rest)) = self.taps.split_first() else {
+            return 0.0;
+        };
+        let y = first * x + rest.iter().zip(self.history.iter().rev()).map(|(h, past)| h * past).sum::<f32>();
+        if self.history.pop_front().is_some() {
+            self.history.push_back(x);
+        }
+        y
+    }
+
+    pub fn process_block(&mut self, input: &[f32]) -> Vec<f32> {
+        let Some(mut pending) = self.pending.take() else {
+            return self.convolve(input);
+        };
+        pending.inputs.extend_from_slice(input);
+        let block = self.overlap_save.as_ref().map_or(1, |os| os.block);
+        let whole = pending.inputs.len() / block * block;
+        if whole > 0 {
+            let ready: Vec<f32> = pending.inputs.drain(..whole).collect();
+            pending.outputs.extend(self.convolve(&ready));
+        }
+        // Fewer than a block of inputs is ever held back, and the latency covers that
+        let output = pending.outputs.drain(..input.len()).collect();
+        self.pending = Some(pending);
+        output
+    }
+
+    /// Direct convolution of `input` with the kernel, through FFTs for every whole block.
+    fn convolve(&mut self, input: &[f32]) -> Vec<f32> {
+        let delay_line = self.history.len();
+        let mut extended = Vec::from(std::mem::take(&mut self.history));
+        extended.extend_from_slice(input);
+
+        let mut output = Vec::with_capacity(input.len());
+        if let Some(os) = &mut self.overlap_save {
+            let size = os.kernel.len();
+            let mut buffer = vec![Complex::new(0.0, 0.0); size];
+            while input.len() - output.len() >= os.block {
+                let start = output.len();
+                for (slot, &x) in buffer.iter_mut().zip(&extended[start..start + size]) {
+                    *slot = Complex::new(x, 0.0);
+                }
+                os.forward.process(&mut buffer);
+                buffer.iter_mut().zip(&os.kernel).for_each(|(x, h)| *x *= h);
+                os.inverse.process(&mut buffer);
+                // The first taps - 1 outputs wrap around and are discarded
+                output.extend(buffer[delay_line..].iter().map(|y| y.re / size as f32));
+                os.runs += 1;
+            }
+        }
+        // Whatever is left is shorter than a block; convolve directly
+        for i in output.len()..input.len() {
+            let newest = i + delay_line;
+            output.push(self.taps.iter().enumerate().map(|(k, h)| h * extended[newest - k]).sum());
+        }
+
+        self.history = extended.split_off(extended.len() - delay_line).into();
+        output
+    }
+
+    pub fn reset(&mut self) {
+        self.history.iter_mut().for_each(|x| *x = 0.0);
+        if let Some(pending) = &mut self.pending {
+            *pending = Pending::new(pending.latency);
+        }
+    }
+}
+
+/// Zero-phase filtering of a recorded buffer: `filter` is run forwards, then backwards over
+/// the result, cancelling its phase and squaring its magnitude response. `padding` samples
+/// of odd reflection at both ends keep the start-up transients out of the data.
+///
+/// `filter` is called twice and must start from a clean state each time.
+pub fn forward_backward(data: &[f32], padding: usize, mut filter: impl FnMut(&[f32]) -> Vec<f32>) -> Vec<f32> {
+    if data.is_empty() {
+        return Vec::new();
+    }
+    let pad = padding.min(data.len() - 1);
+    let (first, last) = (data[0], data[data.len() - 1]);
+    let mut extended: Vec<f32> = data[1..=pad].iter().rev().map(|&x| 2.0 * first - x).collect();
+    extended.extend_from_slice(data);
+    extended.extend(data[data.len() - 1 - pad..data.len() - 1].iter().rev().map(|&x| 2.0 * last - x));
+
+    let mut forward = filter(&extended);
+    forward.reverse();
+    let mut backward = filter(&forward);
+    backward.reverse();
+    backward[pad..pad + data.len()].to_vec()
+}
+
+/// Zero-phase FIR filtering of a recorded buffer; see [`forward_backward`].
+pub fn filtfilt(taps: &[f32], data: &[f32]) -> Vec<f32> {
+    forward_backward(data, 3 * taps.len(), |x| FirFilter::new(taps.to_vec()).process_block(x))
+}
@@ -0,0 +1,178 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use rustfft::{num_complex::Complex, FftPlanner};
+use serde::{Serialize, Deserialize};
+
+use super::error::DspError;
+use super::fir::{FirFilter, FirWindow};
+
+/// Instantaneous amplitude and phase of a signal, sample for sample.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Analytic {
+    /// Envelope, in the unit of the signal
+    pub amplitude: Vec<f32>,
+    /// Phase in radians, -π to π, with 0 at the peaks of a cosine
+    pub phase: Vec<f32>,
+}
+
+impl Analytic {
+    fn from_complex(values: &[Complex<f32>]) -> Self {
+        Self {
+            amplitude: values.iter().map(|z| z.norm()).collect(),
+            phase: values.iter().map(|z| z.arg()).collect(),
+        }
+    }
+}
+
+/// Analytic signal of a recorded buffer: the FFT is zeroed at negative frequencies and
+/// doubled at positive ones, so the real part is `data` and the imaginary part its
+/// Hilbert transform. The buffer is treated as periodic, so its ends are only exact when
+/// it holds whole periods.
+pub fn analytic_signal(data: &[f32]) -> Vec<Complex<f32>> {
+    let n = data.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    let mut planner = FftPlanner::new();
+    let mut buffer: Vec<Complex<f32>> = data.iter().map(|&x| Complex::new(x, 0.0)).collect();
+    planner.plan_fft_forward(n).process(&mut buffer);
+    for (k, x) in buffer.iter_mut().enumerate() {
+        // DC, and Nyquist for even lengths, stay as they are
+        if k == 0 || (n.is_multiple_of(2) && k == n / 2) {
+            continue;
+        }
+        *x *= if k < n.div_ceil(2) { 2.0 } else { 0.0 };
+    }
+    planner.plan_fft_inverse(n).process(&mut buffer);
+    buffer.iter_mut().for_each(|x| *x /= n as f32);
+    buffer
+}
+
+/// Instantaneous amplitude and phase of a recorded buffer; see [`analytic_signal`].
+pub fn hilbert(data: &[f32]) -> Analytic {
+    Analytic::from_complex(&analytic_signal(data))
+}
+
+/// Streaming Hilbert transformer parameters.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HilbertSettings {
+    /// Lowest frequency whose envelope must be exact. Lower limits need longer filters and
+    /// so add more delay.
+    pub low_hz: f32,
+}
+
+impl Default for HilbertSettings {
+    /// Accurate from 4 Hz, for theta and faster rhythms.
+    fn default() -> Self {
+        Self { low_hz: 4.0 }
+    }
+}
+
+/// Analytic signal of every channel for one block of the stream.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AnalyticFrame {
+    /// Timestamp of the block the samples came in with
+    pub timestamp: u64,
+    /// How far the output lags the input; subtract from `timestamp`
+    pub delay_us: u64,
+    /// One entry per channel, in config order
+    pub channels: Vec<Analytic>,
+    /// Name of each channel, if the transformer was given them
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+/// Streaming analytic signal: an FIR Hilbert transformer gives the imaginary part and a
+/// matching delay line the real part. The transformer is a streaming filter, see
+/// [`FirFilter::streaming`], so the output lags the input by half the filter plus its
+/// latency.
+#[derive(Debug)]
+pub struct StreamingHilbert {
+    sample_rate: f32,
+    settings: HilbertSettings,
+    delay: usize,
+    channels: Vec<(FirFilter, VecDeque<f32>)>,
+    labels: Vec<String>,
+}
+
+impl StreamingHilbert {
+    pub fn new(sample_rate: u32, num_channels: usize, settings: HilbertSettings) -> Result<Self, DspError> {
+        if sample_rate == 0 {
+            return Err(DspError::InvalidSampleRate);
+        }
+        let fs = sample_rate as f32;
+        if settings.low_hz.is_nan() || settings.low_hz <= 0.0 || settings.low_hz >= fs / 4.0 {
+            return Err(DspError::InvalidFilter(format!(
+                "Hilbert low frequency must be between 0 and {} Hz", fs / 4.0
+            )));
+        }
+        let taps = hilbert_transformer(fs, settings.low_hz);
+        let delay = taps.len() / 2 + FirFilter::streaming_latency(taps.len());
+        Ok(Self {
+            sample_rate: fs,
+            settings,
+            delay,
+            channels: (0..num_channels)
+                .map(|_| (FirFilter::streaming(taps.clone()), VecDeque::from(vec![0.0; delay])))
+                .collect(),
+            labels: Vec::new(),
+        })
+    }
+
+    /// Name the channels in the frames, in the order the samples arrive.
+    pub fn with_labels(mut self, labels: Vec<String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    pub fn settings(&self) -> &HilbertSettings {
+        &self.settings
+    }
+
+    /// Samples the output lags the input by.
+    pub fn delay_samples(&self) -> usize {
+        self.delay
+    }
+
+    /// Analytic signal of a run of consecutive samples of one channel, delayed by
+    /// [`StreamingHilbert::delay_samples`].
+    pub fn process_block(&mut self, channel: usize, samples: &[f32]) -> Analytic {
+        let (transformer, delay_line) = &mut self.channels[channel];
+        let imaginary = transformer.process_block(samples);
+        let values: Vec<Complex<f32>> = samples.iter().zip(imaginary)
+            .map(|(&x, im)| {
+                delay_line.push_back(x);
+                Complex::new(delay_line.pop_front().unwrap_or(0.0), im)
+            })
+            .collect();
+        Analytic::from_complex(&values)
+    }
+
+    /// Process a block of every channel, whose last sample was taken at `timestamp`.
+    pub fn push(&mut self, samples: &[Vec<f32>], timestamp: u64) -> AnalyticFrame {
+        let channels = samples.iter().enumerate()
+            .map(|(channel, block)| self.process_block(channel, block))
+            .collect();
+        AnalyticFrame {
+            timestamp,
+            delay_us: (self.delay as f64 * 1e6 / self.sample_rate as f64).round() as u64,
+            channels,
+            labels: self.labels.clone(),
+        }
+    }
+}
+
+/// Windowed ideal Hilbert transformer, 2 / (π n) at odd offsets from the centre, long
+/// enough for its passband to start at `low_hz`.
+fn hilbert_transformer(sample_rate: f32, low_hz: f32) -> Vec<f32> {
+    // A Hamming window's transition is about 3.3 / N cycles per sample wide
+    let half = ((3.3 * sample_rate / low_hz / 2.0).ceil() as usize).max(1);
+    let len = 2 * half + 1;
+    FirWindow::Hamming.coefficients(len).iter().enumerate()
+        .map(|(i, w)| {
+            let offset = i as i64 - half as i64;
+            if offset % 2 == 0 { 0.0 } else { (2.0 / (PI * offset as f64) * w) as f32 }
+        })
+        .collect()
+}
@@ -0,0 +1,44 @@
+use rustfft::{num_complex::Complex, FftPlanner};
+
+/// Amplitude of the sinusoidal component of `samples` at `frequency`.
+///
+/// The mean is removed and a Hann window applied before the FFT, and the bin nearest
+/// `frequency` is read. The estimate is exact when the window holds a whole number of
+/// periods; see [`window_length`].
+pub fn tone_amplitude(samples: &[f32], sample_rate: f32, frequency: f32) -> f32 {
+    let n = samples.len();
+    if n < 2 {
+        return 0.0;
+    }
+
+    let mean = samples.iter().sum::<f32>() / n as f32;
+    let window: Vec<f32> = (0..n)
+        .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / n as f32).cos())
+        .collect();
+    let mut buffer: Vec<Complex<f32>> = samples.iter().zip(&window)
+        .map(|(&x, &w)| Complex::new((x - mean) * w, 0.0))
+        .collect();
+    FftPlanner::new().plan_fft_forward(n).process(&mut buffer);
+
+    let bin = ((frequency * n as f32 / sample_rate).round() as usize).min(n / 2);
+    // Undo the window's coherent gain; the factor 2 folds in the negative frequency
+    2.0 * buffer[bin].norm() / window.iter().sum::<f32>()
+}
+
+/// Smallest window of at least `min_samples` that holds a whole number of periods of
+/// `frequency`, so [`tone_amplitude`] reads it from a single bin.
+pub fn window_length(sample_rate: f32, frequency: f32, min_samples: usize) -> usize {
+    let period = sample_rate / frequency;
+    if period.fract().abs() > 1e-3 {
+        return min_samples;
+    }
+    let period = period.round() as usize;
+    min_samples.div_ceil(period).max(1) * period
+}
+
+/// Electrode impedance in ohms from the voltage it develops under a lead-off excitation
+/// current of `current_amps` at `frequency`. Like the usual OpenBCI convention, the
+/// excitation is treated as a sinusoid of that amplitude.
+pub fn impedance_ohms(volts: &[f32], sample_rate: f32, frequency: f32, current_amps: f64) -> f64 {
+    tone_amplitude(volts, sample_rate, frequency) as f64 / current_amps
+}
@@ -1,3 +1,28 @@
+pub mod artifacts;
+pub mod design;
+pub mod error;
 pub mod filters;  // Make the filters module public
-pub use filters::SignalProcessor;
-pub use filters::FrequencyBins;  // Export other types as needed 
\ No newline at end of file
+pub mod fir;
+pub mod hilbert;
+pub mod impedance;
+pub mod psd;
+pub mod reference;
+pub mod spectrogram;
+pub mod spectrum;
+pub mod units;
+pub mod wavelet;
+pub use artifacts::{derive_masks, Artifact, ArtifactDetector, ArtifactMask, ArtifactSettings};
+pub use design::FilterFamily;
+pub use error::DspError;
+pub use filters::{FilterChainSpec, FilterKind, FilterStage, SignalProcessor, StageAdjustment};
+pub use fir::{FirFilter, FirWindow};
+pub use hilbert::{analytic_signal, hilbert, Analytic, AnalyticFrame, HilbertSettings, StreamingHilbert};
+pub use psd::{multitaper, welch, MultitaperSettings, Psd, WelchSettings};
+pub use reference::{LaplacianSite, ReReferencer, Reference};
+pub use spectrogram::{Spectrogram, SpectrogramFrame, SpectrogramScale, SpectrogramSettings};
+pub use spectrum::{BandEdges, BandPower, BandPowerAnalyzer, BandPowerReport, BandPowerSettings, FrequencyBins};
+pub use units::{SignalUnit, UnitConverter};
+pub use wavelet::{cwt, CwtSettings, Scalogram, ScalogramFrame, StreamingCwt};
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,319 @@
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use serde::{Serialize, Deserialize};
+
+use super::error::DspError;
+use super::fir::FirWindow;
+use crate::ProcessedData;
+
+/// Power spectral density on a frequency axis from 0 Hz to Nyquist.
+///
+/// `density` is in the signal unit squared per Hz, so µV²/Hz for microvolt data.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Psd {
+    pub frequencies: Vec<f32>,
+    pub density: Vec<f32>,
+}
+
+impl Psd {
+    /// Spacing of the frequency axis.
+    pub fn resolution_hz(&self) -> f32 {
+        self.frequencies.get(1).copied().unwrap_or(0.0)
+    }
+
+    /// Power between `low_hz` and `high_hz`, both included: the density integrated over
+    /// the band.
+    pub fn band_power(&self, low_hz: f32, high_hz: f32) -> f32 {
+        self.frequencies.iter().zip(&self.density)
+            .filter(|(&f, _)| f >= low_hz && f <= high_hz)
+            .map(|(_, &d)| d)
+            .sum::<f32>() * self.resolution_hz()
+    }
+
+    /// Frequency and density of the highest bin.
+    pub fn peak(&self) -> Option<(f32, f32)> {
+        self.frequencies.iter().copied().zip(self.density.iter().copied())
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
+
+/// Segmenting for Welch's method.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WelchSettings {
+    /// Length of each segment; sets the resolution to 1 / `segment_secs`
+    pub segment_secs: f32,
+    /// Fraction of each segment shared with the next, from 0 up to but excluding 1
+    pub overlap: f32,
+    pub window: FirWindow,
+}
+
+impl Default for WelchSettings {
+    /// Two-second Hann segments overlapping by half.
+    fn default() -> Self {
+        Self { segment_secs: 2.0, overlap: 0.5, window: FirWindow::Hann }
+    }
+}
+
+/// Taper set for the multitaper estimator.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MultitaperSettings {
+    /// Time-half-bandwidth product NW; the spectrum is smoothed over ±NW / T Hz for data
+    /// lasting T seconds
+    pub time_half_bandwidth: f32,
+    /// Number of tapers, `None` for the usual 2NW - 1
+    pub tapers: Option<usize>,
+}
+
+impl Default for MultitaperSettings {
+    fn default() -> Self {
+        Self { time_half_bandwidth: 4.0, tapers: None }
+    }
+}
+
+/// Welch's PSD estimate: the mean of the periodograms of overlapping, windowed and
+/// mean-removed segments of `data`.
+pub fn welch(data: &[f32], sample_rate: f32, settings: &WelchSettings) -> Result<Psd, DspError> {
+    check_sample_rate(sample_rate)?;
+    let segment = (settings.segment_secs * sample_rate).round() as usize;
+    if segment < 2 || !(0.0..1.0).contains(&settings.overlap) {
+        return Err(DspError::InvalidSpectrum(format!(
+            "Welch segments need at least 2 samples and an overlap in 0..1, got {} and {}", segment, settings.overlap
+        )));
+    }
+    if data.len() < segment {
+        return Err(DspError::InvalidSpectrum(format!(
+            "{} samples are fewer than one {} sample segment", data.len(), segment
+        )));
+    }
+
+    let step = ((segment as f32 * (1.0 - settings.overlap)).round() as usize).max(1);
+    let taper: Vec<f32> = settings.window.coefficients(segment).into_iter().map(|w| w as f32).collect();
+    let fft = FftPlanner::new().plan_fft_forward(segment);
+    let starts: Vec<usize> = (0..=data.len() - segment).step_by(step).collect();
+    let mut density = vec![0.0; segment / 2 + 1];
+    for &start in &starts {
+        let periodogram = periodogram(&data[start..start + segment], &taper, sample_rate, fft.as_ref());
+        density.iter_mut().zip(periodogram).for_each(|(d, p)| *d += p / starts.len() as f32);
+    }
+    Ok(Psd { frequencies: frequency_axis(segment, sample_rate), density })
+}
+
+/// Thomson's multitaper PSD estimate over the whole of `data`: the mean of the
+/// periodograms taken with each of the leading discrete prolate spheroidal sequences.
+pub fn multitaper(data: &[f32], sample_rate: f32, settings: &MultitaperSettings) -> Result<Psd, DspError> {
+    check_sample_rate(sample_rate)?;
+    let nw = settings.time_half_bandwidth;
+    let count = settings.tapers.unwrap_or_else(|| ((2.0 * nw).floor() as usize).saturating_sub(1).max(1));
+    if nw.is_nan() || nw < 0.5 || count == 0 || data.len() < 2 || nw >= data.len() as f32 / 2.0 {
+        return Err(DspError::InvalidSpectrum(format!(
+            "Multitaper needs NW from 0.5 to half the {} samples and at least one taper", data.len()
+        )));
+    }
+
+    let fft = FftPlanner::new().plan_fft_forward(data.len());
+    let mut density = vec![0.0; data.len() / 2 + 1];
+    let tapers = dpss(data.len(), nw as f64, count);
+    for taper in &tapers {
+        let taper: Vec<f32> = taper.iter().map(|&w| w as f32).collect();
+        let periodogram = periodogram(data, &taper, sample_rate, fft.as_ref());
+        density.iter_mut().zip(periodogram).for_each(|(d, p)| *d += p / tapers.len() as f32);
+    }
+    Ok(Psd { frequencies: frequency_axis(data.len(), sample_rate), density })
+}
+
+/// [`welch`] of every channel in `channels`.
+pub fn welch_channels(channels: &[Vec<f32>], sample_rate: f32, settings: &WelchSettings) -> Result<Vec<Psd>, DspError> {
+    channels.iter().map(|channel| welch(channel, sample_rate, settings)).collect()
+}
+
+/// [`multitaper`] of every channel in `channels`.
+pub fn multitaper_channels(channels: &[Vec<f32>], sample_rate: f32, settings: &MultitaperSettings) -> Result<Vec<Psd>, DspError> {
+    channels.iter().map(|channel| multitaper(channel, sample_rate, settings)).collect()
+}
+
+/// [`welch`] of every channel across consecutive `ProcessedData` buffers.
+pub fn welch_processed(buffers: &[ProcessedData], sample_rate: f32, settings: &WelchSettings) -> Result<Vec<Psd>, DspError> {
+    welch_channels(&concat_channels(buffers)?, sample_rate, settings)
+}
+
+/// [`multitaper`] of every channel across consecutive `ProcessedData` buffers.
+pub fn multitaper_processed(buffers: &[ProcessedData], sample_rate: f32, settings: &MultitaperSettings) -> Result<Vec<Psd>, DspError> {
+    multitaper_channels(&concat_channels(buffers)?, sample_rate, settings)
+}
+
+/// Each channel's samples across `buffers`, in order.
+pub fn concat_channels(buffers: &[ProcessedData]) -> Result<Vec<Vec<f32>>, DspError> {
+    let mut channels = vec![Vec::new(); buffers.first().map_or(0, |first| first.data.len())];
+    for buffer in buffers {
+        if buffer.data.len() != channels.len() {
+            return Err(DspError::ChannelCountMismatch { expected: channels.len(), found: buffer.data.len() });
+        }
+        channels.iter_mut().zip(&buffer.data).for_each(|(channel, data)| channel.extend_from_slice(data));
+    }
+    Ok(channels)
+}
+
+/// One-sided periodogram of `samples` with their mean removed and `taper` applied, from
+/// 0 Hz to Nyquist, scaled to a density.
+pub(crate) fn periodogram(samples: &[f32], taper: &[f32], sample_rate: f32, fft: &dyn Fft<f32>) -> Vec<f32> {
+    let n = samples.len();
+    let mean = samples.iter().sum::<f32>() / n as f32;
+    let mut buffer: Vec<Complex<f32>> = samples.iter().zip(taper)
+        .map(|(&x, &w)| Complex::new((x - mean) * w, 0.0))
+        .collect();
+    fft.process(&mut buffer);
+
+    let scale = 1.0 / (sample_rate * taper.iter().map(|w| w * w).sum::<f32>());
+    let nyquist_bin = n / 2;
+    buffer[..=nyquist_bin].iter().enumerate()
+        .map(|(k, x)| {
+            // Every bin but DC and Nyquist also holds the negative frequency's power
+            let fold = if k == 0 || (k == nyquist_bin && n.is_multiple_of(2)) { 1.0 } else { 2.0 };
+            fold * x.norm_sqr() * scale
+        })
+        .collect()
+}
+
+fn frequency_axis(n: usize, sample_rate: f32) -> Vec<f32> {
+    (0..=n / 2).map(|k| k as f32 * sample_rate / n as f32).collect()
+}
+
+fn check_sample_rate(sample_rate: f32) -> Result<(), DspError> {
+    if sample_rate.is_nan() || sample_rate <= 0.0 {
+        return Err(DspError::InvalidSampleRate);
+    }
+    Ok(())
+}
+
+/// The `count` leading discrete prolate spheroidal sequences of length `n` and
+/// time-half-bandwidth `nw`, each of unit energy.
+///
+/// They are the eigenvectors of the symmetric tridiagonal matrix that commutes with the
+/// concentration problem (Slepian 1978), found by Sturm bisection for the eigenvalues and
+/// inverse iteration for the vectors.
+pub fn dpss(n: usize, nw: f64, count: usize) -> Vec<Vec<f64>> {
+    let w = nw / n as f64;
+    let diagonal: Vec<f64> = (0..n)
+        .map(|i| ((n as f64 - 1.0 - 2.0 * i as f64) / 2.0).powi(2) * (2.0 * std::f64::consts::PI * w).cos())
+        .collect();
+    let off: Vec<f64> = (1..n).map(|i| i as f64 * (n - i) as f64 / 2.0).collect();
+
+    let mut tapers: Vec<Vec<f64>> = Vec::with_capacity(count);
+    for k in 0..count.min(n) {
+        // Largest first: the k-th largest is the (n - 1 - k)-th smallest
+        let lambda = tridiagonal_eigenvalue(&diagonal, &off, n - 1 - k);
+        let mut v = inverse_iteration(&diagonal, &off, lambda, &tapers);
+        // Conventional signs: symmetric tapers sum positive, antisymmetric ones start positive
+        let reference: f64 = if k.is_multiple_of(2) {
+            v.iter().sum()
+        } else {
+            v.iter().enumerate().map(|(i, x)| (n as f64 - 1.0 - 2.0 * i as f64) * x).sum()
+        };
+        if reference < 0.0 {
+            v.iter_mut().for_each(|x| *x = -*x);
+        }
+        tapers.push(v);
+    }
+    tapers
+}
+
+/// The `index`-th smallest eigenvalue of a symmetric tridiagonal matrix, by bisection on
+/// the Sturm sequence count.
+fn tridiagonal_eigenvalue(diagonal: &[f64], off: &[f64], index: usize) -> f64 {
+    // Gershgorin bounds
+    let radius = |i: usize| off.get(i).map_or(0.0, |e| e.abs()) + if i > 0 { off[i - 1].abs() } else { 0.0 };
+    let mut low = (0..diagonal.len()).map(|i| diagonal[i] - radius(i)).fold(f64::INFINITY, f64::min);
+    let mut high = (0..diagonal.len()).map(|i| diagonal[i] + radius(i)).fold(f64::NEG_INFINITY, f64::max);
+
+    // Number of eigenvalues below x
+    let below = |x: f64| {
+        let mut count = 0;
+        let mut q = 1.0;
+        for i in 0..diagonal.len() {
+            let coupling = if i > 0 { off[i - 1] * off[i - 1] } else { 0.0 };
+            q = diagonal[i] - x - coupling / q;
+            if q == 0.0 {
+                q = f64::EPSILON * (diagonal[i].abs() + 1.0);
+            }
+            if q < 0.0 {
+                count += 1;
+            }
+        }
+        count
+    };
+    for _ in 0..200 {
+        let middle = 0.5 * (low + high);
+        if middle <= low || middle >= high {
+            break;
+        }
+        if below(middle) > index {
+            high = middle;
+        } else {
+            low = middle;
+        }
+    }
+    0.5 * (low + high)
+}
+
+/// Eigenvector of a symmetric tridiagonal matrix for the eigenvalue `lambda`, kept
+/// orthogonal to the vectors already found.
+fn inverse_iteration(diagonal: &[f64], off: &[f64], lambda: f64, found: &[Vec<f64>]) -> Vec<f64> {
+    let n = diagonal.len();
+    // Nudge off the exact eigenvalue so the shifted matrix stays invertible
+    let shift = lambda + 1e-10 * (lambda.abs() + 1.0);
+    let mut v: Vec<f64> = (0..n).map(|i| 1.0 + (i as f64 * 0.618).fract()).collect();
+    for _ in 0..4 {
+        for u in found {
+            let dot: f64 = u.iter().zip(&v).map(|(a, b)| a * b).sum();
+            v.iter_mut().zip(u).for_each(|(x, a)| *x -= dot * a);
+        }
+        v = solve_tridiagonal(diagonal, off, shift, &v);
+        let norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+    v
+}
+
+/// Solve (T - shift I) x = b with Gaussian elimination and partial pivoting, which the
+/// near-singular shifted matrix needs.
+fn solve_tridiagonal(diagonal: &[f64], off: &[f64], shift: f64, b: &[f64]) -> Vec<f64> {
+    let n = diagonal.len();
+    if n == 1 {
+        return vec![b[0] / (diagonal[0] - shift)];
+    }
+    // Row i holds coefficients of x[i], x[i+1], x[i+2]; pivoting can fill in the third
+    let mut rows: Vec<[f64; 3]> = (0..n)
+        .map(|i| [diagonal[i] - shift, off.get(i).copied().unwrap_or(0.0), 0.0])
+        .collect();
+    let mut below: Vec<f64> = off.to_vec();
+    let mut rhs = b.to_vec();
+    for i in 0..n - 1 {
+        // Candidate rows for the pivot: i, and i + 1 whose entry in column i is off[i]
+        if below[i].abs() > rows[i][0].abs() {
+            let next = [below[i], rows[i + 1][0], rows[i + 1][1]];
+            below[i] = rows[i][0];
+            rows[i + 1] = [rows[i][1], rows[i][2], 0.0];
+            rows[i] = next;
+            rhs.swap(i, i + 1);
+        }
+        let pivot = if rows[i][0] == 0.0 { f64::EPSILON } else { rows[i][0] };
+        let factor = below[i] / pivot;
+        rows[i + 1][0] -= factor * rows[i][1];
+        rows[i + 1][1] -= factor * rows[i][2];
+        rhs[i + 1] -= factor * rhs[i];
+    }
+    let mut x = vec![0.0; n];
+    for i in (0..n).rev() {
+        let mut sum = rhs[i];
+        if i + 1 < n {
+            sum -= rows[i][1] * x[i + 1];
+        }
+        if i + 2 < n {
+            sum -= rows[i][2] * x[i + 2];
+        }
+        let pivot = if rows[i][0] == 0.0 { f64::EPSILON } else { rows[i][0] };
+        x[i] = sum / pivot;
+    }
+    x
+}
@@ -0,0 +1,159 @@
+use serde::{Serialize, Deserialize};
+
+use super::error::DspError;
+
+/// A scalp site and the electrodes around it, for the surface Laplacian.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LaplacianSite {
+    pub channel: usize,
+    pub neighbors: Vec<usize>,
+}
+
+/// Spatial re-referencing of the filtered channels.
+///
+/// Channels are numbered by their position in the stream, i.e. in `AdcConfig.channels`
+/// order. Each output channel is centred on one input channel, whose label it keeps;
+/// bipolar derivations are labelled `active-reference`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Reference {
+    /// Channels as recorded against the hardware reference
+    #[default]
+    AsRecorded,
+    /// Subtract the mean of all channels from each
+    CommonAverage,
+    /// Subtract one channel from the others; the reference itself is left out
+    Channel { channel: usize },
+    /// Subtract the mean of the two ear or mastoid electrodes, which are left out
+    LinkedEars { left: usize, right: usize },
+    /// One output per `(active, reference)` pair
+    Bipolar { pairs: Vec<(usize, usize)> },
+    /// One output per site: the site minus the mean of its neighbors
+    Laplacian { sites: Vec<LaplacianSite> },
+}
+
+impl Reference {
+    /// Output channels and their weighted inputs for a stream of `num_channels`.
+    fn rows(&self, num_channels: usize) -> Result<Vec<Derivation>, DspError> {
+        let check = |channel: usize| if channel < num_channels {
+            Ok(channel)
+        } else {
+            Err(DspError::InvalidReference(format!("Channel {} is not among the {} in the stream", channel, num_channels)))
+        };
+        let difference = |channel: usize, reference: &[usize]| -> Result<Derivation, DspError> {
+            let weight = 1.0 / reference.len() as f32;
+            let mut weights = vec![(check(channel)?, 1.0)];
+            for &r in reference {
+                weights.push((check(r)?, -weight));
+            }
+            Ok(Derivation { source: channel, weights })
+        };
+
+        let rows: Vec<Derivation> = match self {
+            Reference::AsRecorded => (0..num_channels).map(|c| difference(c, &[])).collect::<Result<_, _>>()?,
+            Reference::CommonAverage => {
+                if num_channels < 2 {
+                    return Err(DspError::InvalidReference("Common average needs at least 2 channels".to_string()));
+                }
+                let all: Vec<usize> = (0..num_channels).collect();
+                all.iter().map(|&c| difference(c, &all)).collect::<Result<_, _>>()?
+            }
+            Reference::Channel { channel } => {
+                check(*channel)?;
+                (0..num_channels).filter(|c| c != channel).map(|c| difference(c, &[*channel])).collect::<Result<_, _>>()?
+            }
+            Reference::LinkedEars { left, right } => {
+                if left == right {
+                    return Err(DspError::InvalidReference("Linked ears need two different channels".to_string()));
+                }
+                let ears = [check(*left)?, check(*right)?];
+                (0..num_channels).filter(|c| !ears.contains(c)).map(|c| difference(c, &ears)).collect::<Result<_, _>>()?
+            }
+            Reference::Bipolar { pairs } => pairs.iter()
+                .map(|&(active, reference)| if active == reference {
+                    Err(DspError::InvalidReference(format!("Bipolar pair ({}, {}) has the same channel twice", active, reference)))
+                } else {
+                    difference(active, &[reference])
+                })
+                .collect::<Result<_, _>>()?,
+            Reference::Laplacian { sites } => sites.iter()
+                .map(|site| if site.neighbors.is_empty() || site.neighbors.contains(&site.channel) {
+                    Err(DspError::InvalidReference(format!("Laplacian site {} needs neighbors other than itself", site.channel)))
+                } else {
+                    difference(site.channel, &site.neighbors)
+                })
+                .collect::<Result<_, _>>()?,
+        };
+        if rows.is_empty() && *self != Reference::AsRecorded {
+            return Err(DspError::InvalidReference(format!("{:?} leaves no channels", self)));
+        }
+        Ok(rows)
+    }
+
+    /// Check that the reference can be applied to a stream of `num_channels`.
+    pub fn validate(&self, num_channels: usize) -> Result<(), DspError> {
+        self.rows(num_channels).map(|_| ())
+    }
+}
+
+/// One output channel: the input it is centred on and the weight of every input in it.
+#[derive(Clone, Debug, PartialEq)]
+struct Derivation {
+    source: usize,
+    weights: Vec<(usize, f32)>,
+}
+
+/// Applies a [`Reference`] to whole multichannel blocks.
+#[derive(Clone, Debug)]
+pub struct ReReferencer {
+    reference: Reference,
+    rows: Vec<Derivation>,
+}
+
+impl ReReferencer {
+    pub fn new(reference: Reference, num_channels: usize) -> Result<Self, DspError> {
+        let rows = reference.rows(num_channels)?;
+        Ok(Self { reference, rows })
+    }
+
+    pub fn reference(&self) -> &Reference {
+        &self.reference
+    }
+
+    pub fn output_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Labels of the output channels, given those of the input.
+    pub fn labels(&self, input: &[String]) -> Vec<String> {
+        self.rows.iter()
+            .map(|row| match self.reference {
+                Reference::Bipolar { .. } => format!("{}-{}", input[row.source], input[row.weights[1].0]),
+                _ => input[row.source].clone(),
+            })
+            .collect()
+    }
+
+    /// Input channel each output is centred on, in output order.
+    pub fn sources(&self) -> Vec<usize> {
+        self.rows.iter().map(|row| row.source).collect()
+    }
+
+    /// Input channels each output is derived from, in output order.
+    pub fn contributors(&self) -> Vec<Vec<usize>> {
+        self.rows.iter().map(|row| row.weights.iter().map(|&(channel, _)| channel).collect()).collect()
+    }
+
+    /// Re-reference a block holding the same run of samples of every input channel.
+    pub fn apply(&self, channels: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        if self.reference == Reference::AsRecorded {
+            return channels.to_vec();
+        }
+        let len = channels.iter().map(Vec::len).min().unwrap_or(0);
+        self.rows.iter()
+            .map(|row| (0..len)
+                .map(|i| row.weights.iter().map(|&(channel, weight)| weight * channels[channel][i]).sum())
+                .collect())
+            .collect()
+    }
+}
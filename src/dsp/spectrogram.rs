@@ -0,0 +1,159 @@
+use std::sync::Arc;
+use rustfft::{Fft, FftPlanner};
+use serde::{Serialize, Deserialize};
+
+use super::error::DspError;
+use super::fir::FirWindow;
+use super::psd::periodogram;
+use super::spectrum::SlidingWindows;
+
+/// Floor applied before taking logarithms, so silent bins stay finite.
+const MIN_DENSITY: f32 = 1e-12;
+
+/// How spectrogram bins are scaled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpectrogramScale {
+    /// Power spectral density, µV²/Hz for processed data
+    Linear,
+    /// 10 log10 of the density, in dB relative to 1 µV²/Hz
+    #[default]
+    Decibels,
+}
+
+/// Short-time Fourier transform parameters.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpectrogramSettings {
+    /// Length of each FFT window
+    pub window_secs: f32,
+    /// Time between frames
+    pub hop_secs: f32,
+    pub window: FirWindow,
+    pub scale: SpectrogramScale,
+    /// Bins above this are left out of the frames, `None` to keep everything up to Nyquist
+    pub max_frequency_hz: Option<f32>,
+}
+
+impl Default for SpectrogramSettings {
+    /// One-second Hann windows every 100 ms in decibels, up to 60 Hz.
+    fn default() -> Self {
+        Self {
+            window_secs: 1.0,
+            hop_secs: 0.1,
+            window: FirWindow::Hann,
+            scale: SpectrogramScale::default(),
+            max_frequency_hz: Some(60.0),
+        }
+    }
+}
+
+/// One column of a spectrogram, covering every channel over the same window.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpectrogramFrame {
+    /// Frame number, counting up by one per hop since the stream started
+    pub index: u64,
+    /// Timestamp of the window's last sample
+    pub timestamp: u64,
+    /// Spacing of the bins, the first of which is at 0 Hz
+    pub resolution_hz: f32,
+    pub scale: SpectrogramScale,
+    /// Bins of each channel, in config order
+    pub channels: Vec<Vec<f32>>,
+    /// Name of each channel, if the spectrogram was given them
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+/// Streaming STFT: turns blocks of processed samples into [`SpectrogramFrame`]s one hop
+/// apart, however the blocks are split.
+pub struct Spectrogram {
+    sample_rate: f32,
+    settings: SpectrogramSettings,
+    taper: Vec<f32>,
+    fft: Arc<dyn Fft<f32>>,
+    bins: usize,
+    windows: SlidingWindows,
+    frames: u64,
+    labels: Vec<String>,
+}
+
+impl std::fmt::Debug for Spectrogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Spectrogram")
+            .field("settings", &self.settings)
+            .field("frames", &self.frames)
+            .finish()
+    }
+}
+
+impl Spectrogram {
+    pub fn new(sample_rate: u32, num_channels: usize, settings: SpectrogramSettings) -> Result<Self, DspError> {
+        let windows = SlidingWindows::new(sample_rate, settings.window_secs, settings.hop_secs, num_channels)?;
+        let resolution = sample_rate as f32 / windows.window as f32;
+        let nyquist_bin = windows.window / 2;
+        let bins = match settings.max_frequency_hz {
+            Some(max) if max.is_nan() || max <= 0.0 => {
+                return Err(DspError::InvalidSpectrum("Spectrogram maximum frequency must be positive".to_string()));
+            }
+            Some(max) => ((max / resolution).floor() as usize).min(nyquist_bin) + 1,
+            None => nyquist_bin + 1,
+        };
+        Ok(Self {
+            sample_rate: sample_rate as f32,
+            taper: settings.window.coefficients(windows.window).into_iter().map(|w| w as f32).collect(),
+            fft: FftPlanner::new().plan_fft_forward(windows.window),
+            bins,
+            windows,
+            frames: 0,
+            labels: Vec::new(),
+            settings,
+        })
+    }
+
+    /// Name the channels in the frames, in the order the samples arrive.
+    pub fn with_labels(mut self, labels: Vec<String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    pub fn settings(&self) -> &SpectrogramSettings {
+        &self.settings
+    }
+
+    /// Add a block of samples per channel, all of the same length, whose last sample was
+    /// taken at `timestamp`. Returns a frame for every hop the block completes.
+    pub fn push(&mut self, samples: &[Vec<f32>], timestamp: u64) -> Vec<SpectrogramFrame> {
+        let resolution = self.sample_rate / self.windows.window as f32;
+        let sample_period_us = 1e6 / self.sample_rate as f64;
+        self.windows.push(samples).into_iter()
+            .map(|frame| {
+                let channels = frame.samples.iter()
+                    .map(|window| {
+                        let mut psd = periodogram(window, &self.taper, self.sample_rate, self.fft.as_ref());
+                        psd.truncate(self.bins);
+                        if self.settings.scale == SpectrogramScale::Decibels {
+                            psd.iter_mut().for_each(|p| *p = 10.0 * p.max(MIN_DENSITY).log10());
+                        }
+                        psd
+                    })
+                    .collect();
+                let index = self.frames;
+                self.frames += 1;
+                SpectrogramFrame {
+                    index,
+                    timestamp: timestamp.saturating_sub((frame.remaining as f64 * sample_period_us).round() as u64),
+                    resolution_hz: resolution,
+                    scale: self.settings.scale,
+                    channels,
+                    labels: self.labels.clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// Drop the buffered samples; frame numbering continues.
+    pub fn clear(&mut self) {
+        self.windows.clear();
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use rustfft::{Fft, FftPlanner};
+use serde::{Serialize, Deserialize};
+
+use super::error::DspError;
+use super::fir::FirWindow;
+use super::psd::periodogram;
+
+/// Half-width of the band around 50 and 60 Hz counted as line noise.
+const LINE_NOISE_HALF_WIDTH_HZ: f32 = 1.0;
+
+/// Edges of the classic EEG bands in Hz, each `(low, high)` with the low edge included.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BandEdges {
+    pub delta: (f32, f32),
+    pub theta: (f32, f32),
+    pub alpha: (f32, f32),
+    pub beta: (f32, f32),
+    pub gamma: (f32, f32),
+}
+
+impl Default for BandEdges {
+    fn default() -> Self {
+        Self {
+            delta: (0.5, 4.0),
+            theta: (4.0, 8.0),
+            alpha: (8.0, 13.0),
+            beta: (13.0, 30.0),
+            gamma: (30.0, 150.0),
+        }
+    }
+}
+
+impl BandEdges {
+    fn all(&self) -> [(f32, f32); 5] {
+        [self.delta, self.theta, self.alpha, self.beta, self.gamma]
+    }
+}
+
+/// How band power is estimated from the processed stream.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BandPowerSettings {
+    /// Length of the sliding window each estimate is computed over
+    pub window_secs: f32,
+    /// Time between estimates
+    pub hop_secs: f32,
+    /// Taper applied to the window before the FFT
+    pub taper: FirWindow,
+    pub bands: BandEdges,
+}
+
+impl Default for BandPowerSettings {
+    /// Two-second Hann windows every half second, for 0.5 Hz resolution.
+    fn default() -> Self {
+        Self {
+            window_secs: 2.0,
+            hop_secs: 0.5,
+            taper: FirWindow::Hann,
+            bands: BandEdges::default(),
+        }
+    }
+}
+
+/// Power in each band.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BandPower {
+    pub delta: f32,
+    pub theta: f32,
+    pub alpha: f32,
+    pub beta: f32,
+    pub gamma: f32,
+}
+
+impl BandPower {
+    fn from_array([delta, theta, alpha, beta, gamma]: [f32; 5]) -> Self {
+        Self { delta, theta, alpha, beta, gamma }
+    }
+}
+
+/// Spectrum of one channel over one window, split into the EEG bands.
+///
+/// The band vectors hold the power spectral density of each FFT bin in the band, in the
+/// signal unit squared per Hz (µV²/Hz for processed data), starting at the first bin at or
+/// above the band's low edge. Bands above Nyquist are empty.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FrequencyBins {
+    pub channel: usize,
+    /// Name of the channel, if the analyzer was given one
+    #[serde(default)]
+    pub label: String,
+    /// Spacing of the bins
+    pub resolution_hz: f32,
+    pub delta: Vec<f32>,
+    pub theta: Vec<f32>,
+    pub alpha: Vec<f32>,
+    pub beta: Vec<f32>,
+    pub gamma: Vec<f32>,
+    /// Power within 1 Hz of 50 Hz, in µV²
+    pub line_noise_50hz: f32,
+    /// Power within 1 Hz of 60 Hz, in µV²
+    pub line_noise_60hz: f32,
+    /// Power in each band, in µV²
+    pub absolute: BandPower,
+    /// Each band's share of the power across all five bands
+    pub relative: BandPower,
+}
+
+/// Band power of every channel, estimated over the same window.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BandPowerReport {
+    /// Timestamp of the batch that completed the window
+    pub timestamp: u64,
+    /// One entry per channel, in config order
+    pub channels: Vec<FrequencyBins>,
+}
+
+impl BandPowerReport {
+    /// Bins of `channel`, if it is part of the report.
+    pub fn channel(&self, channel: usize) -> Option<&FrequencyBins> {
+        self.channels.iter().find(|bins| bins.channel == channel)
+    }
+}
+
+/// Streaming band-power estimator: keeps a sliding window per channel and produces a
+/// [`BandPowerReport`] every hop once the window has filled.
+pub struct BandPowerAnalyzer {
+    sample_rate: f32,
+    settings: BandPowerSettings,
+    channels: Vec<usize>,
+    labels: Vec<String>,
+    taper: Vec<f32>,
+    fft: Arc<dyn Fft<f32>>,
+    windows: SlidingWindows,
+}
+
+impl std::fmt::Debug for BandPowerAnalyzer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BandPowerAnalyzer")
+            .field("settings", &self.settings)
+            .field("channels", &self.channels)
+            .finish()
+    }
+}
+
+impl BandPowerAnalyzer {
+    /// Analyzer for a stream at `sample_rate` carrying `channels`, in the order the
+    /// samples arrive.
+    pub fn new(sample_rate: u32, channels: &[usize], settings: BandPowerSettings) -> Result<Self, DspError> {
+        let windows = SlidingWindows::new(sample_rate, settings.window_secs, settings.hop_secs, channels.len())?;
+        if settings.bands.all().iter().any(|&(low, high)| low.is_nan() || low < 0.0 || low >= high) {
+            return Err(DspError::InvalidSpectrum(format!("Band edges must be increasing and not negative: {:?}", settings.bands)));
+        }
+
+        let taper: Vec<f32> = settings.taper.coefficients(windows.window).into_iter().map(|w| w as f32).collect();
+        Ok(Self {
+            sample_rate: sample_rate as f32,
+            channels: channels.to_vec(),
+            labels: Vec::new(),
+            taper,
+            fft: FftPlanner::new().plan_fft_forward(windows.window),
+            windows,
+            settings,
+        })
+    }
+
+    /// Name the channels in the reports, in the order the samples arrive.
+    pub fn with_labels(mut self, labels: Vec<String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    pub fn settings(&self) -> &BandPowerSettings {
+        &self.settings
+    }
+
+    /// Add a block of samples per channel, all of the same length, returning a report for
+    /// every hop it completes.
+    pub fn push(&mut self, samples: &[Vec<f32>], timestamp: u64) -> Vec<BandPowerReport> {
+        self.windows.push(samples).into_iter()
+            .map(|frame| BandPowerReport {
+                timestamp,
+                channels: frame.samples.iter().zip(&self.channels).enumerate()
+                    .map(|(i, (window, &channel))| self.bins(channel, self.labels.get(i).cloned().unwrap_or_default(), window))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Drop the buffered samples, e.g. after a gap in the stream.
+    pub fn clear(&mut self) {
+        self.windows.clear();
+    }
+
+    fn bins(&self, channel: usize, label: String, samples: &[f32]) -> FrequencyBins {
+        let psd = periodogram(samples, &self.taper, self.sample_rate, self.fft.as_ref());
+        let resolution = self.sample_rate / self.windows.window as f32;
+        let bin_range = |low: f32, high: f32| {
+            let first = (low / resolution).ceil() as usize;
+            let end = ((high / resolution).ceil() as usize).min(psd.len());
+            first.min(end)..end
+        };
+        let power = |low: f32, high: f32| psd[bin_range(low, high)].iter().sum::<f32>() * resolution;
+        // Half a bin past the top so both edges are included
+        let line_noise = |center: f32| power(center - LINE_NOISE_HALF_WIDTH_HZ, center + LINE_NOISE_HALF_WIDTH_HZ + resolution / 2.0);
+
+        let bands = self.settings.bands.all();
+        let absolute = bands.map(|(low, high)| power(low, high));
+        let total: f32 = absolute.iter().sum();
+        let relative = absolute.map(|p| if total > 0.0 { p / total } else { 0.0 });
+        let [delta, theta, alpha, beta, gamma] = bands.map(|(low, high)| psd[bin_range(low, high)].to_vec());
+
+        FrequencyBins {
+            channel,
+            label,
+            resolution_hz: resolution,
+            delta,
+            theta,
+            alpha,
+            beta,
+            gamma,
+            line_noise_50hz: line_noise(50.0),
+            line_noise_60hz: line_noise(60.0),
+            absolute: BandPower::from_array(absolute),
+            relative: BandPower::from_array(relative),
+        }
+    }
+}
+
+/// The contents of every channel's window when a hop completes.
+pub(crate) struct WindowFrame {
+    /// One window per channel, oldest sample first
+    pub samples: Vec<Vec<f32>>,
+    /// How many samples of the pushed block came after the window's last sample
+    pub remaining: usize,
+}
+
+/// Equal sliding windows over every channel of a stream, advanced one hop at a time
+/// regardless of how the stream is split into blocks.
+pub(crate) struct SlidingWindows {
+    pub window: usize,
+    hop: usize,
+    buffers: Vec<VecDeque<f32>>,
+    since_hop: usize,
+}
+
+impl SlidingWindows {
+    pub(crate) fn new(sample_rate: u32, window_secs: f32, hop_secs: f32, channels: usize) -> Result<Self, DspError> {
+        if sample_rate == 0 {
+            return Err(DspError::InvalidSampleRate);
+        }
+        let window = (window_secs * sample_rate as f32).round() as usize;
+        let hop = (hop_secs * sample_rate as f32).round() as usize;
+        if window < 2 || hop == 0 || window_secs.is_nan() || hop_secs.is_nan() {
+            return Err(DspError::InvalidSpectrum(format!(
+                "Window must hold at least 2 samples and the hop at least 1 at {} Hz", sample_rate
+            )));
+        }
+        Ok(Self { window, hop, buffers: vec![VecDeque::with_capacity(window); channels], since_hop: 0 })
+    }
+
+    /// Add a block of samples per channel, all of the same length, returning the windows
+    /// at every hop it completes. The first frame comes once the window has filled.
+    pub(crate) fn push(&mut self, samples: &[Vec<f32>]) -> Vec<WindowFrame> {
+        let len = samples.iter().map(Vec::len).min().unwrap_or(0);
+        let mut frames = Vec::new();
+        for i in 0..len {
+            for (buffer, channel) in self.buffers.iter_mut().zip(samples) {
+                if buffer.len() == self.window {
+                    buffer.pop_front();
+                }
+                buffer.push_back(channel[i]);
+            }
+            self.since_hop += 1;
+            if self.buffers.first().is_some_and(|b| b.len() == self.window) && self.since_hop >= self.hop {
+                self.since_hop = 0;
+                frames.push(WindowFrame {
+                    samples: self.buffers.iter().map(|b| b.iter().copied().collect()).collect(),
+                    remaining: len - 1 - i,
+                });
+            }
+        }
+        frames
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.buffers.iter_mut().for_each(VecDeque::clear);
+        self.since_hop = 0;
+    }
+}
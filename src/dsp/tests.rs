@@ -0,0 +1,738 @@
+use super::*;
+use crate::board_driver::{AdcConfig, ChannelCalibration, ChannelSettings};
+use super::artifacts::{derive_masks, Artifact, ArtifactDetector, ArtifactMask, ArtifactSettings};
+use super::filters::{FilterKind, StageAdjustment};
+use super::fir::{self, FirFilter, FirWindow};
+use super::hilbert::{self, HilbertSettings, StreamingHilbert};
+use super::psd::{self, MultitaperSettings, WelchSettings};
+use super::reference::{LaplacianSite, ReReferencer, Reference};
+use super::spectrogram::{Spectrogram, SpectrogramScale, SpectrogramSettings};
+use super::spectrum::{BandPowerAnalyzer, BandPowerSettings};
+use super::wavelet::{self, CwtSettings, StreamingCwt};
+
+#[test]
+fn test_unit_converter_applies_vref_gain_and_calibration() {
+    let config = AdcConfig {
+        channels: vec![0, 1, 2],
+        channel_settings: vec![
+            ChannelSettings::with_gain(24.0),
+            ChannelSettings::with_gain(1.0),
+            ChannelSettings::with_gain(24.0),
+        ],
+        calibration: vec![
+            ChannelCalibration::default(),
+            ChannelCalibration::default(),
+            ChannelCalibration { offset_uv: 10.0, gain_correction: 1.1 },
+        ],
+        ..AdcConfig::default()
+    };
+    let full_scale = 8_388_608.0;
+
+    let uv = UnitConverter::new(&config, SignalUnit::Microvolts);
+    assert_eq!(uv.unit(), SignalUnit::Microvolts);
+    // Full scale is VREF / gain
+    assert!((uv.convert(0, full_scale) - 4.5e6 / 24.0).abs() < 0.5);
+    assert!((uv.convert(1, -full_scale) + 4.5e6).abs() < 0.5);
+    assert!((uv.convert(0, 1.0) - 0.02235).abs() < 1e-4);
+    // (22.35 uV - 10 uV) * 1.1
+    assert!((uv.convert(2, 1000.0) - 13.585).abs() < 1e-2);
+
+    let volts = UnitConverter::new(&config, SignalUnit::Volts);
+    assert!((volts.convert(1, full_scale / 2.0) - 2.25).abs() < 1e-6);
+    let counts = UnitConverter::new(&config, SignalUnit::Counts);
+    assert_eq!(counts.convert(2, 1234.0), 1234.0);
+
+    let mismatched = AdcConfig { calibration: vec![ChannelCalibration::default()], ..config };
+    assert!(mismatched.validate_channel_settings().is_err());
+}
+
+/// Steady-state gain of `processor` on channel 0 for a sine at `frequency`.
+fn gain_at(processor: &mut SignalProcessor, sample_rate: f32, frequency: f32) -> f32 {
+    let n = (sample_rate * 4.0) as usize;
+    let output: Vec<f32> = (0..n)
+        .map(|i| (2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate).sin())
+        .map(|x| processor.process_sample(0, x))
+        .collect();
+    // Skip the first half while the filters settle; the rest holds whole periods
+    let settled = &output[n / 2..];
+    (2.0 * settled.iter().map(|x| x * x).sum::<f32>() / settled.len() as f32).sqrt()
+}
+
+#[test]
+fn test_filter_chain_spec_controls_stages() -> Result<(), DspError> {
+    let mut both = SignalProcessor::with_chain(250, 1, FilterChainSpec::default())?;
+    let mut fifty = SignalProcessor::with_chain(250, 1, FilterChainSpec::with_mains(50.0))?;
+    assert!(gain_at(&mut both, 250.0, 50.0) < 0.05);
+    assert!(gain_at(&mut both, 250.0, 60.0) < 0.05);
+    assert!(gain_at(&mut fifty, 250.0, 50.0) < 0.05);
+    assert!(gain_at(&mut fifty, 250.0, 60.0) > 0.9);
+
+    // A disabled stage is skipped
+    let disabled = FilterChainSpec::new(vec![FilterStage::notch(50.0).with_enabled(false)]);
+    let mut processor = SignalProcessor::with_chain(250, 1, disabled)?;
+    assert!((gain_at(&mut processor, 250.0, 50.0) - 1.0).abs() < 1e-3);
+
+    // Higher orders roll off faster; cutoffs keep the -3 dB point
+    let mut second = SignalProcessor::with_chain(250, 1, FilterChainSpec::new(vec![FilterStage::lowpass(20.0)]))?;
+    let mut fifth = SignalProcessor::with_chain(250, 1, FilterChainSpec::new(vec![FilterStage::lowpass(20.0).with_order(5)]))?;
+    assert!((gain_at(&mut second, 250.0, 20.0) - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.02);
+    assert!((gain_at(&mut fifth, 250.0, 20.0) - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.02);
+    assert!(gain_at(&mut fifth, 250.0, 40.0) < gain_at(&mut second, 250.0, 40.0) / 4.0);
+    let mut highpass = SignalProcessor::with_chain(250, 1, FilterChainSpec::new(vec![FilterStage::highpass(5.0).with_order(3)]))?;
+    assert!(gain_at(&mut highpass, 250.0, 1.0) < 0.01);
+    assert!(gain_at(&mut highpass, 250.0, 30.0) > 0.99);
+
+    for invalid in [
+        FilterStage::lowpass(0.0),
+        FilterStage::notch(50.0).with_q(0.0),
+        FilterStage::notch(50.0).with_order(3),
+        FilterStage::highpass(1.0).with_order(0),
+    ] {
+        assert!(SignalProcessor::with_chain(250, 1, FilterChainSpec::new(vec![invalid])).is_err());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_filter_chain_swap_keeps_unchanged_stages() -> Result<(), DspError> {
+    // 7 Hz on a DC offset, which the 0.1 Hz high-pass takes seconds to settle on
+    let input: Vec<f32> = (0..500)
+        .map(|i| (2.0 * std::f32::consts::PI * 7.0 * i as f32 / 250.0).sin() + 0.5)
+        .collect();
+    let mut reference = SignalProcessor::with_chain(250, 1, FilterChainSpec::default())?;
+    let mut same = SignalProcessor::with_chain(250, 1, FilterChainSpec::default())?;
+    let mut dropped = SignalProcessor::with_chain(250, 1, FilterChainSpec::default())?;
+    let mut restarted = SignalProcessor::with_chain(250, 1, FilterChainSpec::default())?;
+
+    for (i, &x) in input.iter().enumerate() {
+        if i == 250 {
+            same.set_chain(FilterChainSpec::default())?;
+            // Dropping the 60 Hz notch barely changes a 7 Hz signal
+            dropped.set_chain(FilterChainSpec::with_mains(50.0))?;
+            restarted = SignalProcessor::with_chain(250, 1, FilterChainSpec::with_mains(50.0))?;
+        }
+        let expected = reference.process_sample(0, x);
+        assert_eq!(same.process_sample(0, x), expected);
+        let dropped_out = dropped.process_sample(0, x);
+        let restarted_out = restarted.process_sample(0, x);
+        if i == 250 {
+            assert!((dropped_out - expected).abs() < 0.01, "{} vs {}", dropped_out, expected);
+            // A fresh chain would show the high-pass restart transient instead
+            assert!((restarted_out - expected).abs() > 0.1, "{} vs {}", restarted_out, expected);
+        }
+    }
+    assert_eq!(dropped.chain(), &FilterChainSpec::with_mains(50.0));
+
+    let json = serde_json::to_string(&FilterChainSpec::default()).unwrap();
+    assert!(json.contains(r#""type":"notch","center_hz":50.0"#), "{}", json);
+    let parsed: FilterChainSpec = serde_json::from_str(r#"{"stages": [{"type": "highpass", "cutoff_hz": 0.5, "order": 4}]}"#).unwrap();
+    assert_eq!(parsed.stages, vec![FilterStage::highpass(0.5).with_order(4)]);
+    Ok(())
+}
+
+/// Frequencies across the whole band at 250 Hz, for comparing magnitude responses.
+const PROBE_HZ: [f32; 10] = [0.5, 2.0, 8.0, 10.0, 13.0, 20.0, 30.0, 50.0, 80.0, 120.0];
+
+/// Prewarped analog frequency of `f` at 250 Hz, as the bilinear transform sees it.
+fn warped(f: f32) -> f64 {
+    (std::f64::consts::PI * f as f64 / 250.0).tan()
+}
+
+/// Chebyshev polynomial of the first kind, valid on the whole real line.
+fn chebyshev(n: usize, x: f64) -> f64 {
+    if x.abs() <= 1.0 {
+        (n as f64 * x.acos()).cos()
+    } else {
+        (n as f64 * x.abs().acosh()).cosh() * x.signum().powi(n as i32)
+    }
+}
+
+fn assert_response(sections: &[biquad::Coefficients<f32>], expected: impl Fn(f64) -> f64) {
+    for f in PROBE_HZ {
+        let actual = design::magnitude_response(sections, f, 250.0);
+        let wanted = expected(warped(f));
+        assert!((actual - wanted).abs() < 1e-3, "{} Hz: {} instead of {}", f, actual, wanted);
+    }
+}
+
+#[test]
+fn test_butterworth_design_matches_analytic_response() -> Result<(), DspError> {
+    for order in 1..=8 {
+        let n = order as i32;
+        let low = design::design(FilterFamily::Butterworth, &FilterKind::Lowpass { cutoff_hz: 20.0 }, order, 250.0)?;
+        assert_eq!(low.len(), order.div_ceil(2));
+        assert_response(&low, |w| 1.0 / (1.0 + (w / warped(20.0)).powi(2 * n)).sqrt());
+
+        let high = design::design(FilterFamily::Butterworth, &FilterKind::Highpass { cutoff_hz: 5.0 }, order, 250.0)?;
+        assert_response(&high, |w| 1.0 / (1.0 + (warped(5.0) / w).powi(2 * n)).sqrt());
+    }
+
+    // Band edges at 8 and 13 Hz: x = (w^2 - w0^2) / (w * bw) plays the low-pass role
+    let (w1, w2) = (warped(8.0), warped(13.0));
+    let x = |w: f64| (w * w - w1 * w2) / (w * (w2 - w1));
+    let band = design::design(FilterFamily::Butterworth, &FilterKind::Bandpass { low_hz: 8.0, high_hz: 13.0 }, 4, 250.0)?;
+    assert_eq!(band.len(), 4);
+    assert_response(&band, |w| 1.0 / (1.0 + x(w).powi(8)).sqrt());
+    let stop = design::design(FilterFamily::Butterworth, &FilterKind::Bandstop { low_hz: 8.0, high_hz: 13.0 }, 3, 250.0)?;
+    assert_response(&stop, |w| 1.0 / (1.0 + x(w).powi(-6)).sqrt());
+    Ok(())
+}
+
+#[test]
+fn test_chebyshev_and_bessel_designs_match_analytic_response() -> Result<(), DspError> {
+    // Type I: |H|^2 = 1 / (1 + eps^2 T_n(w / wc)^2), 1 dB ripple up to 30 Hz
+    let eps2 = 10f64.powf(0.1) - 1.0;
+    for order in [4, 5] {
+        let cheby1 = design::design(FilterFamily::ChebyshevI { ripple_db: 1.0 }, &FilterKind::Lowpass { cutoff_hz: 30.0 }, order, 250.0)?;
+        assert_response(&cheby1, |w| 1.0 / (1.0 + eps2 * chebyshev(order, w / warped(30.0)).powi(2)).sqrt());
+    }
+
+    // Type II: |H|^2 = 1 / (1 + 1 / (de^2 T_n(ws / w)^2)), at least 40 dB down from 30 Hz
+    let de2 = 1.0 / (10f64.powf(4.0) - 1.0);
+    for order in [4, 5] {
+        let cheby2 = design::design(FilterFamily::ChebyshevII { stopband_db: 40.0 }, &FilterKind::Lowpass { cutoff_hz: 30.0 }, order, 250.0)?;
+        assert_response(&cheby2, |w| 1.0 / (1.0 + 1.0 / (de2 * chebyshev(order, warped(30.0) / w).powi(2))).sqrt());
+    }
+    let cheby2_high = design::design(FilterFamily::ChebyshevII { stopband_db: 40.0 }, &FilterKind::Highpass { cutoff_hz: 10.0 }, 4, 250.0)?;
+    assert_response(&cheby2_high, |w| 1.0 / (1.0 + 1.0 / (de2 * chebyshev(4, w / warped(10.0)).powi(2))).sqrt());
+
+    // Fourth-order Bessel: 105 / |theta_4(js)| with s scaled by its -3 dB frequency, 2.1139
+    let bessel = design::design(FilterFamily::Bessel, &FilterKind::Lowpass { cutoff_hz: 20.0 }, 4, 250.0)?;
+    assert_response(&bessel, |w| {
+        let s = rustfft::num_complex::Complex64::new(0.0, 2.1139 * w / warped(20.0));
+        105.0 / (s.powu(4) + 10.0 * s.powu(3) + 45.0 * s * s + 105.0 * s + 105.0).norm()
+    });
+
+    assert!(design::design(FilterFamily::ChebyshevI { ripple_db: 0.0 }, &FilterKind::Lowpass { cutoff_hz: 30.0 }, 4, 250.0).is_err());
+    assert!(design::design(FilterFamily::Butterworth, &FilterKind::Bandpass { low_hz: 13.0, high_hz: 8.0 }, 4, 250.0).is_err());
+    assert!(design::design(FilterFamily::Butterworth, &FilterKind::Notch { center_hz: 50.0 }, 2, 250.0).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_designed_stage_runs_in_filter_chain() -> Result<(), DspError> {
+    // An eighth-order alpha band-pass rejects theta far better than a single biquad could
+    let alpha = FilterStage::bandpass(8.0, 13.0).with_family(FilterFamily::ChebyshevI { ripple_db: 0.5 }).with_order(4);
+    let mut processor = SignalProcessor::with_chain(250, 1, FilterChainSpec::new(vec![alpha.clone()]))?;
+    assert!(gain_at(&mut processor, 250.0, 10.0) > 0.9);
+    assert!(gain_at(&mut processor, 250.0, 4.0) < 0.01);
+
+    let json = serde_json::to_string(&alpha).unwrap();
+    assert_eq!(serde_json::from_str::<FilterStage>(&json).unwrap(), alpha);
+    Ok(())
+}
+
+#[test]
+fn test_fir_designs_are_linear_phase_and_meet_spec() -> Result<(), DspError> {
+    let lowpass = FilterKind::Lowpass { cutoff_hz: 30.0 };
+    let taps = fir::windowed_sinc(&lowpass, 101, FirWindow::Hamming, 250.0)?;
+    assert!(taps.iter().zip(taps.iter().rev()).all(|(a, b)| (a - b).abs() < 1e-7), "Kernel is not symmetric");
+    assert!((fir::magnitude_response(&taps, 0.0, 250.0) - 1.0).abs() < 1e-6);
+    assert!((fir::magnitude_response(&taps, 30.0, 250.0) - 0.5).abs() < 0.02);
+    for f in (40..=125).step_by(5) {
+        assert!(fir::magnitude_response(&taps, f as f32, 250.0) < 0.003, "Leak at {} Hz", f);
+    }
+
+    // Equiripple: the error swings equally in both bands (equal weights)
+    let taps = fir::equiripple(61, &[(0.0, 25.0), (35.0, 125.0)], &[1.0, 0.0], &[1.0, 1.0], 250.0)?;
+    let error = |band: std::ops::RangeInclusive<u32>, desired: f64| band
+        .map(|f| (fir::magnitude_response(&taps, f as f32 / 10.0, 250.0) - desired).abs())
+        .fold(0.0, f64::max);
+    let (pass, stop) = (error(0..=250, 1.0), error(350..=1250, 0.0));
+    assert!(stop < 0.01, "Stopband ripple {}", stop);
+    assert!((pass - stop).abs() < 0.1 * stop, "Ripple {} in the passband, {} in the stopband", pass, stop);
+
+    assert!(fir::windowed_sinc(&lowpass, 100, FirWindow::Hamming, 250.0).is_err());
+    assert!(fir::equiripple(61, &[(0.0, 40.0), (35.0, 125.0)], &[1.0, 0.0], &[1.0, 1.0], 250.0).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_fir_overlap_save_matches_direct_convolution() -> Result<(), DspError> {
+    let taps = fir::windowed_sinc(&FilterKind::Bandpass { low_hz: 8.0, high_hz: 13.0 }, 301, FirWindow::Blackman, 250.0)?;
+    let input: Vec<f32> = (0..5000).map(|i| ((i * 7919) % 1000) as f32 / 500.0 - 1.0).collect();
+    let expected: Vec<f32> = (0..input.len())
+        .map(|n| taps.iter().enumerate().filter(|&(k, _)| k <= n).map(|(k, h)| h * input[n - k]).sum())
+        .collect();
+
+    // Odd block sizes straddle the FFT blocks and the direct-form tail
+    let mut filter = FirFilter::new(taps.clone());
+    let mut output = Vec::new();
+    let mut rest = input.as_slice();
+    for size in [1, 17, 1203, 3, 2048].iter().cycle() {
+        if rest.is_empty() {
+            break;
+        }
+        let (block, tail) = rest.split_at((*size).min(rest.len()));
+        output.extend(filter.process_block(block));
+        rest = tail;
+    }
+    assert_eq!(output.len(), expected.len());
+    for (y, e) in output.iter().zip(&expected) {
+        assert!((y - e).abs() < 1e-4, "{} != {}", y, e);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_streaming_fir_runs_ffts_on_short_blocks() -> Result<(), DspError> {
+    let taps = fir::windowed_sinc(&FilterKind::Bandpass { low_hz: 8.0, high_hz: 13.0 }, 301, FirWindow::Blackman, 250.0)?;
+    let input: Vec<f32> = (0..5000).map(|i| ((i * 7919) % 1000) as f32 / 500.0 - 1.0).collect();
+    let mut exact = FirFilter::new(taps.clone());
+    let expected = exact.process_block(&input);
+
+    // Fed 32 samples at a time, as the acquisition does, it matches direct convolution
+    // delayed by its latency, and the convolution runs through FFTs
+    let mut filter = FirFilter::streaming(taps.clone());
+    let latency = filter.latency_samples();
+    assert_eq!(latency, 1024 - 301);
+    assert_eq!(filter.group_delay_samples(), 150.0 + latency as f32);
+    let output: Vec<f32> = input.chunks(32).flat_map(|block| filter.process_block(block)).collect();
+    assert_eq!(output.len(), input.len());
+    assert!(output[..latency].iter().all(|&y| y == 0.0));
+    for (y, e) in output[latency..].iter().zip(&expected) {
+        assert!((y - e).abs() < 1e-4, "{} != {}", y, e);
+    }
+    assert_eq!(filter.fft_runs(), (input.len() / (latency + 1)) as u64);
+
+    // One sample at a time gives the same, and a reset starts over
+    filter.reset();
+    let single: Vec<f32> = input[..1000].iter().map(|&x| filter.process(x)).collect();
+    assert_eq!(&single[..], &output[..1000]);
+
+    // Short kernels are convolved directly without delay
+    let short = FirFilter::streaming(taps[..31].to_vec());
+    assert_eq!(short.latency_samples(), 0);
+    assert_eq!(short.fft_runs(), 0);
+    Ok(())
+}
+
+#[test]
+fn test_fir_stage_reports_group_delay() -> Result<(), DspError> {
+    let fir = FilterStage::lowpass(40.0).with_family(FilterFamily::WindowedSinc { window: FirWindow::Hamming }).with_order(100);
+    let equiripple = FilterStage::highpass(5.0).with_family(FilterFamily::Equiripple { transition_hz: 4.0 }).with_order(200);
+    // 101 taps delay by 50 samples
+    let mut processor = SignalProcessor::with_chain(250, 1, FilterChainSpec::new(vec![fir.clone()]))?;
+    assert!((processor.group_delay_secs() - 0.2).abs() < 1e-6);
+    assert!(gain_at(&mut processor, 250.0, 10.0) > 0.99);
+
+    // IIR stages add nothing to the reported delay; 201 taps add 100 samples
+    processor.set_chain(FilterChainSpec::new(vec![FilterStage::notch(50.0), fir.clone(), equiripple.clone()]))?;
+    assert!((processor.group_delay_secs() - 0.6).abs() < 1e-6);
+
+    // With block FFTs, 101 taps run through 256-point FFTs of 156 outputs, held back by
+    // 155 samples, and 201 taps are held back by 311
+    processor.set_chain(FilterChainSpec::new(vec![fir.clone().with_block_fft(true), equiripple.with_block_fft(true)]))?;
+    assert!((processor.group_delay_secs() - 616.0 / 250.0).abs() < 1e-6);
+
+    // A sine comes out delayed by exactly the group delay, however short the blocks
+    let input: Vec<f32> = (0..1000).map(|i| (2.0 * std::f32::consts::PI * 5.0 * i as f32 / 250.0).sin()).collect();
+    for (stage, delay) in [(fir.clone(), 50), (fir.with_block_fft(true), 205)] {
+        let mut processor = SignalProcessor::with_chain(250, 1, FilterChainSpec::new(vec![stage]))?;
+        let output: Vec<f32> = input.chunks(10).flat_map(|block| processor.process_block(0, block)).collect();
+        for i in 400..1000 {
+            assert!((output[i] - input[i - delay]).abs() < 0.01);
+        }
+    }
+
+    assert!(FilterStage::lowpass(40.0).with_family(FilterFamily::WindowedSinc { window: FirWindow::Hann }).with_order(99)
+        .validate(250).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_filtfilt_is_zero_phase() -> Result<(), DspError> {
+    let input: Vec<f32> = (0..2500).map(|i| (2.0 * std::f32::consts::PI * 10.0 * i as f32 / 250.0).sin()).collect();
+    let chains = [
+        FilterChainSpec::new(vec![
+            FilterStage::lowpass(30.0).with_family(FilterFamily::WindowedSinc { window: FirWindow::Hamming }).with_order(100),
+        ]),
+        FilterChainSpec::new(vec![FilterStage::bandpass(5.0, 20.0).with_order(4), FilterStage::notch(50.0)]),
+    ];
+    for chain in chains {
+        let output = chain.filtfilt(250, &input)?;
+        assert_eq!(output.len(), input.len());
+        // Away from the ends the in-band sine comes out unshifted
+        for (y, x) in output[250..2250].iter().zip(&input[250..2250]) {
+            assert!((y - x).abs() < 0.02, "{:?}: {} != {}", chain, y, x);
+        }
+    }
+    assert!(fir::filtfilt(&[1.0], &[]).is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_filter_chain_fits_below_nyquist() -> Result<(), DspError> {
+    assert_eq!(SignalProcessor::new(0, 1).err(), Some(DspError::InvalidSampleRate));
+
+    // 125 Hz: the 100 Hz low-pass is dropped, both notches still fit
+    let mut processor = SignalProcessor::new(125, 1)?;
+    assert_eq!(processor.adjustments(), &[StageAdjustment {
+        index: 3,
+        requested: FilterKind::Lowpass { cutoff_hz: 100.0 },
+        realized: None,
+    }]);
+    assert!(gain_at(&mut processor, 125.0, 50.0) < 0.05);
+    assert!(gain_at(&mut processor, 125.0, 10.0) > 0.95);
+
+    // 100 Hz: the 50 Hz notch sits on Nyquist and goes too
+    processor.reset(100, 1)?;
+    let dropped: Vec<usize> = processor.adjustments().iter().map(|a| a.index).collect();
+    assert_eq!(dropped, vec![1, 2, 3]);
+    assert_eq!(processor.chain(), &FilterChainSpec::default());
+
+    // Bands reaching past Nyquist keep the edge that remains
+    let spec = FilterChainSpec::new(vec![FilterStage::bandpass(8.0, 80.0), FilterStage::bandstop(40.0, 70.0)]);
+    let (realized, adjustments) = spec.realize(125)?;
+    assert_eq!(realized.stages[0].kind, FilterKind::Highpass { cutoff_hz: 8.0 });
+    assert_eq!(realized.stages[1].kind, FilterKind::Lowpass { cutoff_hz: 40.0 });
+    assert_eq!(adjustments.len(), 2);
+
+    // Edges that are simply invalid are still errors
+    assert!(matches!(
+        FilterChainSpec::new(vec![FilterStage::highpass(-1.0)]).validate(125),
+        Err(DspError::InvalidFilter(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_band_power_of_known_sinusoids() -> Result<(), DspError> {
+    let sample_rate = 250.0;
+    let settings = BandPowerSettings::default();
+    let mut analyzer = BandPowerAnalyzer::new(250, &[3, 7], settings.clone())?;
+
+    // Channel 3: 20 µV at 10 Hz plus 5 µV of 50 Hz mains; channel 7: 10 µV at 2 Hz
+    let tone = |amplitude: f32, frequency: f32, i: usize| amplitude * (2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate).sin();
+    let signal = |range: std::ops::Range<usize>| vec![
+        range.clone().map(|i| tone(20.0, 10.0, i) + tone(5.0, 50.0, i)).collect::<Vec<f32>>(),
+        range.map(|i| tone(10.0, 2.0, i)).collect(),
+    ];
+
+    // Batch boundaries do not matter: the first report comes once the window fills, then one per hop
+    let mut reports = analyzer.push(&signal(0..333), 1);
+    assert!(reports.is_empty());
+    reports.extend(analyzer.push(&signal(333..2500), 2));
+    assert_eq!(reports.len(), 1 + (2500 - 500) / 125);
+
+    let last = reports.last().unwrap();
+    assert_eq!(last.timestamp, 2);
+    let alpha = last.channel(3).unwrap();
+    assert_eq!(alpha.resolution_hz, 0.5);
+    // A sinusoid of amplitude A carries A²/2 of power
+    assert!((alpha.absolute.alpha - 200.0).abs() < 2.0, "alpha power {}", alpha.absolute.alpha);
+    assert!((alpha.relative.alpha + alpha.relative.gamma - 1.0).abs() < 1e-3);
+    assert!((alpha.line_noise_50hz - 12.5).abs() < 0.2, "50 Hz power {}", alpha.line_noise_50hz);
+    assert!(alpha.line_noise_60hz < 0.01);
+    assert_eq!(alpha.alpha.len(), 10);
+    // Band vectors hold the PSD: its integral over the band is the band power
+    assert!((alpha.alpha.iter().sum::<f32>() * alpha.resolution_hz - alpha.absolute.alpha).abs() < 1e-3);
+
+    let delta = last.channel(7).unwrap();
+    assert!((delta.absolute.delta - 50.0).abs() < 0.5);
+    assert!(delta.relative.delta > 0.99);
+    // 150 Hz lies beyond Nyquist, so gamma stops at the 125 Hz bin
+    assert_eq!(delta.gamma.len(), 191);
+
+    let invalid = BandPowerSettings { hop_secs: 0.0, ..settings };
+    assert!(matches!(BandPowerAnalyzer::new(250, &[0], invalid), Err(DspError::InvalidSpectrum(_))));
+    Ok(())
+}
+
+#[test]
+fn test_welch_and_multitaper_recover_sine_power_and_noise_floor() -> Result<(), DspError> {
+    use rand::{rngs::StdRng, SeedableRng};
+    use rand_distr::{Distribution, Normal};
+
+    // 20 µV at 10 Hz in white noise of 1 µV RMS, whose density is 2σ²/fs one-sided
+    let sample_rate = 250.0;
+    let mut rng = StdRng::seed_from_u64(7);
+    let noise = Normal::new(0.0, 1.0).unwrap();
+    let data: Vec<f32> = (0..5000)
+        .map(|i| 20.0 * (2.0 * std::f32::consts::PI * 10.0 * i as f32 / sample_rate).sin() + noise.sample(&mut rng))
+        .collect();
+    let floor = 2.0 / sample_rate;
+
+    let welch = psd::welch(&data, sample_rate, &WelchSettings::default())?;
+    assert_eq!(welch.resolution_hz(), 0.5);
+    assert_eq!(welch.frequencies.len(), 251);
+    assert_eq!(welch.peak().map(|(f, _)| f), Some(10.0));
+    assert!((welch.band_power(8.0, 12.0) - 200.0).abs() < 4.0, "Welch power {}", welch.band_power(8.0, 12.0));
+    let mean_floor = welch.band_power(20.0, 100.0) / 80.0;
+    assert!((mean_floor - floor).abs() < 0.1 * floor, "Welch floor {} vs {}", mean_floor, floor);
+
+    let multitaper = psd::multitaper(&data, sample_rate, &MultitaperSettings::default())?;
+    assert_eq!(multitaper.resolution_hz(), 0.05);
+    assert!((multitaper.band_power(9.0, 11.0) - 200.0).abs() < 4.0, "Multitaper power {}", multitaper.band_power(9.0, 11.0));
+    let mean_floor = multitaper.band_power(20.0, 100.0) / 80.0;
+    assert!((mean_floor - floor).abs() < 0.1 * floor, "Multitaper floor {} vs {}", mean_floor, floor);
+
+    assert!(psd::welch(&data[..100], sample_rate, &WelchSettings::default()).is_err());
+    assert!(psd::welch(&data, sample_rate, &WelchSettings { overlap: 1.0, ..Default::default() }).is_err());
+    assert!(psd::multitaper(&data[..4], sample_rate, &MultitaperSettings::default()).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_dpss_tapers_are_orthonormal_with_alternating_symmetry() {
+    let n = 128;
+    let tapers = psd::dpss(n, 3.0, 5);
+    assert_eq!(tapers.len(), 5);
+    for (k, taper) in tapers.iter().enumerate() {
+        for (j, other) in tapers.iter().enumerate() {
+            let dot: f64 = taper.iter().zip(other).map(|(a, b)| a * b).sum();
+            assert!((dot - if j == k { 1.0 } else { 0.0 }).abs() < 1e-9, "<{}, {}> = {}", k, j, dot);
+        }
+        let parity = if k % 2 == 0 { 1.0 } else { -1.0 };
+        assert!(taper.iter().zip(taper.iter().rev()).all(|(a, b)| (a - parity * b).abs() < 1e-9));
+    }
+    // The first taper is the bell concentrated in the middle
+    let middle = tapers[0][n / 2];
+    assert!(tapers[0].iter().all(|&w| w > 0.0 && w <= middle + 1e-12));
+    assert!(tapers[0][0] < 0.01 * middle);
+}
+
+#[test]
+fn test_spectrogram_frames_are_contiguous_across_batches() -> Result<(), DspError> {
+    let sample_rate = 250;
+    let settings = SpectrogramSettings { window_secs: 1.0, hop_secs: 0.2, scale: SpectrogramScale::Linear, ..Default::default() };
+    // 10 Hz on channel 0, 25 Hz on channel 1; sample i is taken at i * 4 ms
+    let signal = |range: std::ops::Range<usize>| vec![
+        range.clone().map(|i| (2.0 * std::f32::consts::PI * 10.0 * i as f32 / 250.0).sin()).collect::<Vec<f32>>(),
+        range.map(|i| (2.0 * std::f32::consts::PI * 25.0 * i as f32 / 250.0).sin()).collect(),
+    ];
+    let timestamp_of = |i: usize| i as u64 * 4000;
+
+    let mut whole = Spectrogram::new(sample_rate, 2, settings.clone())?;
+    let expected = whole.push(&signal(0..1000), timestamp_of(999));
+    assert_eq!(expected.len(), 1 + (1000 - 250) / 50);
+
+    // Irregular batches, some shorter than a hop and some spanning several
+    let mut batched = Spectrogram::new(sample_rate, 2, settings)?;
+    let mut frames = Vec::new();
+    let mut start = 0;
+    for size in [7, 1, 130, 49, 301, 2, 90, 420] {
+        frames.extend(batched.push(&signal(start..start + size), timestamp_of(start + size - 1)));
+        start += size;
+    }
+    assert_eq!(start, 1000);
+    assert_eq!(frames, expected);
+
+    for (n, frame) in frames.iter().enumerate() {
+        assert_eq!(frame.index, n as u64);
+        // Each frame ends on the last sample of its window
+        assert_eq!(frame.timestamp, timestamp_of(249 + 50 * n));
+        assert_eq!(frame.channels[0].len(), 61);
+        let peak = |bins: &Vec<f32>| bins.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)).map(|(k, _)| k as f32).unwrap();
+        assert_eq!(peak(&frame.channels[0]) * frame.resolution_hz, 10.0);
+        assert_eq!(peak(&frame.channels[1]) * frame.resolution_hz, 25.0);
+    }
+
+    // Decibels are 10 log10 of the linear density
+    let mut decibels = Spectrogram::new(sample_rate, 2, SpectrogramSettings { scale: SpectrogramScale::Decibels, ..batched.settings().clone() })?;
+    let frame = decibels.push(&signal(0..250), timestamp_of(249)).remove(0);
+    let linear = &expected[0].channels[0];
+    assert!((frame.channels[0][10] - 10.0 * linear[10].log10()).abs() < 1e-4);
+    Ok(())
+}
+
+#[test]
+fn test_hilbert_envelope_and_phase_offline_and_streaming() -> Result<(), DspError> {
+    use std::f32::consts::PI;
+    let sample_rate = 250;
+    let t = |i: usize| i as f32 / sample_rate as f32;
+
+    // 20 Hz carrier modulated at 1 Hz, over whole periods of both
+    let envelope: Vec<f32> = (0..1000).map(|i| 1.0 + 0.5 * (2.0 * PI * t(i)).cos()).collect();
+    let am: Vec<f32> = envelope.iter().enumerate().map(|(i, e)| e * (2.0 * PI * 20.0 * t(i)).cos()).collect();
+    let analytic = hilbert::hilbert(&am);
+    for (i, (&amplitude, &expected)) in analytic.amplitude.iter().zip(&envelope).enumerate() {
+        assert!((amplitude - expected).abs() < 1e-3, "sample {}: {} vs {}", i, amplitude, expected);
+    }
+    // A cosine's phase advances 2π f per second, starting at 0
+    for (i, &phase) in analytic.phase.iter().enumerate() {
+        let expected = (2.0 * PI * 20.0 * t(i) + PI).rem_euclid(2.0 * PI) - PI;
+        let error = (phase - expected + PI).rem_euclid(2.0 * PI) - PI;
+        assert!(error.abs() < 1e-3, "sample {}: {} vs {}", i, phase, expected);
+    }
+
+    // Streaming output matches the offline one, late by the filter delay, however the
+    // stream is split
+    let sine: Vec<f32> = (0..2000).map(|i| 3.0 * (2.0 * PI * 12.0 * t(i)).sin()).collect();
+    let offline = hilbert::hilbert(&sine);
+    let mut streaming = StreamingHilbert::new(sample_rate, 1, HilbertSettings::default())?;
+    let delay = streaming.delay_samples();
+    let mut amplitude: Vec<f32> = Vec::new();
+    let mut phase: Vec<f32> = Vec::new();
+    let mut start = 0;
+    for size in [1, 63, 250, 17, 900, 769] {
+        let frame = streaming.push(&[sine[start..start + size].to_vec()], 0);
+        assert_eq!(frame.delay_us, (delay as u64 * 4000));
+        amplitude.extend(&frame.channels[0].amplitude);
+        phase.extend(&frame.channels[0].phase);
+        start += size;
+    }
+    assert_eq!(start, sine.len());
+    for n in 2 * delay..sine.len() {
+        assert!((amplitude[n] - offline.amplitude[n - delay]).abs() < 0.03, "sample {}", n);
+        let error = (phase[n] - offline.phase[n - delay] + PI).rem_euclid(2.0 * PI) - PI;
+        assert!(error.abs() < 0.01, "sample {}", n);
+    }
+
+    assert!(matches!(StreamingHilbert::new(sample_rate, 1, HilbertSettings { low_hz: 100.0 }), Err(DspError::InvalidFilter(_))));
+    Ok(())
+}
+
+#[test]
+fn test_morlet_cwt_picks_out_sine_offline_and_streaming() -> Result<(), DspError> {
+    let sample_rate = 250;
+    let sine: Vec<f32> = (0..2000).map(|i| 20.0 * (2.0 * std::f32::consts::PI * 10.0 * i as f32 / 250.0).sin()).collect();
+    let settings = CwtSettings { frequencies: vec![5.0, 10.0, 20.0], cycles: 7.0 };
+
+    let scalogram = wavelet::cwt(&sine, sample_rate as f32, &settings)?;
+    assert_eq!(scalogram.amplitude.len(), 3);
+    assert!(scalogram.amplitude.iter().all(|row| row.len() == sine.len()));
+    // Away from the ends, only the 10 Hz wavelet responds, with the sine's amplitude
+    for n in 500..1500 {
+        assert!((scalogram.amplitude[1][n] - 20.0).abs() < 0.2, "sample {}: {}", n, scalogram.amplitude[1][n]);
+        assert!(scalogram.amplitude[0][n] < 0.5 && scalogram.amplitude[2][n] < 0.5);
+    }
+
+    let mut streaming = StreamingCwt::new(sample_rate, 1, settings)?;
+    let delay = streaming.delay_samples();
+    let mut amplitude: Vec<Vec<f32>> = vec![Vec::new(); 3];
+    let mut start = 0;
+    for size in [40, 333, 7, 1000, 620] {
+        let frame = streaming.push(&[sine[start..start + size].to_vec()], 0);
+        for (row, block) in amplitude.iter_mut().zip(&frame.amplitude[0]) {
+            row.extend(block);
+        }
+        start += size;
+    }
+    assert_eq!(start, sine.len());
+    for (row, expected) in amplitude.iter().zip(&scalogram.amplitude) {
+        for n in delay..sine.len() {
+            assert!((row[n] - expected[n - delay]).abs() < 0.01, "sample {}", n);
+        }
+    }
+
+    assert!(wavelet::cwt(&sine, 250.0, &CwtSettings { frequencies: vec![125.0], cycles: 7.0 }).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_re_referencing_derivations_and_labels() -> Result<(), DspError> {
+    // Four channels, two samples each
+    let block = vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0, 8.0], vec![7.0, 10.0]];
+    let labels: Vec<String> = ["Fz", "Cz", "A1", "A2"].iter().map(|s| s.to_string()).collect();
+    let derive = |reference: Reference| -> Result<(Vec<Vec<f32>>, Vec<String>), DspError> {
+        let referencer = ReReferencer::new(reference, 4)?;
+        let output = referencer.apply(&block);
+        assert_eq!(output.len(), referencer.output_count());
+        Ok((output, referencer.labels(&labels)))
+    };
+
+    assert_eq!(derive(Reference::AsRecorded)?, (block.clone(), labels.clone()));
+
+    // Means 4 and 6
+    let (output, names) = derive(Reference::CommonAverage)?;
+    assert_eq!(output, vec![vec![-3.0, -4.0], vec![-1.0, -2.0], vec![1.0, 2.0], vec![3.0, 4.0]]);
+    assert_eq!(names, labels);
+
+    let (output, names) = derive(Reference::Channel { channel: 1 })?;
+    assert_eq!(output, vec![vec![-2.0, -2.0], vec![2.0, 4.0], vec![4.0, 6.0]]);
+    assert_eq!(names, ["Fz", "A1", "A2"]);
+
+    // Ears average 6 and 9
+    let (output, names) = derive(Reference::LinkedEars { left: 2, right: 3 })?;
+    assert_eq!(output, vec![vec![-5.0, -7.0], vec![-3.0, -5.0]]);
+    assert_eq!(names, ["Fz", "Cz"]);
+
+    let (output, names) = derive(Reference::Bipolar { pairs: vec![(0, 1), (2, 3), (1, 2)] })?;
+    assert_eq!(output, vec![vec![-2.0, -2.0], vec![-2.0, -2.0], vec![-2.0, -4.0]]);
+    assert_eq!(names, ["Fz-Cz", "A1-A2", "Cz-A1"]);
+
+    let (output, names) = derive(Reference::Laplacian { sites: vec![LaplacianSite { channel: 1, neighbors: vec![0, 2, 3] }] })?;
+    // Neighbors average 13/3 and 20/3
+    assert_eq!(output.len(), 1);
+    assert!((output[0][0] + 4.0 / 3.0).abs() < 1e-5 && (output[0][1] + 8.0 / 3.0).abs() < 1e-5);
+    assert_eq!(names, ["Cz"]);
+
+    for invalid in [
+        Reference::Channel { channel: 4 },
+        Reference::LinkedEars { left: 2, right: 2 },
+        Reference::Bipolar { pairs: vec![(1, 1)] },
+        Reference::Bipolar { pairs: Vec::new() },
+        Reference::Laplacian { sites: vec![LaplacianSite { channel: 0, neighbors: vec![0, 1] }] },
+    ] {
+        assert!(matches!(ReReferencer::new(invalid, 4), Err(DspError::InvalidReference(_))));
+    }
+    assert!(Reference::CommonAverage.validate(1).is_err());
+
+    // The processor filters, then re-references, and keeps the reference across resets
+    let mut processor = SignalProcessor::with_chain(250, 4, FilterChainSpec::passthrough())?;
+    processor.set_reference(Reference::Bipolar { pairs: vec![(3, 0)] })?;
+    assert_eq!(processor.process_channels(&block), vec![vec![6.0, 8.0]]);
+    assert_eq!(processor.output_sources(), [3]);
+    assert!(processor.reset(250, 3).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_artifact_detector_flags_each_kind() -> Result<(), DspError> {
+    let sample_rate = 250;
+    let wave = |hz: f32, amplitude: f32, i: usize| amplitude * (2.0 * std::f32::consts::PI * hz * i as f32 / 250.0).sin();
+    // 2 s of a 10 Hz, 20 µV rhythm, disturbed from sample 500 on all but channel 0
+    let channels: Vec<Vec<f32>> = (0..5)
+        .map(|channel| (0..1000)
+            .map(|i| {
+                let x = wave(10.0, 20.0, i);
+                match (channel, i >= 500) {
+                    (1, true) if i < 520 => 1000.0,
+                    (2, true) => 0.0,
+                    (3, true) => x + 300.0,
+                    (4, true) => x + wave(80.0, 40.0, i),
+                    _ => x,
+                }
+            })
+            .collect())
+        .collect();
+
+    let mut detector = ArtifactDetector::new(sample_rate, &[(-1000.0, 1000.0); 5], ArtifactSettings::default())?;
+    let mut masks: Vec<Vec<ArtifactMask>> = vec![Vec::new(); 5];
+    for start in (0..1000).step_by(32) {
+        let end = (start + 32).min(1000);
+        let block: Vec<Vec<f32>> = channels.iter().map(|c| c[start..end].to_vec()).collect();
+        for (all, new) in masks.iter_mut().zip(detector.push(&block)) {
+            all.extend(new);
+        }
+    }
+    let flagged = |channel: usize, artifact: Artifact| -> Vec<usize> {
+        masks[channel].iter().enumerate().filter(|(_, m)| m.contains(artifact)).map(|(i, _)| i).collect()
+    };
+
+    assert!(masks[0].iter().all(|m| m.is_clean()));
+    for (channel, channel_masks) in masks.iter().enumerate() {
+        assert!(channel_masks[..500].iter().all(|m| m.is_clean()), "channel {} flagged early", channel);
+    }
+    // Half-second windows are 125 samples
+    assert_eq!(flagged(1, Artifact::Clipping), (500..520).collect::<Vec<_>>());
+    assert_eq!(flagged(1, Artifact::Step), [500, 520]);
+    assert_eq!(flagged(2, Artifact::Flatline), (624..1000).collect::<Vec<_>>());
+    assert_eq!(flagged(3, Artifact::Step), [500]);
+    assert_eq!(flagged(3, Artifact::Amplitude), (500..624).collect::<Vec<_>>());
+    let muscle = flagged(4, Artifact::Muscle);
+    assert!(muscle.first().is_some_and(|&i| i > 500 && i < 600) && muscle.last() == Some(&999), "{:?}", muscle);
+    assert_eq!(masks[4][999].artifacts(), [Artifact::Muscle]);
+
+    // Re-referenced channels carry the artifacts of everything they are derived from
+    let derived = derive_masks(&masks, &[vec![0, 2], vec![0]]);
+    assert!(derived[0][700].contains(Artifact::Flatline));
+    assert!(derived[1][700].is_clean());
+
+    assert!(matches!(
+        ArtifactDetector::new(sample_rate, &[(-1.0, 1.0)], ArtifactSettings { step_uv: Some(-1.0), ..Default::default() }),
+        Err(DspError::InvalidArtifactSettings(_))
+    ));
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use serde::{Serialize, Deserialize};
+
+use crate::board_driver::AdcConfig;
+
+/// Unit of the sample values in a stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SignalUnit {
+    /// Raw signed 24-bit ADC output codes
+    Counts,
+    Volts,
+    #[default]
+    Microvolts,
+}
+
+/// Converts raw ADC codes of each configured channel into a physical unit.
+///
+/// A code becomes a voltage through VREF and the channel's PGA gain, and the channel's
+/// calibration is then applied: `(volts - offset) * gain_correction`.
+#[derive(Debug, Clone)]
+pub struct UnitConverter {
+    unit: SignalUnit,
+    /// Microvolts per count, offset in microvolts and gain correction, per channel
+    channels: Vec<(f64, f64, f64)>,
+}
+
+impl UnitConverter {
+    pub fn new(config: &AdcConfig, unit: SignalUnit) -> Self {
+        let full_scale = (1u32 << 23) as f64;
+        let channels = (0..config.channels.len()).map(|i| {
+            let settings = config.settings_for(i);
+            let calibration = config.calibration_for(i);
+            (
+                config.vref as f64 * 1e6 / (settings.gain as f64 * full_scale),
+                calibration.offset_uv as f64,
+                calibration.gain_correction as f64,
+            )
+        }).collect();
+        Self { unit, channels }
+    }
+
+    pub fn unit(&self) -> SignalUnit {
+        self.unit
+    }
+
+    /// Lowest and highest value the channel at position `channel` can read, from the
+    /// ADC's negative and positive full-scale codes.
+    pub fn range(&self, channel: usize) -> (f32, f32) {
+        let full_scale = (1i32 << 23) as f32;
+        let (a, b) = (self.convert(channel, -full_scale), self.convert(channel, full_scale - 1.0));
+        (a.min(b), a.max(b))
+    }
+
+    /// Convert one code from the channel at position `channel` in `AdcConfig::channels`.
+    pub fn convert(&self, channel: usize, code: f32) -> f32 {
+        let (uv_per_count, offset_uv, gain_correction) = self.channels[channel];
+        let microvolts = (code as f64 * uv_per_count - offset_uv) * gain_correction;
+        match self.unit {
+            SignalUnit::Counts => code,
+            SignalUnit::Volts => (microvolts * 1e-6) as f32,
+            SignalUnit::Microvolts => microvolts as f32,
+        }
+    }
+}
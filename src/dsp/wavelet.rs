@@ -0,0 +1,231 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use serde::{Serialize, Deserialize};
+
+use super::error::DspError;
+use super::fir::FirFilter;
+
+/// Morlet kernels are cut off this many Gaussian standard deviations from their centre.
+const MORLET_SPAN_SIGMAS: f64 = 3.0;
+
+/// Morlet continuous wavelet transform parameters.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CwtSettings {
+    /// Centre frequencies of the wavelets, in Hz
+    pub frequencies: Vec<f32>,
+    /// Cycles under each wavelet's Gaussian (its width as 2π standard deviations); more
+    /// cycles resolve frequency better and time worse
+    pub cycles: f32,
+}
+
+impl Default for CwtSettings {
+    /// 4 to 40 Hz in 1 Hz steps with 7-cycle wavelets.
+    fn default() -> Self {
+        Self { frequencies: (4..=40).map(|f| f as f32).collect(), cycles: 7.0 }
+    }
+}
+
+/// Time-frequency amplitude and phase of one channel.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Scalogram {
+    pub frequencies: Vec<f32>,
+    /// Amplitude per frequency and sample, in the unit of the signal: a sinusoid of
+    /// amplitude A at a wavelet's frequency reads A
+    pub amplitude: Vec<Vec<f32>>,
+    /// Phase per frequency and sample, in radians
+    pub phase: Vec<Vec<f32>>,
+}
+
+/// Morlet wavelet sampled as a pair of FIR kernels, real and imaginary part, of odd
+/// length. Each is only as long as its own Gaussian.
+#[derive(Clone, Debug)]
+struct Morlet {
+    re: Vec<f32>,
+    im: Vec<f32>,
+    /// Half the kernel length, the samples its output lags the input by
+    delay: usize,
+}
+
+impl Morlet {
+    /// One wavelet per frequency of `settings`.
+    fn bank(sample_rate: f32, settings: &CwtSettings) -> Result<Vec<Self>, DspError> {
+        if sample_rate.is_nan() || sample_rate <= 0.0 {
+            return Err(DspError::InvalidSampleRate);
+        }
+        let nyquist = sample_rate / 2.0;
+        if settings.frequencies.is_empty() || settings.frequencies.iter().any(|&f| !(f > 0.0 && f < nyquist)) {
+            return Err(DspError::InvalidSpectrum(format!(
+                "Wavelet frequencies must be between 0 and {} Hz (half the sample rate)", nyquist
+            )));
+        }
+        if settings.cycles.is_nan() || settings.cycles <= 0.0 {
+            return Err(DspError::InvalidSpectrum("Wavelet cycles must be positive".to_string()));
+        }
+
+        Ok(settings.frequencies.iter()
+            .map(|&f| {
+                let sigma = settings.cycles as f64 / (2.0 * PI * f as f64) * sample_rate as f64;
+                let span = (MORLET_SPAN_SIGMAS * sigma).ceil() as i64;
+                let omega = 2.0 * PI * f as f64 / sample_rate as f64;
+                let gaussian = |t: i64| (-(t * t) as f64 / (2.0 * sigma * sigma)).exp();
+                // Twice over the Gaussian's sum, so a real sinusoid of amplitude A reads A
+                let scale = 2.0 / (-span..=span).map(gaussian).sum::<f64>();
+                // Kernel index i is the wavelet at t = span - i: convolution flips it
+                let (re, im) = (0..=2 * span)
+                    .map(|i| {
+                        let t = span - i;
+                        let g = scale * gaussian(t);
+                        ((g * (omega * t as f64).cos()) as f32, (g * (omega * t as f64).sin()) as f32)
+                    })
+                    .unzip();
+                Self { re, im, delay: span as usize }
+            })
+            .collect())
+    }
+}
+
+/// Morlet CWT of a recorded buffer, aligned with `data`. The ends are computed as if the
+/// signal were zero outside it, so they are attenuated within a wavelet's length.
+pub fn cwt(data: &[f32], sample_rate: f32, settings: &CwtSettings) -> Result<Scalogram, DspError> {
+    let mut scalogram = Scalogram { frequencies: settings.frequencies.clone(), ..Default::default() };
+    for morlet in Morlet::bank(sample_rate, settings)? {
+        let mut padded = data.to_vec();
+        padded.resize(data.len() + morlet.delay, 0.0);
+        let re = FirFilter::new(morlet.re).process_block(&padded);
+        let im = FirFilter::new(morlet.im).process_block(&padded);
+        let (amplitude, phase) = polar(&re[morlet.delay..], &im[morlet.delay..]);
+        scalogram.amplitude.push(amplitude);
+        scalogram.phase.push(phase);
+    }
+    Ok(scalogram)
+}
+
+fn polar(re: &[f32], im: &[f32]) -> (Vec<f32>, Vec<f32>) {
+    re.iter().zip(im).map(|(&x, &y)| (x.hypot(y), y.atan2(x))).unzip()
+}
+
+/// Wavelet amplitude of every channel for one block of the stream.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScalogramFrame {
+    /// Timestamp of the block the samples came in with
+    pub timestamp: u64,
+    /// How far the output lags the input; subtract from `timestamp`
+    pub delay_us: u64,
+    pub frequencies: Vec<f32>,
+    /// Amplitude per channel, frequency and sample
+    pub amplitude: Vec<Vec<Vec<f32>>>,
+    /// Name of each channel, if the transform was given them
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+/// Streaming Morlet CWT: the same wavelets as [`cwt`] run as streaming FIR filters, see
+/// [`FirFilter::streaming`]. Each wavelet's output is held back to line up with the
+/// slowest, so the output lags the input by half the longest wavelet plus the latency of
+/// its filter.
+#[derive(Debug)]
+pub struct StreamingCwt {
+    sample_rate: f32,
+    settings: CwtSettings,
+    delay: usize,
+    /// Filter of every frequency, per channel
+    channels: Vec<Vec<WaveletFilter>>,
+    labels: Vec<String>,
+}
+
+/// Real and imaginary filter of one wavelet, and the outputs held back to line it up
+/// with the others.
+#[derive(Debug)]
+struct WaveletFilter {
+    re: FirFilter,
+    im: FirFilter,
+    aligned: VecDeque<(f32, f32)>,
+}
+
+impl WaveletFilter {
+    /// Samples the filters alone lag the input by.
+    fn own_delay(morlet: &Morlet) -> usize {
+        morlet.delay + FirFilter::streaming_latency(morlet.re.len())
+    }
+
+    /// Filter whose output lags the input by `delay` samples in all.
+    fn new(morlet: &Morlet, delay: usize) -> Self {
+        Self {
+            re: FirFilter::streaming(morlet.re.clone()),
+            im: FirFilter::streaming(morlet.im.clone()),
+            aligned: VecDeque::from(vec![(0.0, 0.0); delay - Self::own_delay(morlet)]),
+        }
+    }
+
+    fn process_block(&mut self, samples: &[f32]) -> (Vec<f32>, Vec<f32>) {
+        let (re, im) = (self.re.process_block(samples), self.im.process_block(samples));
+        re.into_iter().zip(im)
+            .map(|value| {
+                self.aligned.push_back(value);
+                self.aligned.pop_front().unwrap_or_default()
+            })
+            .unzip()
+    }
+}
+
+impl StreamingCwt {
+    pub fn new(sample_rate: u32, num_channels: usize, settings: CwtSettings) -> Result<Self, DspError> {
+        if sample_rate == 0 {
+            return Err(DspError::InvalidSampleRate);
+        }
+        let bank = Morlet::bank(sample_rate as f32, &settings)?;
+        let delay = bank.iter().map(WaveletFilter::own_delay).max().unwrap_or(0);
+        Ok(Self {
+            sample_rate: sample_rate as f32,
+            delay,
+            channels: (0..num_channels)
+                .map(|_| bank.iter().map(|morlet| WaveletFilter::new(morlet, delay)).collect())
+                .collect(),
+            labels: Vec::new(),
+            settings,
+        })
+    }
+
+    /// Name the channels in the frames, in the order the samples arrive.
+    pub fn with_labels(mut self, labels: Vec<String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    pub fn settings(&self) -> &CwtSettings {
+        &self.settings
+    }
+
+    /// Samples the output lags the input by.
+    pub fn delay_samples(&self) -> usize {
+        self.delay
+    }
+
+    /// Scalogram of a run of consecutive samples of one channel, delayed by
+    /// [`StreamingCwt::delay_samples`].
+    pub fn process_block(&mut self, channel: usize, samples: &[f32]) -> Scalogram {
+        let mut scalogram = Scalogram { frequencies: self.settings.frequencies.clone(), ..Default::default() };
+        for filter in self.channels[channel].iter_mut() {
+            let (re, im) = filter.process_block(samples);
+            let (amplitude, phase) = polar(&re, &im);
+            scalogram.amplitude.push(amplitude);
+            scalogram.phase.push(phase);
+        }
+        scalogram
+    }
+
+    /// Process a block of every channel, whose last sample was taken at `timestamp`.
+    pub fn push(&mut self, samples: &[Vec<f32>], timestamp: u64) -> ScalogramFrame {
+        let amplitude = samples.iter().enumerate()
+            .map(|(channel, block)| self.process_block(channel, block).amplitude)
+            .collect();
+        ScalogramFrame {
+            timestamp,
+            delay_us: (self.delay as f64 * 1e6 / self.sample_rate as f64).round() as u64,
+            frequencies: self.settings.frequencies.clone(),
+            amplitude,
+            labels: self.labels.clone(),
+        }
+    }
+}
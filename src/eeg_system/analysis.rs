@@ -0,0 +1,226 @@
+use std::sync::Arc;
+use tokio::sync::{broadcast, Mutex};
+
+use crate::dsp::error::DspError;
+use crate::dsp::hilbert::{AnalyticFrame, HilbertSettings, StreamingHilbert};
+use crate::dsp::spectrogram::{Spectrogram, SpectrogramFrame, SpectrogramSettings};
+use crate::dsp::spectrum::{BandPowerAnalyzer, BandPowerReport, BandPowerSettings};
+use crate::dsp::wavelet::{CwtSettings, ScalogramFrame, StreamingCwt};
+
+/// Sample rate and channels of the processed stream the analyses are fed with.
+pub(crate) struct StreamLayout {
+    pub sample_rate: u32,
+    /// ADC channel number each channel is centred on
+    pub channels: Vec<usize>,
+    pub labels: Vec<String>,
+}
+
+/// An analysis fed with the processed stream, batch by batch, whose results are published
+/// on a channel of their own.
+pub(crate) trait StreamAnalysis: Sized + Send + 'static {
+    type Settings: Clone + Send;
+    type Output: Clone + Send + 'static;
+
+    fn build(layout: &StreamLayout, settings: Self::Settings) -> Result<Self, DspError>;
+
+    fn settings(&self) -> &Self::Settings;
+
+    /// Add one batch of processed samples per channel, whose last sample was taken at
+    /// `timestamp`.
+    fn analyze(&mut self, samples: &[Vec<f32>], timestamp: u64) -> Vec<Self::Output>;
+}
+
+impl StreamAnalysis for BandPowerAnalyzer {
+    type Settings = BandPowerSettings;
+    type Output = BandPowerReport;
+
+    fn build(layout: &StreamLayout, settings: BandPowerSettings) -> Result<Self, DspError> {
+        Ok(BandPowerAnalyzer::new(layout.sample_rate, &layout.channels, settings)?.with_labels(layout.labels.clone()))
+    }
+
+    fn settings(&self) -> &BandPowerSettings {
+        self.settings()
+    }
+
+    fn analyze(&mut self, samples: &[Vec<f32>], timestamp: u64) -> Vec<BandPowerReport> {
+        self.push(samples, timestamp)
+    }
+}
+
+impl StreamAnalysis for Spectrogram {
+    type Settings = SpectrogramSettings;
+    type Output = SpectrogramFrame;
+
+    fn build(layout: &StreamLayout, settings: SpectrogramSettings) -> Result<Self, DspError> {
+        Ok(Spectrogram::new(layout.sample_rate, layout.channels.len(), settings)?.with_labels(layout.labels.clone()))
+    }
+
+    fn settings(&self) -> &SpectrogramSettings {
+        self.settings()
+    }
+
+    fn analyze(&mut self, samples: &[Vec<f32>], timestamp: u64) -> Vec<SpectrogramFrame> {
+        self.push(samples, timestamp)
+    }
+}
+
+impl StreamAnalysis for StreamingHilbert {
+    type Settings = HilbertSettings;
+    type Output = AnalyticFrame;
+
+    fn build(layout: &StreamLayout, settings: HilbertSettings) -> Result<Self, DspError> {
+        Ok(StreamingHilbert::new(layout.sample_rate, layout.channels.len(), settings)?.with_labels(layout.labels.clone()))
+    }
+
+    fn settings(&self) -> &HilbertSettings {
+        self.settings()
+    }
+
+    fn analyze(&mut self, samples: &[Vec<f32>], timestamp: u64) -> Vec<AnalyticFrame> {
+        vec![self.push(samples, timestamp)]
+    }
+}
+
+impl StreamAnalysis for StreamingCwt {
+    type Settings = CwtSettings;
+    type Output = ScalogramFrame;
+
+    fn build(layout: &StreamLayout, settings: CwtSettings) -> Result<Self, DspError> {
+        Ok(StreamingCwt::new(layout.sample_rate, layout.channels.len(), settings)?.with_labels(layout.labels.clone()))
+    }
+
+    fn settings(&self) -> &CwtSettings {
+        self.settings()
+    }
+
+    fn analyze(&mut self, samples: &[Vec<f32>], timestamp: u64) -> Vec<ScalogramFrame> {
+        vec![self.push(samples, timestamp)]
+    }
+}
+
+/// An optional [`StreamAnalysis`] shared with the processing task, and the channel its
+/// results go out on. Clones share both.
+pub(crate) struct AnalysisTap<A: StreamAnalysis> {
+    analysis: Arc<Mutex<Option<A>>>,
+    tx: broadcast::Sender<A::Output>,
+}
+
+impl<A: StreamAnalysis> Clone for AnalysisTap<A> {
+    fn clone(&self) -> Self {
+        Self { analysis: Arc::clone(&self.analysis), tx: self.tx.clone() }
+    }
+}
+
+impl<A: StreamAnalysis> AnalysisTap<A> {
+    /// An idle tap whose channel holds `capacity` results; slow subscribers lag rather
+    /// than block processing.
+    pub(crate) fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity);
+        Self { analysis: Arc::new(Mutex::new(None)), tx }
+    }
+
+    /// Start the analysis of a stream laid out as `layout` or, with `None`, stop it.
+    pub(crate) async fn set(&self, layout: &StreamLayout, settings: Option<A::Settings>) -> Result<(), DspError> {
+        let analysis = settings.map(|settings| A::build(layout, settings)).transpose()?;
+        *self.analysis.lock().await = analysis;
+        Ok(())
+    }
+
+    /// The running analysis built afresh, with the same settings, for a stream laid out
+    /// as `layout`, to put in place with [`AnalysisTap::replace`]; `None` if not running.
+    pub(crate) async fn rebuilt(&self, layout: &StreamLayout) -> Result<Option<A>, DspError> {
+        self.analysis.lock().await.as_ref()
+            .map(|analysis| A::build(layout, analysis.settings().clone()))
+            .transpose()
+    }
+
+    pub(crate) async fn replace(&self, analysis: Option<A>) {
+        *self.analysis.lock().await = analysis;
+    }
+
+    /// Feed a batch to the analysis, if running, and publish whatever it produces.
+    pub(crate) async fn push(&self, samples: &[Vec<f32>], timestamp: u64) {
+        if let Some(analysis) = self.analysis.lock().await.as_mut() {
+            for output in analysis.analyze(samples, timestamp) {
+                // Sending only fails when nobody is subscribed
+                let _ = self.tx.send(output);
+            }
+        }
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<A::Output> {
+        self.tx.subscribe()
+    }
+}
+
+/// Capacity of the band-power channel
+const BAND_POWER_CAPACITY: usize = 16;
+
+/// Capacity of the spectrogram channel: a few seconds of frames at the default hop
+const SPECTROGRAM_CAPACITY: usize = 64;
+
+/// Capacity of the Hilbert and wavelet channels, which carry one frame per batch
+const PER_BATCH_CAPACITY: usize = 64;
+
+/// Analyses built for a new stream layout, not yet running.
+pub(crate) struct RebuiltAnalyses {
+    band_power: Option<BandPowerAnalyzer>,
+    spectrogram: Option<Spectrogram>,
+    hilbert: Option<StreamingHilbert>,
+    cwt: Option<StreamingCwt>,
+}
+
+/// Every analysis that can run on the processed stream. Clones share them.
+#[derive(Clone)]
+pub(crate) struct Analyses {
+    pub band_power: AnalysisTap<BandPowerAnalyzer>,
+    pub spectrogram: AnalysisTap<Spectrogram>,
+    pub hilbert: AnalysisTap<StreamingHilbert>,
+    pub cwt: AnalysisTap<StreamingCwt>,
+}
+
+impl Analyses {
+    pub(crate) fn new() -> Self {
+        Self {
+            band_power: AnalysisTap::new(BAND_POWER_CAPACITY),
+            spectrogram: AnalysisTap::new(SPECTROGRAM_CAPACITY),
+            hilbert: AnalysisTap::new(PER_BATCH_CAPACITY),
+            cwt: AnalysisTap::new(PER_BATCH_CAPACITY),
+        }
+    }
+
+    /// Every running analysis built afresh for a stream laid out as `layout`, leaving
+    /// those running untouched until [`Analyses::install`]. Fails if any doesn't fit.
+    pub(crate) async fn rebuilt(&self, layout: &StreamLayout) -> Result<RebuiltAnalyses, DspError> {
+        Ok(RebuiltAnalyses {
+            band_power: self.band_power.rebuilt(layout).await?,
+            spectrogram: self.spectrogram.rebuilt(layout).await?,
+            hilbert: self.hilbert.rebuilt(layout).await?,
+            cwt: self.cwt.rebuilt(layout).await?,
+        })
+    }
+
+    /// Put analyses built by [`Analyses::rebuilt`] in place of those running.
+    pub(crate) async fn install(&self, rebuilt: RebuiltAnalyses) {
+        self.band_power.replace(rebuilt.band_power).await;
+        self.spectrogram.replace(rebuilt.spectrogram).await;
+        self.hilbert.replace(rebuilt.hilbert).await;
+        self.cwt.replace(rebuilt.cwt).await;
+    }
+
+    /// Start every running analysis afresh for a stream laid out as `layout`; if any
+    /// doesn't fit, all are left as they were.
+    pub(crate) async fn rebuild(&self, layout: &StreamLayout) -> Result<(), DspError> {
+        let rebuilt = self.rebuilt(layout).await?;
+        self.install(rebuilt).await;
+        Ok(())
+    }
+
+    /// Feed a batch to every running analysis.
+    pub(crate) async fn push(&self, samples: &[Vec<f32>], timestamp: u64) {
+        self.band_power.push(samples, timestamp).await;
+        self.spectrogram.push(samples, timestamp).await;
+        self.hilbert.push(samples, timestamp).await;
+        self.cwt.push(samples, timestamp).await;
+    }
+}
@@ -0,0 +1,53 @@
+use async_trait::async_trait;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+use crate::board_driver::DriverEvent;
+
+/// What an [`EventLoop`] does with the driver's events.
+#[async_trait]
+pub(crate) trait EventHandler: Send + 'static {
+    /// Handle one event; returning `false` ends the loop.
+    async fn handle(&mut self, event: DriverEvent) -> bool;
+}
+
+/// Task feeding the driver's events to an [`EventHandler`]. Stopping it hands the event
+/// receiver back, so the same driver can be run again.
+pub(crate) struct EventLoop {
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<mpsc::Receiver<DriverEvent>>,
+}
+
+impl EventLoop {
+    pub(crate) fn spawn<H: EventHandler>(mut events: mpsc::Receiver<DriverEvent>, mut handler: H) -> Self {
+        let (stop, mut stopped) = oneshot::channel();
+        let task = tokio::spawn(async move {
+            loop {
+                let event = tokio::select! {
+                    _ = &mut stopped => break,
+                    event = events.recv() => event,
+                };
+                let Some(event) = event else { break };
+                // An event still being handled is abandoned on stop, so a consumer that
+                // no longer reads can't hold the loop up
+                let keep_going = tokio::select! {
+                    _ = &mut stopped => break,
+                    keep_going = handler.handle(event) => keep_going,
+                };
+                if !keep_going {
+                    break;
+                }
+            }
+            events
+        });
+        Self { stop, task }
+    }
+
+    /// Stop the loop and take the event receiver back; `None` if the task panicked and
+    /// lost it.
+    pub(crate) async fn stop(self) -> Option<mpsc::Receiver<DriverEvent>> {
+        // Fails only when the loop already ended by itself
+        let _ = self.stop.send(());
+        self.task.await.ok()
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use log::debug;
+use serde::{Serialize, Deserialize};
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::Notify;
+
+/// What happens when a subscriber's queue is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backpressure {
+    /// Wait for the subscriber to make room, holding up every subscriber and, once the
+    /// driver's buffer fills, acquisition. Nothing is lost.
+    #[default]
+    Block,
+    /// Make room by dropping the oldest queued item: the subscriber skips ahead
+    DropOldest,
+    /// Drop the new item: the subscriber keeps what it has queued
+    DropNewest,
+}
+
+/// How one subscriber is keeping up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubscriberStats {
+    pub id: u64,
+    pub backpressure: Backpressure,
+    pub capacity: usize,
+    /// Items waiting to be received
+    pub queued: usize,
+    /// Items dropped so far because the queue was full
+    pub dropped: u64,
+}
+
+struct QueueState<T> {
+    items: VecDeque<T>,
+    dropped: u64,
+    /// No more items will come
+    closed: bool,
+    /// The subscriber went away
+    abandoned: bool,
+}
+
+/// One subscriber's bounded queue.
+struct Queue<T> {
+    id: u64,
+    backpressure: Backpressure,
+    capacity: usize,
+    state: Mutex<QueueState<T>>,
+    /// Woken when an item arrives or the queue closes
+    readable: Notify,
+    /// Woken when an item is taken or the subscriber goes away, for blocked senders
+    writable: Notify,
+}
+
+impl<T> Queue<T> {
+    fn lock(&self) -> std::sync::MutexGuard<'_, QueueState<T>> {
+        // The state stays consistent even if a holder panicked
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Queue `item` as the backpressure policy says; `false` once the subscriber is gone.
+    async fn send(&self, item: T) -> bool {
+        let mut item = Some(item);
+        loop {
+            {
+                let mut state = self.lock();
+                if state.abandoned {
+                    return false;
+                }
+                if state.items.len() >= self.capacity {
+                    match self.backpressure {
+                        Backpressure::Block => {}
+                        Backpressure::DropOldest => {
+                            state.items.pop_front();
+                            state.dropped += 1;
+                        }
+                        Backpressure::DropNewest => {
+                            state.dropped += 1;
+                            return true;
+                        }
+                    }
+                }
+                if state.items.len() < self.capacity {
+                    state.items.extend(item.take());
+                    drop(state);
+                    self.readable.notify_one();
+                    return true;
+                }
+            }
+            self.writable.notified().await;
+        }
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.readable.notify_one();
+    }
+
+    fn stats(&self) -> SubscriberStats {
+        let state = self.lock();
+        SubscriberStats {
+            id: self.id,
+            backpressure: self.backpressure,
+            capacity: self.capacity,
+            queued: state.items.len(),
+            dropped: state.dropped,
+        }
+    }
+}
+
+/// Receiving end of a subscription to one of the `EegSystem` streams. Dropping it
+/// unsubscribes.
+pub struct Subscription<T> {
+    queue: Arc<Queue<T>>,
+}
+
+impl<T> Subscription<T> {
+    /// Wait for the next item; `None` once the stream has ended and the queue is empty.
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            {
+                let mut state = self.queue.lock();
+                if let Some(item) = state.items.pop_front() {
+                    drop(state);
+                    self.queue.writable.notify_one();
+                    return Some(item);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.queue.readable.notified().await;
+        }
+    }
+
+    /// The next item if one is queued.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let mut state = self.queue.lock();
+        match state.items.pop_front() {
+            Some(item) => {
+                drop(state);
+                self.queue.writable.notify_one();
+                Ok(item)
+            }
+            None if state.closed => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Items dropped so far because this subscriber fell behind.
+    pub fn dropped(&self) -> u64 {
+        self.queue.lock().dropped
+    }
+
+    pub fn stats(&self) -> SubscriberStats {
+        self.queue.stats()
+    }
+}
+
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        self.queue.lock().abandoned = true;
+        self.queue.writable.notify_one();
+    }
+}
+
+struct FanOutInner<T> {
+    subscribers: Mutex<Vec<Arc<Queue<T>>>>,
+    next_id: AtomicU64,
+}
+
+impl<T> FanOutInner<T> {
+    fn subscribers(&self) -> std::sync::MutexGuard<'_, Vec<Arc<Queue<T>>>> {
+        self.subscribers.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<T> Drop for FanOutInner<T> {
+    fn drop(&mut self) {
+        for queue in self.subscribers().iter() {
+            queue.close();
+        }
+    }
+}
+
+/// Hands every item to each of any number of subscribers, each with a queue and
+/// [`Backpressure`] policy of its own. Clones share the subscribers; when the last clone
+/// goes, subscribers see the end of the stream.
+pub(crate) struct FanOut<T> {
+    inner: Arc<FanOutInner<T>>,
+}
+
+impl<T> Clone for FanOut<T> {
+    fn clone(&self) -> Self {
+        Self { inner: Arc::clone(&self.inner) }
+    }
+}
+
+impl<T: Clone> FanOut<T> {
+    pub(crate) fn new() -> Self {
+        Self { inner: Arc::new(FanOutInner { subscribers: Mutex::new(Vec::new()), next_id: AtomicU64::new(0) }) }
+    }
+
+    /// Add a subscriber whose queue holds up to `capacity` items, at least one.
+    pub(crate) fn subscribe(&self, backpressure: Backpressure, capacity: usize) -> Subscription<T> {
+        let queue = Arc::new(Queue {
+            id: self.inner.next_id.fetch_add(1, Ordering::Relaxed),
+            backpressure,
+            capacity: capacity.max(1),
+            state: Mutex::new(QueueState { items: VecDeque::new(), dropped: 0, closed: false, abandoned: false }),
+            readable: Notify::new(),
+            writable: Notify::new(),
+        });
+        self.inner.subscribers().push(Arc::clone(&queue));
+        Subscription { queue }
+    }
+
+    /// Hand `item` to every subscriber, forgetting those that went away.
+    pub(crate) async fn send(&self, item: T) {
+        let subscribers = self.inner.subscribers().clone();
+        let mut gone = Vec::new();
+        for queue in &subscribers {
+            if !queue.send(item.clone()).await {
+                gone.push(queue.id);
+            }
+        }
+        if !gone.is_empty() {
+            debug!("Subscribers {:?} went away", gone);
+            self.inner.subscribers().retain(|queue| !gone.contains(&queue.id));
+        }
+    }
+
+    pub(crate) fn has_subscribers(&self) -> bool {
+        !self.inner.subscribers().is_empty()
+    }
+
+    pub(crate) fn stats(&self) -> Vec<SubscriberStats> {
+        self.inner.subscribers().iter().map(|queue| queue.stats()).collect()
+    }
+}
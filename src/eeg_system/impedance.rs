@@ -0,0 +1,177 @@
+use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
+use tokio::sync::broadcast;
+
+use crate::board_driver::ads1299_registers::{InputMux, LeadOffCurrent, LeadOffFrequency};
+use crate::board_driver::{AdcConfig, AdcData, DriverError, DriverEvent, DriverStatus, LeadOffConfig};
+use crate::dsp::impedance::{impedance_ohms, window_length};
+use crate::dsp::units::{SignalUnit, UnitConverter};
+use super::event_loop::EventHandler;
+use super::SystemEvent;
+
+/// How an impedance check excites and measures the electrodes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImpedanceSettings {
+    /// AC lead-off excitation current
+    pub current: LeadOffCurrent,
+    /// AC lead-off excitation frequency; DC cannot be used to measure impedance
+    pub frequency: LeadOffFrequency,
+    /// Minimum length of each measurement window, rounded up to whole excitation periods
+    pub window_secs: f32,
+}
+
+impl Default for ImpedanceSettings {
+    /// 6 nA at 31.2 Hz over one-second windows.
+    fn default() -> Self {
+        Self {
+            current: LeadOffCurrent::Na6,
+            frequency: LeadOffFrequency::Ac31_2,
+            window_secs: 1.0,
+        }
+    }
+}
+
+impl ImpedanceSettings {
+    /// `config` with AC lead-off excitation driven into every positive electrode.
+    pub fn apply(&self, config: &AdcConfig) -> Result<AdcConfig, DriverError> {
+        if self.frequency == LeadOffFrequency::Dc {
+            return Err(DriverError::ConfigurationError(
+                "Impedance measurement needs an AC lead-off frequency".to_string()
+            ));
+        }
+        if self.window_secs <= 0.0 {
+            return Err(DriverError::ConfigurationError(
+                "Impedance window must be longer than 0 s".to_string()
+            ));
+        }
+        Ok(AdcConfig {
+            lead_off: Some(LeadOffConfig {
+                current: self.current,
+                frequency: self.frequency,
+                positive: true,
+                negative: false,
+                ..LeadOffConfig::default()
+            }),
+            ..config.clone()
+        })
+    }
+}
+
+/// Impedance of one electrode.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ImpedanceReading {
+    pub channel: usize,
+    pub kohms: f32,
+}
+
+/// Impedances measured over one window.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ImpedanceReport {
+    /// Timestamp of the last sample in the window
+    pub timestamp: u64,
+    /// One reading per configured channel on the electrode input, in config order
+    pub readings: Vec<ImpedanceReading>,
+}
+
+impl ImpedanceReport {
+    /// Reading for `channel`, if it was measured.
+    pub fn kohms(&self, channel: usize) -> Option<f32> {
+        self.readings.iter().find(|r| r.channel == channel).map(|r| r.kohms)
+    }
+}
+
+/// Collects samples from an excited acquisition and turns each full window into a report.
+pub(crate) struct ImpedanceMeter {
+    sample_rate: f32,
+    frequency: f32,
+    current_amps: f64,
+    window: usize,
+    volts: UnitConverter,
+    /// (position in the sample vector, channel number) of each measured channel
+    measured: Vec<(usize, usize)>,
+    buffers: Vec<Vec<f32>>,
+}
+
+impl ImpedanceMeter {
+    /// `config` must be the excited configuration returned by [`ImpedanceSettings::apply`].
+    pub(crate) fn new(config: &AdcConfig, settings: &ImpedanceSettings) -> Result<Self, DriverError> {
+        let sample_rate = config.sample_rate as f32;
+        let frequency = settings.frequency.hz(config.sample_rate).ok_or_else(|| DriverError::ConfigurationError(
+            "Impedance measurement needs an AC lead-off frequency".to_string()
+        ))? as f32;
+
+        let measured: Vec<(usize, usize)> = config.channels.iter().enumerate()
+            .filter_map(|(i, &ch)| {
+                let settings = config.settings_for(i);
+                (!settings.power_down && settings.input == InputMux::Normal).then_some((i, ch))
+            })
+            .collect();
+        if measured.is_empty() {
+            return Err(DriverError::ConfigurationError(
+                "No channels on the electrode input to measure".to_string()
+            ));
+        }
+
+        let min_samples = (settings.window_secs * sample_rate).ceil() as usize;
+        Ok(Self {
+            sample_rate,
+            frequency,
+            current_amps: settings.current.amps(),
+            window: window_length(sample_rate, frequency, min_samples),
+            volts: UnitConverter::new(config, SignalUnit::Volts),
+            buffers: vec![Vec::new(); measured.len()],
+            measured,
+        })
+    }
+
+    /// Add a batch of raw samples, returning a report for every window it completes.
+    pub(crate) fn push(&mut self, batch: &[AdcData]) -> Vec<ImpedanceReport> {
+        let mut reports = Vec::new();
+        for sample in batch {
+            for (buffer, &(index, _)) in self.buffers.iter_mut().zip(&self.measured) {
+                buffer.push(sample.samples[index].first().map_or(0.0, |&code| self.volts.convert(index, code)));
+            }
+            if self.buffers[0].len() == self.window {
+                reports.push(self.report(sample.timestamp));
+            }
+        }
+        reports
+    }
+
+    fn report(&mut self, timestamp: u64) -> ImpedanceReport {
+        let readings = self.buffers.iter_mut().zip(&self.measured)
+            .map(|(buffer, &(_, channel))| {
+                let ohms = impedance_ohms(buffer, self.sample_rate, self.frequency, self.current_amps);
+                buffer.clear();
+                ImpedanceReading { channel, kohms: (ohms / 1e3) as f32 }
+            })
+            .collect();
+        ImpedanceReport { timestamp, readings }
+    }
+}
+
+/// Publishes the reports of an [`ImpedanceMeter`] fed with the driver's events.
+pub(crate) struct ImpedanceCheck {
+    pub meter: ImpedanceMeter,
+    pub system_events: broadcast::Sender<SystemEvent>,
+}
+
+#[async_trait]
+impl EventHandler for ImpedanceCheck {
+    async fn handle(&mut self, event: DriverEvent) -> bool {
+        match event {
+            DriverEvent::Data(batch) => {
+                for report in self.meter.push(&batch) {
+                    let _ = self.system_events.send(SystemEvent::Impedance(report));
+                }
+            }
+            DriverEvent::LeadOff(status) => {
+                let _ = self.system_events.send(SystemEvent::LeadOff(status));
+            }
+            DriverEvent::StatusChange(DriverStatus::Stopped) => return false,
+            _ => {}
+        }
+        true
+    }
+}
@@ -1,53 +1,213 @@
 use std::error::Error;
 use std::sync::Arc;
-use tokio::sync::{mpsc, Mutex}; // Use Tokio Mutex
-use tokio::task::JoinHandle;
+use tokio::sync::{broadcast, mpsc, watch, Mutex}; // Use Tokio Mutex
 use std::time::Duration;
+use log::warn;
 
 use crate::board_driver::{
-    create_driver, AdcConfig, AdcDriver, DriverError, DriverEvent, DriverStatus, DriverType,
+    create_driver, AdcConfig, AdcDriver, DriverCapabilities, DriverError, DriverEvent, DriverStatus, DriverType, ElectrodeInfo,
+    LeadOffStatus, MockDriver, MockSettings,
 };
-use crate::dsp::filters::SignalProcessor;
-use super::ProcessedData;
+use crate::dsp::artifacts::{ArtifactDetector, ArtifactSettings};
+use crate::dsp::filters::{FilterChainSpec, SignalProcessor, StageAdjustment};
+use crate::dsp::hilbert::{AnalyticFrame, HilbertSettings};
+use crate::dsp::reference::Reference;
+use crate::dsp::spectrogram::{SpectrogramFrame, SpectrogramSettings};
+use crate::dsp::spectrum::{BandPowerReport, BandPowerSettings};
+use crate::dsp::wavelet::{CwtSettings, ScalogramFrame};
+use crate::dsp::units::{SignalUnit, UnitConverter};
+use super::{ProcessedData, RawData};
+
+mod analysis;
+mod event_loop;
+mod fanout;
+mod impedance;
+mod pipeline;
+mod sample_tracker;
+use analysis::{Analyses, RebuiltAnalyses, StreamLayout};
+use event_loop::EventLoop;
+use fanout::FanOut;
+pub use fanout::{Backpressure, SubscriberStats, Subscription};
+pub use impedance::{ImpedanceReading, ImpedanceReport, ImpedanceSettings};
+use impedance::{ImpedanceCheck, ImpedanceMeter};
+use pipeline::Pipeline;
+pub use sample_tracker::{DataGap, SampleRateStats};
+use sample_tracker::SampleTracker;
+
+/// Queue capacity of the subscriber `EegSystem::new` hands out
+const PROCESSED_CAPACITY: usize = 100;
+
+/// Capacity of the system event channel; slow subscribers lag rather than block processing
+const SYSTEM_EVENT_CAPACITY: usize = 64;
+
+/// Events about the acquisition itself, published alongside the data stream.
+#[derive(Debug, Clone)]
+pub enum SystemEvent {
+    /// The set of disconnected electrodes changed
+    LeadOff(LeadOffStatus),
+    /// One measurement window of an impedance check completed
+    Impedance(ImpedanceReport),
+    /// Samples went missing or came twice
+    DataGap(DataGap),
+}
+
+/// Where an [`EegSystem`] is in its lifecycle.
+///
+/// `start` runs the system from `Idle` or, restarting it, from `Running`; `stop` brings
+/// it back to `Idle`, ending an impedance check if one is running. `reconfigure` keeps
+/// the system in the state it is in. Nothing leaves `ShutDown`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemState {
+    /// The driver is ready but not acquiring
+    Idle,
+    /// Acquiring and publishing `ProcessedData`
+    Running,
+    /// Acquiring with lead-off excitation, publishing impedance reports only
+    CheckingImpedance,
+    /// The driver was shut down for good
+    ShutDown,
+}
 
 pub struct EegSystem {
     driver: Box<dyn AdcDriver>,
+    state: SystemState,
     processor: Arc<Mutex<SignalProcessor>>,
-    processing_task: Option<JoinHandle<()>>,
-    tx: mpsc::Sender<ProcessedData>,
+    // Consumes the driver's events while acquiring; holds on to `event_rx` until stopped
+    event_loop: Option<EventLoop>,
+    // Every subscriber to the processed stream, and to the raw one
+    processed: FanOut<ProcessedData>,
+    raw: FanOut<RawData>,
     event_rx: Option<mpsc::Receiver<DriverEvent>>,
+    system_events: broadcast::Sender<SystemEvent>,
+    // Sample rate figures of the current or last run
+    rate_stats: watch::Receiver<SampleRateStats>,
+    // Band power, spectrogram and the like, fed with the processed stream
+    analyses: Analyses,
+    // Flags artifacts in the samples before filtering, when enabled
+    artifacts: Arc<Mutex<Option<ArtifactDetector>>>,
+    // Set during an impedance check: the configuration to restore and whether to resume processing
+    impedance_restore: Option<(AdcConfig, bool)>,
+    // What a mock driver simulates, kept for when the driver has to be rebuilt
+    mock_settings: MockSettings,
 }
 
 impl EegSystem {
-    /// Creates an EEG processing system without starting it
+    /// Creates an EEG processing system without starting it. Every channel is run
+    /// through the filter chain `filters`. The subscription returned blocks processing
+    /// when full; [`EegSystem::subscribe_processed`] adds more.
     pub async fn new(
-        config: AdcConfig
-    ) -> Result<(Self, mpsc::Receiver<ProcessedData>), Box<dyn Error>> {
-        let (driver, event_rx) = create_driver(config.clone()).await?;
-        let processor = Arc::new(Mutex::new(SignalProcessor::new(
-            config.sample_rate,
-            config.channels.len(),
-        )));
-        let (tx, rx) = mpsc::channel(100);
+        config: AdcConfig,
+        filters: FilterChainSpec,
+    ) -> Result<(Self, Subscription<ProcessedData>), Box<dyn Error>> {
+        // Check the chain before touching any hardware
+        SignalProcessor::with_chain(config.sample_rate, config.channels.len(), filters.clone())?;
+        let (driver, event_rx) = create_driver(config).await?;
+        Self::with_driver(driver, event_rx, filters).await
+    }
+
+    /// Like [`EegSystem::new`], on a mock driver simulating what `settings` script. The
+    /// settings carry over to any mock driver the system has to rebuild.
+    pub async fn with_mock(
+        config: AdcConfig,
+        settings: MockSettings,
+        filters: FilterChainSpec,
+    ) -> Result<(Self, Subscription<ProcessedData>), Box<dyn Error>> {
+        let (driver, event_rx) = MockDriver::with_settings(config, settings.clone(), 0)?;
+        let (mut system, rx) = Self::with_driver(Box::new(driver), event_rx, filters).await?;
+        system.mock_settings = settings;
+        Ok((system, rx))
+    }
+
+    /// Like [`EegSystem::new`], on a driver created beforehand. A driver rebuilt for
+    /// another driver type, or after the driver lost its event stream, is created from
+    /// the configuration alone.
+    pub async fn with_driver(
+        mut driver: Box<dyn AdcDriver>,
+        event_rx: mpsc::Receiver<DriverEvent>,
+        filters: FilterChainSpec,
+    ) -> Result<(Self, Subscription<ProcessedData>), Box<dyn Error>> {
+        let config = driver.get_config().await?;
+        let processor = match SignalProcessor::with_chain(config.sample_rate, config.channels.len(), filters) {
+            Ok(processor) => Arc::new(Mutex::new(processor)),
+            Err(e) => {
+                driver.shutdown().await?;
+                return Err(Box::new(e));
+            }
+        };
+        let processed = FanOut::new();
+        let rx = processed.subscribe(Backpressure::Block, PROCESSED_CAPACITY);
+        let (system_events, _) = broadcast::channel(SYSTEM_EVENT_CAPACITY);
+        let (_, rate_stats) = watch::channel(SampleTracker::new(config.sample_rate).stats());
 
         let system = Self {
             driver,
+            state: SystemState::Idle,
             processor,
-            processing_task: None,
-            tx,
+            event_loop: None,
+            processed,
+            raw: FanOut::new(),
             event_rx: Some(event_rx),
+            system_events,
+            rate_stats,
+            analyses: Analyses::new(),
+            artifacts: Arc::new(Mutex::new(None)),
+            impedance_restore: None,
+            mock_settings: MockSettings::default(),
         };
 
         Ok((system, rx))
     }
 
-    /// Starts the processing system with the given configuration
+    /// Where the system is in its lifecycle.
+    pub fn state(&self) -> SystemState {
+        self.state
+    }
+
+    /// Starts processing with the given configuration, restarting if already running.
+    /// The driver is rebuilt first when `config` differs from its own.
     pub async fn start(&mut self, config: AdcConfig) -> Result<(), Box<dyn Error>> {
-        self.initialize_processing(config).await
+        self.apply(config, true).await
+    }
+
+    /// Stop the data acquisition and processing, keeping the driver ready for another
+    /// start. An impedance check is ended without resuming processing.
+    pub async fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        match self.state {
+            SystemState::Running => {
+                self.halt().await?;
+                self.state = SystemState::Idle;
+            }
+            SystemState::CheckingImpedance => {
+                if let Some((_, resume)) = self.impedance_restore.as_mut() {
+                    *resume = false;
+                }
+                self.stop_impedance_check().await?;
+            }
+            SystemState::Idle | SystemState::ShutDown => {}
+        }
+        Ok(())
+    }
+
+    /// Switch to a new configuration: the driver is rebuilt for it, the processor and
+    /// analyses start afresh, and processing resumes if it was running. If the new
+    /// configuration is rejected, the previous one stays and the system is left idle.
+    pub async fn reconfigure(&mut self, config: AdcConfig) -> Result<(), Box<dyn Error>> {
+        let resume = self.state == SystemState::Running;
+        self.apply(config, resume).await
     }
 
-    /// Internal helper to initialize or reinitialize the driver and processing task
-    async fn initialize_processing(&mut self, config: AdcConfig) -> Result<(), Box<dyn Error>> {
+    /// Bring driver and processing to `config`, running afterwards if `run`.
+    async fn apply(&mut self, config: AdcConfig, run: bool) -> Result<(), Box<dyn Error>> {
+        match self.state {
+            SystemState::CheckingImpedance => return Err(Box::new(DriverError::ConfigurationError(
+                "Impedance check in progress, stop it first".into()
+            ))),
+            SystemState::ShutDown => return Err(Box::new(DriverError::ConfigurationError(
+                "System has been shut down".into()
+            ))),
+            SystemState::Idle | SystemState::Running => {}
+        }
+
         // Add validation before proceeding
         if config.channels.is_empty() {
             return Err(Box::new(DriverError::ConfigurationError(
@@ -60,89 +220,427 @@ impl EegSystem {
                 "Sample rate must be greater than 0".into()
             )));
         }
+        config.validate_channel_settings()?;
+        DriverCapabilities::for_config(&config).validate(&config)?;
 
-        // Stop any existing processing task gracefully
-        if let Some(task) = self.processing_task.take() {
-            task.abort();
-        }
-
-        // Reset the signal processor
-        {
-            let mut proc_guard = self.processor.lock().await;
-            proc_guard.reset(config.sample_rate, config.channels.len());
-        }
-
-        self.driver.start_acquisition().await?;
-
-        // Take ownership of the event receiver
-        let mut event_rx = self.event_rx.take().expect("Event receiver should exist");
-
-        // Start the processing task
-        let processor: Arc<Mutex<SignalProcessor>> = Arc::clone(&self.processor);
-        let tx = self.tx.clone();
-
-        self.processing_task = Some(tokio::spawn(async move {
-            while let Some(event) = event_rx.recv().await {
-                match event {
-                    DriverEvent::Data(data_batch) => {
-                        // Pre-allocate with known capacity
-                        let batch_size = data_batch.len();
-                        let channel_count = data_batch[0].samples.len();
-                        let samples_per_channel = data_batch[0].samples[0].len();
-                        
-                        let mut processed_channels: Vec<Vec<f32>> = Vec::with_capacity(channel_count);
-                        for _ in 0..channel_count {
-                            processed_channels.push(Vec::with_capacity(batch_size * samples_per_channel));
-                        }
-                        
-                        // Single lock acquisition for the batch
-                        let mut proc_guard = processor.lock().await;
-                        
-                        // Process all samples in the batch
-                        for data in &data_batch {
-                            for (ch_idx, channel_samples) in data.samples.iter().enumerate() {
-                                processed_channels[ch_idx].extend(
-                                    channel_samples.iter().map(|&sample| 
-                                        proc_guard.process_sample(ch_idx, sample as f32)
-                                    )
-                                );
-                            }
-                        }
-                        drop(proc_guard);
-
-                        if tx.send(ProcessedData {
-                            data: processed_channels,
-                            timestamp: data_batch.last().unwrap().timestamp,
-                            channel_count,
-                        }).await.is_err() {
-                            break;
-                        }
-                    }
-                    DriverEvent::StatusChange(status) => {
-                        if status == DriverStatus::Stopped {
-                            break;
-                        }
-                    }
-                    _ => {}
-                }
+        self.halt().await?;
+        self.state = SystemState::Idle;
+
+        // The processor is left as it was if the filters or reference don't fit `config`
+        self.processor.lock().await.reset(config.sample_rate, config.channels.len())?;
+        let previous = self.driver.get_config().await?;
+
+        // Samples are filtered and published in microvolts
+        let converter = UnitConverter::new(&config, SignalUnit::Microvolts);
+        // Running analyses and the artifact detector follow the new layout. They are built
+        // before the driver is touched, so one that doesn't fit leaves everything as it was
+        let (analyses, detector) = match self.prepare_followers(&config, &converter).await {
+            Ok(prepared) => prepared,
+            Err(e) => {
+                self.processor.lock().await.reset(previous.sample_rate, previous.channels.len())?;
+                return Err(e);
+            }
+        };
+
+        if config != previous {
+            if let Err(e) = self.replace_driver(config.clone()).await {
+                self.processor.lock().await.reset(previous.sample_rate, previous.channels.len())?;
+                return Err(e);
             }
+        }
+        self.analyses.install(analyses).await;
+        if let Some(detector) = detector {
+            *self.artifacts.lock().await = Some(detector);
+        }
+
+        if run {
+            self.run_pipeline(config, converter).await?;
+        }
+        Ok(())
+    }
+
+    /// Running analyses and artifact detector built afresh for `config`, without putting
+    /// them in place. The processor must already be reset for `config`.
+    async fn prepare_followers(
+        &self,
+        config: &AdcConfig,
+        converter: &UnitConverter,
+    ) -> Result<(RebuiltAnalyses, Option<ArtifactDetector>), Box<dyn Error>> {
+        let layout = stream_layout(config, &*self.processor.lock().await);
+        let analyses = self.analyses.rebuilt(&layout).await?;
+        let detector = self.artifacts.lock().await.as_ref()
+            .map(|detector| ArtifactDetector::new(config.sample_rate, &rails(converter, config.channels.len()), detector.settings().clone()))
+            .transpose()?;
+        Ok((analyses, detector))
+    }
+
+    /// Start acquiring and processing with the driver as configured.
+    async fn run_pipeline(&mut self, config: AdcConfig, converter: UnitConverter) -> Result<(), Box<dyn Error>> {
+        let events = self.take_events()?;
+        if let Err(e) = self.driver.start_acquisition().await {
+            self.event_rx = Some(events);
+            return Err(Box::new(e));
+        }
+        let tracker = SampleTracker::new(config.sample_rate);
+        let (rate_stats, rate_stats_rx) = watch::channel(tracker.stats());
+        self.rate_stats = rate_stats_rx;
+        self.event_loop = Some(EventLoop::spawn(events, Pipeline {
+            config,
+            converter,
+            processor: Arc::clone(&self.processor),
+            artifacts: Arc::clone(&self.artifacts),
+            analyses: self.analyses.clone(),
+            processed: self.processed.clone(),
+            raw: self.raw.clone(),
+            system_events: self.system_events.clone(),
+            tracker,
+            rate_stats,
         }));
+        self.state = SystemState::Running;
+        Ok(())
+    }
+
+    /// The driver's event receiver, while no event loop holds it.
+    fn take_events(&mut self) -> Result<mpsc::Receiver<DriverEvent>, DriverError> {
+        self.event_rx.take().ok_or_else(|| DriverError::Other("Driver event receiver missing".into()))
+    }
+
+    /// Stop acquisition and the event loop, taking the event receiver back with nothing
+    /// left in it. Should the receiver be lost, the driver is rebuilt for a fresh one.
+    async fn halt(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut events = match self.event_loop.take() {
+            Some(event_loop) => event_loop.stop().await,
+            None => self.event_rx.take(),
+        };
 
+        // Keep draining while the driver stops so it can't block on a full channel
+        let stopped = {
+            let stopping = self.driver.stop_acquisition();
+            tokio::pin!(stopping);
+            loop {
+                let Some(events) = events.as_mut() else { break stopping.await };
+                tokio::select! {
+                    result = &mut stopping => break result,
+                    _ = events.recv() => {}
+                }
+            }
+        };
+        if let Some(events) = events.as_mut() {
+            while events.try_recv().is_ok() {}
+        }
+        self.event_rx = events;
+        stopped?;
+
+        if self.event_rx.is_none() {
+            let config = self.driver.get_config().await?;
+            self.rebuild_driver(config).await?;
+        }
         Ok(())
     }
 
-    /// Stop the data acquisition & abort the background task
-    pub async fn stop(&mut self) -> Result<(), Box<dyn Error>> {
-        self.driver.stop_acquisition().await?;
-        if let Some(task) = self.processing_task.take() {
-            task.abort();
+    /// Replace the filter chain while the stream keeps running. The switch happens between
+    /// batches, and stages that are unchanged in `filters` keep their state.
+    pub async fn set_filter_chain(&self, filters: FilterChainSpec) -> Result<(), Box<dyn Error>> {
+        self.processor.lock().await.set_chain(filters)?;
+        Ok(())
+    }
+
+    /// The filter chain currently applied.
+    pub async fn filter_chain(&self) -> FilterChainSpec {
+        self.processor.lock().await.chain().clone()
+    }
+
+    /// Stages of the filter chain that were clipped or dropped because they reach past
+    /// Nyquist at the current sample rate.
+    pub async fn filter_adjustments(&self) -> Vec<StageAdjustment> {
+        self.processor.lock().await.adjustments().to_vec()
+    }
+
+    /// Re-reference the filtered channels, between batches. The output channel count and
+    /// labels follow the reference, and running analyses start afresh on the new layout.
+    pub async fn set_reference(&self, reference: Reference) -> Result<(), Box<dyn Error>> {
+        let config = self.driver.get_config().await?;
+        let mut processor = self.processor.lock().await;
+        processor.set_reference(reference)?;
+        self.analyses.rebuild(&stream_layout(&config, &processor)).await?;
+        Ok(())
+    }
+
+    /// The re-referencing currently applied.
+    pub async fn reference(&self) -> Reference {
+        self.processor.lock().await.reference().clone()
+    }
+
+    /// Electrodes of the channels in `ProcessedData`, as the reference leaves them.
+    pub async fn electrodes(&self) -> Result<Vec<ElectrodeInfo>, Box<dyn Error>> {
+        let config = self.driver.get_config().await?;
+        Ok(output_electrodes(&config, &*self.processor.lock().await))
+    }
+
+    /// Layout of the processed stream, which analyses are built for.
+    async fn output_layout(&self) -> Result<StreamLayout, Box<dyn Error>> {
+        let config = self.driver.get_config().await?;
+        Ok(stream_layout(&config, &*self.processor.lock().await))
+    }
+
+    /// Start or, with `None`, stop flagging artifacts. Masks are then attached to every
+    /// `ProcessedData`, each re-referenced channel carrying those of its inputs.
+    pub async fn set_artifact_detection(&self, settings: Option<ArtifactSettings>) -> Result<(), Box<dyn Error>> {
+        let detector = match settings {
+            Some(settings) => {
+                let config = self.driver.get_config().await?;
+                let converter = UnitConverter::new(&config, SignalUnit::Microvolts);
+                Some(ArtifactDetector::new(config.sample_rate, &rails(&converter, config.channels.len()), settings)?)
+            }
+            None => None,
+        };
+        *self.artifacts.lock().await = detector;
+        Ok(())
+    }
+
+    /// Start or, with `None`, stop estimating band power from the processed stream. Reports
+    /// go to [`EegSystem::subscribe_band_power`] subscribers every hop.
+    pub async fn set_band_power(&self, settings: Option<BandPowerSettings>) -> Result<(), Box<dyn Error>> {
+        self.analyses.band_power.set(&self.output_layout().await?, settings).await?;
+        Ok(())
+    }
+
+    /// Subscribe to band-power reports. Each subscriber sees every report sent after it
+    /// subscribed; one that falls behind skips ahead.
+    pub fn subscribe_band_power(&self) -> broadcast::Receiver<BandPowerReport> {
+        self.analyses.band_power.subscribe()
+    }
+
+    /// Start or, with `None`, stop computing a spectrogram of the processed stream. Frames
+    /// go to [`EegSystem::subscribe_spectrogram`] subscribers every hop.
+    pub async fn set_spectrogram(&self, settings: Option<SpectrogramSettings>) -> Result<(), Box<dyn Error>> {
+        self.analyses.spectrogram.set(&self.output_layout().await?, settings).await?;
+        Ok(())
+    }
+
+    /// Subscribe to spectrogram frames, on a channel of their own. Each subscriber sees
+    /// every frame sent after it subscribed; one that falls behind skips ahead.
+    pub fn subscribe_spectrogram(&self) -> broadcast::Receiver<SpectrogramFrame> {
+        self.analyses.spectrogram.subscribe()
+    }
+
+    /// Start or, with `None`, stop computing the instantaneous amplitude and phase of the
+    /// processed stream. An [`AnalyticFrame`] per batch goes to
+    /// [`EegSystem::subscribe_hilbert`] subscribers, delayed by the Hilbert filter.
+    pub async fn set_hilbert(&self, settings: Option<HilbertSettings>) -> Result<(), Box<dyn Error>> {
+        self.analyses.hilbert.set(&self.output_layout().await?, settings).await?;
+        Ok(())
+    }
+
+    /// Subscribe to analytic-signal frames. Each subscriber sees every frame sent after it
+    /// subscribed; one that falls behind skips ahead.
+    pub fn subscribe_hilbert(&self) -> broadcast::Receiver<AnalyticFrame> {
+        self.analyses.hilbert.subscribe()
+    }
+
+    /// Start or, with `None`, stop the Morlet wavelet transform of the processed stream. A
+    /// [`ScalogramFrame`] per batch goes to [`EegSystem::subscribe_cwt`] subscribers,
+    /// delayed as [`ScalogramFrame::delay_us`] reports.
+    pub async fn set_cwt(&self, settings: Option<CwtSettings>) -> Result<(), Box<dyn Error>> {
+        self.analyses.cwt.set(&self.output_layout().await?, settings).await?;
+        Ok(())
+    }
+
+    /// Subscribe to wavelet frames. Each subscriber sees every frame sent after it
+    /// subscribed; one that falls behind skips ahead.
+    pub fn subscribe_cwt(&self) -> broadcast::Receiver<ScalogramFrame> {
+        self.analyses.cwt.subscribe()
+    }
+
+    /// Switch to impedance-check mode: acquisition restarts with AC lead-off excitation on
+    /// every electrode and a [`SystemEvent::Impedance`] report is published per window until
+    /// [`EegSystem::stop_impedance_check`]. No `ProcessedData` is produced meanwhile.
+    pub async fn start_impedance_check(&mut self, settings: ImpedanceSettings) -> Result<(), Box<dyn Error>> {
+        match self.state {
+            SystemState::CheckingImpedance => return Err(Box::new(DriverError::ConfigurationError(
+                "Impedance check already running".into()
+            ))),
+            SystemState::ShutDown => return Err(Box::new(DriverError::ConfigurationError(
+                "System has been shut down".into()
+            ))),
+            SystemState::Idle | SystemState::Running => {}
+        }
+
+        if !self.driver.capabilities().await.impedance_measurement {
+            return Err(Box::new(DriverError::ConfigurationError(
+                "Driver cannot measure impedance".into()
+            )));
         }
+        let config = self.driver.get_config().await?;
+        let excited = settings.apply(&config)?;
+        let meter = ImpedanceMeter::new(&excited, &settings)?;
+        let was_processing = self.state == SystemState::Running;
+
+        self.halt().await?;
+        self.state = SystemState::Idle;
+        if let Err(e) = self.run_impedance_check(excited, meter).await {
+            // Back to where the check started from; the failure to start is what's reported
+            if let Err(restore) = self.restore_config(config, was_processing).await {
+                warn!("Configuration not restored after the impedance check failed to start: {}", restore);
+            }
+            return Err(e);
+        }
+        self.impedance_restore = Some((config, was_processing));
+        self.state = SystemState::CheckingImpedance;
         Ok(())
     }
 
-    /// Reconfigure the driver with new settings, resetting the processor
-    pub async fn reconfigure(&mut self, config: AdcConfig) -> Result<(), Box<dyn Error>> {
-        self.initialize_processing(config).await
+    /// Bring the halted driver to the `excited` configuration and feed its events to `meter`.
+    async fn run_impedance_check(&mut self, excited: AdcConfig, meter: ImpedanceMeter) -> Result<(), Box<dyn Error>> {
+        self.replace_driver(excited).await?;
+        let events = self.take_events()?;
+        if let Err(e) = self.driver.start_acquisition().await {
+            self.event_rx = Some(events);
+            return Err(Box::new(e));
+        }
+        self.event_loop = Some(EventLoop::spawn(events, ImpedanceCheck {
+            meter,
+            system_events: self.system_events.clone(),
+        }));
+        Ok(())
+    }
+
+    /// Leave impedance-check mode, restoring the previous configuration and resuming
+    /// processing if it was running before the check.
+    pub async fn stop_impedance_check(&mut self) -> Result<(), Box<dyn Error>> {
+        let Some((config, was_processing)) = self.impedance_restore.take() else {
+            return Ok(());
+        };
+        self.restore_config(config, was_processing).await
+    }
+
+    /// Stop whatever runs and bring the driver back to `config`, resuming processing if
+    /// `resume`.
+    async fn restore_config(&mut self, config: AdcConfig, resume: bool) -> Result<(), Box<dyn Error>> {
+        self.halt().await?;
+        self.state = SystemState::Idle;
+        self.replace_driver(config.clone()).await?;
+        if resume {
+            let converter = UnitConverter::new(&config, SignalUnit::Microvolts);
+            self.run_pipeline(config, converter).await?;
+        }
+        Ok(())
+    }
+
+    /// Run an impedance check for a single window and return its report.
+    pub async fn measure_impedance(&mut self, settings: ImpedanceSettings) -> Result<ImpedanceReport, Box<dyn Error>> {
+        // Generous bound: one window plus time to bring the driver up and down
+        let timeout = Duration::from_secs_f32(settings.window_secs * 2.0 + 5.0);
+        let mut events = self.subscribe_events();
+        if let Err(e) = self.start_impedance_check(settings).await {
+            // Never leave the excited configuration behind, however far the start got
+            self.stop_impedance_check().await?;
+            return Err(e);
+        }
+
+        let report = tokio::time::timeout(timeout, async {
+            loop {
+                match events.recv().await {
+                    Ok(SystemEvent::Impedance(report)) => return Some(report),
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        }).await;
+
+        self.stop_impedance_check().await?;
+        match report {
+            Ok(Some(report)) => Ok(report),
+            Ok(None) => Err(Box::new(DriverError::Other("System event channel closed".into()))),
+            Err(_) => Err(Box::new(DriverError::Other("Impedance measurement timed out".into()))),
+        }
+    }
+
+    /// Whether an impedance check is running.
+    pub fn is_checking_impedance(&self) -> bool {
+        self.state == SystemState::CheckingImpedance
+    }
+
+    /// Bring the halted driver to `config`: reconfigured in place if it is the same kind
+    /// of driver, keeping its event stream, or else replaced.
+    async fn replace_driver(&mut self, config: AdcConfig) -> Result<(), Box<dyn Error>> {
+        if config.board_driver == self.driver.get_config().await?.board_driver {
+            self.driver.configure(config).await?;
+            return Ok(());
+        }
+        self.rebuild_driver(config).await
+    }
+
+    /// Shut the halted driver down and replace it with a fresh one for `config`. Should
+    /// that fail, a driver with the previous configuration is brought back.
+    async fn rebuild_driver(&mut self, config: AdcConfig) -> Result<(), Box<dyn Error>> {
+        let previous = self.driver.get_config().await?;
+        self.driver.shutdown().await?;
+        self.event_rx = None;
+        match self.create_driver(config).await {
+            Ok((driver, event_rx)) => {
+                self.driver = driver;
+                self.event_rx = Some(event_rx);
+                Ok(())
+            }
+            Err(e) => {
+                match self.create_driver(previous).await {
+                    Ok((driver, event_rx)) => {
+                        self.driver = driver;
+                        self.event_rx = Some(event_rx);
+                    }
+                    Err(_) => self.state = SystemState::ShutDown,
+                }
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    /// A fresh driver for `config`; a mock one keeps simulating the system's mock settings.
+    async fn create_driver(&self, config: AdcConfig) -> Result<(Box<dyn AdcDriver>, mpsc::Receiver<DriverEvent>), DriverError> {
+        if config.board_driver != DriverType::Mock {
+            return create_driver(config).await;
+        }
+        let (driver, event_rx) = MockDriver::with_settings(config, self.mock_settings.clone(), 0)?;
+        Ok((Box::new(driver), event_rx))
+    }
+
+    /// Subscribe to the processed stream, at any time, with a queue of `capacity` batches
+    /// of its own. What happens when the queue is full is up to `backpressure`; a
+    /// subscriber that falls behind sees how much it missed in its stats.
+    pub fn subscribe_processed(&self, backpressure: Backpressure, capacity: usize) -> Subscription<ProcessedData> {
+        self.processed.subscribe(backpressure, capacity)
+    }
+
+    /// How each subscriber to the processed stream is keeping up.
+    pub fn processed_subscribers(&self) -> Vec<SubscriberStats> {
+        self.processed.stats()
+    }
+
+    /// Subscribe to the driver's batches as they come, before any processing, like
+    /// [`EegSystem::subscribe_processed`]. Each `RawData` starts at the same sample index
+    /// as the `ProcessedData` made from it, and repeated samples are left out of both.
+    /// Nothing is sent during an impedance check.
+    pub fn subscribe_raw(&self, backpressure: Backpressure, capacity: usize) -> Subscription<RawData> {
+        self.raw.subscribe(backpressure, capacity)
+    }
+
+    /// How each subscriber to the raw stream is keeping up.
+    pub fn raw_subscribers(&self) -> Vec<SubscriberStats> {
+        self.raw.stats()
+    }
+
+    /// How many samples came in, went missing or came twice since processing last
+    /// started, and the rate they arrived at against the configured one. Drift of the
+    /// ADC clock against the system clock, or lost reads, show up as a difference.
+    pub fn sample_rate_stats(&self) -> SampleRateStats {
+        *self.rate_stats.borrow()
+    }
+
+    /// Subscribe to system events such as lead-off changes. Each subscriber sees
+    /// every event sent after it subscribed.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<SystemEvent> {
+        self.system_events.subscribe()
     }
 
     /// Retrieve the current driver status
@@ -150,6 +648,11 @@ impl EegSystem {
         self.driver.get_status().await
     }
 
+    /// What the driver and its hardware support, to check configurations against
+    pub async fn capabilities(&self) -> DriverCapabilities {
+        self.driver.capabilities().await
+    }
+
     /// Retrieve the driver's configuration
     pub async fn driver_config(&self) -> Result<AdcConfig, DriverError> {
         self.driver.get_config().await
@@ -164,12 +667,17 @@ impl EegSystem {
     pub async fn shutdown(&mut self) -> Result<(), Box<dyn Error>> {
         // Add timeout for safety
         const SHUTDOWN_TIMEOUT_MS: u64 = 1000;
-        
+
+        if self.state == SystemState::ShutDown {
+            return Ok(());
+        }
         let shutdown_future = async {
             // Convert the Box<dyn Error> to DriverError
-            if let Err(e) = self.stop().await {
+            if let Err(e) = self.halt().await {
                 return Err(DriverError::Other(e.to_string()));
             }
+            self.impedance_restore = None;
+            self.state = SystemState::ShutDown;
             self.driver.shutdown().await
         };
         
@@ -183,6 +691,29 @@ impl EegSystem {
     }
 }
 
+/// Electrodes of the output channels of `processor`: each is labelled by the reference
+/// and otherwise described by the electrode it is centred on.
+fn output_electrodes(config: &AdcConfig, processor: &SignalProcessor) -> Vec<ElectrodeInfo> {
+    let inputs: Vec<ElectrodeInfo> = (0..config.channels.len()).map(|i| config.electrode_for(i)).collect();
+    let labels: Vec<String> = inputs.iter().map(|electrode| electrode.label.clone()).collect();
+    processor.output_sources().into_iter().zip(processor.output_labels(&labels))
+        .map(|(source, label)| ElectrodeInfo { label, ..inputs[source].clone() })
+        .collect()
+}
+
+/// Lowest and highest reading of each of `count` channels.
+fn rails(converter: &UnitConverter, count: usize) -> Vec<(f32, f32)> {
+    (0..count).map(|channel| converter.range(channel)).collect()
+}
+
+fn stream_layout(config: &AdcConfig, processor: &SignalProcessor) -> StreamLayout {
+    StreamLayout {
+        sample_rate: config.sample_rate,
+        channels: processor.output_sources().iter().map(|&i| config.channels[i]).collect(),
+        labels: output_electrodes(config, processor).into_iter().map(|electrode| electrode.label).collect(),
+    }
+}
+
 impl Drop for EegSystem {
     fn drop(&mut self) {
         // Since we can't use .await in Drop, we'll just log a warning
@@ -190,3 +721,6 @@ impl Drop for EegSystem {
         eprintln!("Always call system.shutdown().await before dropping the system");
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,110 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use log::warn;
+use tokio::sync::{broadcast, watch, Mutex};
+
+use crate::board_driver::{AdcConfig, AdcData, DriverEvent, DriverStatus};
+use crate::dsp::artifacts::{derive_masks, ArtifactDetector};
+use crate::dsp::filters::SignalProcessor;
+use crate::dsp::units::UnitConverter;
+use super::analysis::Analyses;
+use super::event_loop::EventHandler;
+use super::fanout::FanOut;
+use super::sample_tracker::{SampleRateStats, SampleTracker};
+use super::{output_electrodes, ProcessedData, RawData, SystemEvent};
+
+/// Turns the driver's batches into `ProcessedData`: unit conversion, artifact detection,
+/// filters and re-referencing, then the analyses of the result. The batches also go out
+/// unprocessed as `RawData`. Breaks in the sample indices are reported as
+/// [`SystemEvent::DataGap`], and repeated samples left out of both streams.
+pub(crate) struct Pipeline {
+    pub config: AdcConfig,
+    pub converter: UnitConverter,
+    pub processor: Arc<Mutex<SignalProcessor>>,
+    pub artifacts: Arc<Mutex<Option<ArtifactDetector>>>,
+    pub analyses: Analyses,
+    pub processed: FanOut<ProcessedData>,
+    pub raw: FanOut<RawData>,
+    pub system_events: broadcast::Sender<SystemEvent>,
+    pub tracker: SampleTracker,
+    /// Where the tracker's figures are published after each batch
+    pub rate_stats: watch::Sender<SampleRateStats>,
+}
+
+impl Pipeline {
+    /// Process one batch and hand it to every subscriber.
+    async fn process(&mut self, data_batch: Vec<AdcData>) {
+        let (data_batch, gaps) = self.tracker.push(data_batch);
+        for gap in gaps {
+            warn!("Sample index jumped from {} to {}", gap.expected, gap.received);
+            // Sending only fails when nobody is subscribed
+            let _ = self.system_events.send(SystemEvent::DataGap(gap));
+        }
+        // Fails only when nobody holds the receiver
+        let _ = self.rate_stats.send(self.tracker.stats());
+
+        let (Some(first), Some(last)) = (data_batch.first(), data_batch.last()) else { return };
+        let first_sample = first.sample_index;
+        let timestamp = last.timestamp;
+
+        // Gather each channel's samples so the filters see the batch as one block
+        let mut raw_channels: Vec<Vec<f32>> = vec![Vec::with_capacity(data_batch.len()); data_batch[0].samples.len()];
+        for data in &data_batch {
+            for (ch_idx, channel_samples) in data.samples.iter().enumerate() {
+                raw_channels[ch_idx].extend(channel_samples.iter().map(|&sample| self.converter.convert(ch_idx, sample)));
+            }
+        }
+
+        let input_masks = self.artifacts.lock().await.as_mut().map(|detector| detector.push(&raw_channels));
+
+        // Single lock acquisition for the batch, held while the analyses run so a change
+        // of reference can't come between them and the filters
+        let mut proc_guard = self.processor.lock().await;
+        let processed_channels = proc_guard.process_channels(&raw_channels);
+        let group_delay_us = (proc_guard.group_delay_secs() as f64 * 1e6).round() as u64;
+        let electrodes = output_electrodes(&self.config, &proc_guard);
+        let artifact_masks = input_masks
+            .map(|masks| derive_masks(&masks, &proc_guard.output_contributors()))
+            .unwrap_or_default();
+        self.analyses.push(&processed_channels, timestamp).await;
+        drop(proc_guard);
+
+        if self.raw.has_subscribers() {
+            self.raw.send(RawData {
+                samples: data_batch,
+                first_sample,
+                channels: self.config.channels.clone(),
+                electrodes: (0..self.config.channels.len()).map(|i| self.config.electrode_for(i)).collect(),
+            }).await;
+        }
+        self.processed.send(ProcessedData {
+            channel_count: processed_channels.len(),
+            electrodes,
+            artifacts: artifact_masks,
+            data: processed_channels,
+            timestamp,
+            unit: self.converter.unit(),
+            group_delay_us,
+            first_sample,
+        }).await;
+    }
+}
+
+#[async_trait]
+impl EventHandler for Pipeline {
+    async fn handle(&mut self, event: DriverEvent) -> bool {
+        match event {
+            DriverEvent::Data(batch) => {
+                self.process(batch).await;
+                true
+            }
+            DriverEvent::LeadOff(status) => {
+                // Sending only fails when nobody is subscribed
+                let _ = self.system_events.send(SystemEvent::LeadOff(status));
+                true
+            }
+            DriverEvent::StatusChange(DriverStatus::Stopped) => false,
+            _ => true,
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use serde::{Serialize, Deserialize};
+
+use crate::board_driver::AdcData;
+
+/// A break in the sample indices the driver delivered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataGap {
+    /// Index the sample should have had
+    pub expected: u64,
+    /// Index it had
+    pub received: u64,
+    /// Samples skipped, when the index jumped ahead
+    pub missing: u64,
+    /// Samples delivered again, when the index went back. Repeats are left out of the
+    /// processed stream.
+    pub duplicated: u64,
+    /// Timestamp of the sample after the break
+    pub timestamp: u64,
+}
+
+/// Sample rate the stream actually arrives at, against the configured one.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SampleRateStats {
+    pub nominal_hz: f32,
+    /// Samples received per second of timestamps since the first batch; 0 until there is
+    /// a second batch
+    pub effective_hz: f32,
+    /// Samples received, repeats left out
+    pub received: u64,
+    pub missing: u64,
+    pub duplicated: u64,
+}
+
+impl SampleRateStats {
+    /// How far the effective rate is off the nominal one, in parts per million.
+    pub fn deviation_ppm(&self) -> f32 {
+        if self.effective_hz == 0.0 {
+            return 0.0;
+        }
+        (self.effective_hz / self.nominal_hz - 1.0) * 1e6
+    }
+}
+
+/// Follows the sample indices of the stream, flagging breaks in them and measuring the
+/// rate samples arrive at.
+#[derive(Debug)]
+pub(crate) struct SampleTracker {
+    nominal_hz: u32,
+    /// Index the next sample should have
+    expected: Option<u64>,
+    /// Timestamp of the first batch
+    first_timestamp: Option<u64>,
+    last_timestamp: u64,
+    /// Samples received after the first batch
+    received_since_first: u64,
+    received: u64,
+    missing: u64,
+    duplicated: u64,
+}
+
+impl SampleTracker {
+    pub(crate) fn new(nominal_hz: u32) -> Self {
+        Self {
+            nominal_hz,
+            expected: None,
+            first_timestamp: None,
+            last_timestamp: 0,
+            received_since_first: 0,
+            received: 0,
+            missing: 0,
+            duplicated: 0,
+        }
+    }
+
+    /// Check a batch against the indices seen so far. Returns the batch without repeated
+    /// samples, and every break found in it.
+    pub(crate) fn push(&mut self, batch: Vec<AdcData>) -> (Vec<AdcData>, Vec<DataGap>) {
+        let mut kept = Vec::with_capacity(batch.len());
+        let mut gaps = Vec::new();
+        // Report a repeated run once, when the index first goes back
+        let mut repeating = false;
+        for sample in batch {
+            let index = sample.sample_index;
+            match self.expected {
+                Some(expected) if index < expected => {
+                    if !repeating {
+                        gaps.push(DataGap { expected, received: index, missing: 0, duplicated: expected - index, timestamp: sample.timestamp });
+                        repeating = true;
+                    }
+                    self.duplicated += 1;
+                    continue;
+                }
+                Some(expected) if index > expected => {
+                    gaps.push(DataGap { expected, received: index, missing: index - expected, duplicated: 0, timestamp: sample.timestamp });
+                    self.missing += index - expected;
+                }
+                _ => {}
+            }
+            repeating = false;
+            self.expected = Some(index + 1);
+            kept.push(sample);
+        }
+
+        if let Some(last) = kept.last() {
+            self.received += kept.len() as u64;
+            match self.first_timestamp {
+                None => self.first_timestamp = Some(last.timestamp),
+                Some(_) => self.received_since_first += kept.len() as u64,
+            }
+            self.last_timestamp = last.timestamp;
+        }
+        (kept, gaps)
+    }
+
+    pub(crate) fn stats(&self) -> SampleRateStats {
+        let elapsed_us = self.first_timestamp.map_or(0, |first| self.last_timestamp.saturating_sub(first));
+        SampleRateStats {
+            nominal_hz: self.nominal_hz as f32,
+            effective_hz: if elapsed_us == 0 { 0.0 } else { (self.received_since_first as f64 * 1e6 / elapsed_us as f64) as f32 },
+            received: self.received,
+            missing: self.missing,
+            duplicated: self.duplicated,
+        }
+    }
+}
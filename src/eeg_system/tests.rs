@@ -1,9 +1,29 @@
 use super::*;
+use std::error::Error;
 use std::time::Duration;
 use tokio::time::sleep;
+use crate::board_driver::tests::MOCK_HARDWARE;
+use crate::board_driver::AdcData;
+use crate::board_driver::{ElectrodeInfo, ElectrodeType, MockArtifact, MockDriver, MockSettings, Montage, ScriptedArtifact};
+use crate::board_driver::mock_driver::MOCK_AMPLITUDE_UV;
+use crate::dsp::artifacts::{Artifact, ArtifactMask, ArtifactSettings};
+use crate::dsp::filters::FilterStage;
+use crate::dsp::hilbert::HilbertSettings;
+use crate::dsp::DspError;
+use crate::dsp::psd::{MultitaperSettings, WelchSettings};
+use crate::dsp::reference::Reference;
+use crate::dsp::spectrogram::SpectrogramSettings;
+use crate::dsp::spectrum::{BandPowerSettings, FrequencyBins};
+use super::sample_tracker::SampleTracker;
+
+/// System on a mock driver simulating what `settings` script, with the default filters.
+async fn mock_system(config: AdcConfig, settings: MockSettings) -> Result<(EegSystem, Subscription<ProcessedData>), Box<dyn Error>> {
+    EegSystem::with_mock(config, settings, FilterChainSpec::default()).await
+}
 
 #[tokio::test]
 async fn test_eeg_system_lifecycle() -> Result<(), Box<dyn Error>> {
+    let _hardware = MOCK_HARDWARE.lock().await;
     // Create a basic configuration
     let config = AdcConfig {
         sample_rate: 250,
@@ -13,14 +33,14 @@ async fn test_eeg_system_lifecycle() -> Result<(), Box<dyn Error>> {
     };
 
     // Create system with mock driver
-    let (mut system, mut rx) = EegSystem::new(config.clone(), false).await?;
+    let (mut system, mut rx) = EegSystem::new(config.clone(), FilterChainSpec::default()).await?;
     
     // Check initial state
-    assert_eq!(system.driver_status(), DriverStatus::NotInitialized);
+    assert_eq!(system.driver_status().await, DriverStatus::Ok);
     
     // Start the system
     system.start(config.clone()).await?;
-    assert_eq!(system.driver_status(), DriverStatus::Running);
+    assert_eq!(system.driver_status().await, DriverStatus::Running);
     
     // Wait briefly to collect some data
     let timeout = Duration::from_millis(100);
@@ -40,7 +60,7 @@ async fn test_eeg_system_lifecycle() -> Result<(), Box<dyn Error>> {
                 sleep(Duration::from_millis(10)).await;
                 continue;
             }
-            Err(e) => return Err(Box::new(DriverError::Other(format!("Receive error: {}", e)))),
+            Err(e) => return Err(DriverError::Other(format!("Receive error: {}", e)).into()),
         }
     }
     
@@ -48,17 +68,18 @@ async fn test_eeg_system_lifecycle() -> Result<(), Box<dyn Error>> {
     
     // Test stopping
     system.stop().await?;
-    assert_eq!(system.driver_status(), DriverStatus::Stopped);
+    assert_eq!(system.driver_status().await, DriverStatus::Stopped);
     
     // Test shutdown
     system.shutdown().await?;
-    assert_eq!(system.driver_status(), DriverStatus::NotInitialized);
+    assert_eq!(system.driver_status().await, DriverStatus::NotInitialized);
     
     Ok(())
 }
 
 #[tokio::test]
 async fn test_eeg_system_reconfigure() -> Result<(), Box<dyn Error>> {
+    let _hardware = MOCK_HARDWARE.lock().await;
     let initial_config = AdcConfig {
         sample_rate: 250,
         channels: vec![0],
@@ -66,8 +87,9 @@ async fn test_eeg_system_reconfigure() -> Result<(), Box<dyn Error>> {
         ..Default::default()
     };
 
-    let (mut system, _rx) = EegSystem::new(initial_config.clone(), false).await?;
+    let (mut system, mut rx) = EegSystem::new(initial_config.clone(), FilterChainSpec::default()).await?;
     system.start(initial_config).await?;
+    assert!(rx.recv().await.is_some());
     
     // Test reconfiguration with different settings
     let new_config = AdcConfig {
@@ -80,26 +102,145 @@ async fn test_eeg_system_reconfigure() -> Result<(), Box<dyn Error>> {
     system.reconfigure(new_config.clone()).await?;
     
     // Verify new configuration took effect
-    let current_config = system.driver_config()?;
+    let current_config = system.driver_config().await?;
     assert_eq!(current_config.sample_rate, new_config.sample_rate);
     assert_eq!(current_config.channels.len(), new_config.channels.len());
+    assert_eq!(current_config.gain, 2.0);
+
+    // Processing resumed on the new driver
+    assert_eq!(system.state(), SystemState::Running);
+    assert_eq!(system.driver_status().await, DriverStatus::Running);
+    let data = tokio::time::timeout(Duration::from_secs(2), rx.recv()).await?.expect("data");
+    assert_eq!(data.channel_count, 2);
+
+    // What the driver can't do is refused up front, without interrupting the stream
+    assert!(!system.capabilities().await.gains.contains(&3.0));
+    assert!(system.reconfigure(AdcConfig { gain: 3.0, ..new_config.clone() }).await.is_err());
+    assert_eq!(system.state(), SystemState::Running);
+    assert_eq!(system.driver_config().await?, new_config);
     
     system.shutdown().await?;
     Ok(())
 }
 
+#[tokio::test]
+async fn test_reconfigure_rejected_by_running_analysis() -> Result<(), Box<dyn Error>> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let config = AdcConfig { sample_rate: 500, channels: vec![0, 1], batch_size: 50, ..Default::default() };
+    let (mut system, mut rx) = EegSystem::new(config.clone(), FilterChainSpec::default()).await?;
+    let cwt = CwtSettings { frequencies: vec![10.0, 200.0], cycles: 5.0 };
+    system.set_cwt(Some(cwt.clone())).await?;
+    system.start(config.clone()).await?;
+    assert!(rx.recv().await.is_some());
+
+    // 200 Hz is past Nyquist at 250 Hz: the driver keeps its configuration
+    let slower = AdcConfig { sample_rate: 250, channels: vec![0, 1, 2], ..config.clone() };
+    assert!(system.reconfigure(slower).await.is_err());
+    assert_eq!(system.state(), SystemState::Idle);
+    assert_eq!(system.driver_config().await?, config);
+
+    // and the processor and wavelets carry on as before once restarted
+    let mut frames = system.subscribe_cwt();
+    system.start(config).await?;
+    while rx.try_recv().is_ok() {}
+    let data = tokio::time::timeout(Duration::from_secs(2), rx.recv()).await?.expect("data");
+    assert_eq!(data.channel_count, 2);
+    let frame = tokio::time::timeout(Duration::from_secs(2), frames.recv()).await??;
+    assert_eq!(frame.frequencies, cwt.frequencies);
+    assert_eq!(frame.amplitude.len(), 2);
+
+    let drain = tokio::spawn(async move { while rx.recv().await.is_some() {} });
+    system.shutdown().await?;
+    drain.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_repeated_start_stop_reconfigure_cycles() -> Result<(), Box<dyn Error>> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let config = AdcConfig { channels: vec![0, 1], ..Default::default() };
+    let (mut system, mut rx) = EegSystem::new(config.clone(), FilterChainSpec::default()).await?;
+    assert_eq!(system.state(), SystemState::Idle);
+
+    for cycle in 0..3 {
+        // Starting twice restarts rather than failing
+        system.start(config.clone()).await?;
+        system.start(config.clone()).await?;
+        assert_eq!(system.state(), SystemState::Running);
+        let data = tokio::time::timeout(Duration::from_secs(2), rx.recv()).await?.expect("data");
+        assert_eq!(data.channel_count, 2, "cycle {}", cycle);
+
+        // Reconfiguring while running resumes with the new layout
+        let wider = AdcConfig { channels: vec![0, 1, 2], sample_rate: 500, ..config.clone() };
+        system.reconfigure(wider).await?;
+        assert_eq!(system.state(), SystemState::Running);
+        let data = loop {
+            let data = tokio::time::timeout(Duration::from_secs(2), rx.recv()).await?.expect("data");
+            if data.channel_count == 3 {
+                break data;
+            }
+        };
+        assert!(data.data.iter().all(|channel| channel.iter().all(|x| x.is_finite())));
+
+        system.stop().await?;
+        system.stop().await?;
+        assert_eq!(system.state(), SystemState::Idle);
+        assert_eq!(system.driver_status().await, DriverStatus::Stopped);
+        while rx.try_recv().is_ok() {}
+
+        // Reconfiguring while idle stays idle
+        system.reconfigure(config.clone()).await?;
+        assert_eq!(system.state(), SystemState::Idle);
+        assert_eq!(system.driver_config().await?.channels, config.channels);
+    }
+
+    // A rejected configuration leaves the previous one in place
+    let invalid = AdcConfig { calibration: vec![Default::default()], ..config.clone() };
+    assert!(system.reconfigure(invalid).await.is_err());
+    assert_eq!(system.driver_config().await?, config);
+    system.start(config.clone()).await?;
+    assert!(tokio::time::timeout(Duration::from_secs(2), rx.recv()).await?.is_some());
+
+    system.shutdown().await?;
+    assert_eq!(system.state(), SystemState::ShutDown);
+    assert!(system.start(config).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stop_does_not_wait_for_the_consumer() -> Result<(), Box<dyn Error>> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let config = AdcConfig { batch_size: 4, ..Default::default() };
+    let (mut system, _rx) = EegSystem::new(config.clone(), FilterChainSpec::default()).await?;
+    system.start(config.clone()).await?;
+    // Nobody reads, so the processed channel fills up and processing blocks
+    sleep(Duration::from_millis(2500)).await;
+
+    tokio::time::timeout(Duration::from_secs(2), system.stop()).await??;
+    assert_eq!(system.state(), SystemState::Idle);
+    system.start(config).await?;
+    system.shutdown().await?;
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_error_handling() -> Result<(), Box<dyn Error>> {
+    let _hardware = MOCK_HARDWARE.lock().await;
     // Test invalid configuration
     let invalid_config = AdcConfig {
         sample_rate: 0, // Invalid sample rate
-        channels: vec![],
+        channels: vec![0],
         gain: 1.0,
         ..Default::default()
     };
 
-    let (mut system, _rx) = EegSystem::new(invalid_config.clone(), false).await?;
-    
+    // The filter chain cannot be built, reported as an error rather than a panic
+    let result = EegSystem::new(invalid_config.clone(), FilterChainSpec::default()).await;
+    assert!(matches!(result, Err(ref e) if e.downcast_ref::<DspError>() == Some(&DspError::InvalidSampleRate)));
+
+    let valid_config = AdcConfig { sample_rate: 250, ..invalid_config.clone() };
+    let (mut system, _rx) = EegSystem::new(valid_config, FilterChainSpec::default()).await?;
+
     // Should fail with appropriate error
     let result = system.start(invalid_config).await;
     assert!(result.is_err());
@@ -108,11 +249,44 @@ async fn test_error_handling() -> Result<(), Box<dyn Error>> {
         assert!(e.to_string().contains("Sample rate must be greater than 0"));
     }
     
+    system.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_low_sample_rate_fits_filters_below_nyquist() -> Result<(), Box<dyn Error>> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let config = AdcConfig {
+        sample_rate: 250,
+        channels: vec![0],
+        gain: 1.0,
+        ..Default::default()
+    };
+
+    let (mut system, mut rx) = EegSystem::new(config.clone(), FilterChainSpec::default()).await?;
+    assert!(system.filter_adjustments().await.is_empty());
+
+    // At 125 Hz the 100 Hz low-pass has nothing left to remove
+    let low_rate = AdcConfig { sample_rate: 125, ..config };
+    system.reconfigure(low_rate.clone()).await?;
+    assert_eq!(system.state(), SystemState::Idle);
+    let adjustments = system.filter_adjustments().await;
+    assert_eq!(adjustments.len(), 1);
+    assert_eq!(adjustments[0].index, 3);
+    assert_eq!(adjustments[0].realized, None);
+    assert_eq!(system.filter_chain().await, FilterChainSpec::default());
+
+    system.start(low_rate).await?;
+    let data = tokio::time::timeout(Duration::from_secs(2), rx.recv()).await?.expect("Processed data");
+    assert!(data.data[0].iter().all(|x| x.is_finite()));
+
+    system.shutdown().await?;
     Ok(())
 }
 
 #[tokio::test]
 async fn test_signal_processing() -> Result<(), Box<dyn Error>> {
+    let _hardware = MOCK_HARDWARE.lock().await;
     let config = AdcConfig {
         sample_rate: 250,
         channels: vec![0],
@@ -120,7 +294,7 @@ async fn test_signal_processing() -> Result<(), Box<dyn Error>> {
         ..Default::default()
     };
 
-    let (mut system, mut rx) = EegSystem::new(config.clone(), false).await?;
+    let (mut system, mut rx) = EegSystem::new(config.clone(), FilterChainSpec::default()).await?;
     system.start(config).await?;
     
     // Collect some processed data
@@ -130,6 +304,9 @@ async fn test_signal_processing() -> Result<(), Box<dyn Error>> {
     
     while start.elapsed() < timeout {
         if let Ok(data) = rx.try_recv() {
+            assert_eq!(data.unit, SignalUnit::Microvolts);
+            // The default chain is all IIR, so no FIR delay to correct for
+            assert_eq!(data.group_delay_us, 0);
             samples.extend(data.data[0].clone()); // Get channel 0 data
             if samples.len() >= 50 {
                 break;
@@ -151,6 +328,570 @@ async fn test_signal_processing() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-// Add this to src/eeg_system.rs:
-#[cfg(test)]
-mod tests; 
\ No newline at end of file
+#[tokio::test]
+async fn test_impedance_check_against_mock() -> Result<(), Box<dyn Error>> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let config = AdcConfig {
+        sample_rate: 250,
+        channels: vec![0, 1, 2],
+        gain: 24.0,
+        batch_size: 50,
+        ..Default::default()
+    };
+    let settings = MockSettings { impedance_kohms: vec![5.0, 20.0, 50.0], ..MockSettings::default() };
+    let (mut system, mut rx) = mock_system(config.clone(), settings).await?;
+    system.start(config.clone()).await?;
+    assert!(rx.recv().await.is_some());
+
+    let settings = ImpedanceSettings { window_secs: 0.5, ..ImpedanceSettings::default() };
+    let report = system.measure_impedance(settings).await?;
+    for (channel, expected) in [(0, 5.0), (1, 20.0), (2, 50.0)] {
+        let kohms = report.kohms(channel).expect("every electrode measured");
+        assert!((kohms - expected).abs() < expected * 0.02, "channel {}: {} kOhm, expected {}", channel, kohms, expected);
+    }
+
+    // The original configuration is back, without excitation, and processing resumed
+    assert!(!system.is_checking_impedance());
+    assert!(system.driver_config().await?.lead_off.is_none());
+    while rx.try_recv().is_ok() {}
+    assert!(rx.recv().await.is_some());
+
+    assert!(system.measure_impedance(ImpedanceSettings {
+        frequency: crate::board_driver::ads1299_registers::LeadOffFrequency::Dc,
+        ..ImpedanceSettings::default()
+    }).await.is_err());
+
+    system.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_settings_survive_a_rebuilt_driver() -> Result<(), Box<dyn Error>> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let config = AdcConfig { channels: vec![0, 1], gain: 24.0, ..Default::default() };
+    let settings = MockSettings { impedance_kohms: vec![5.0, 20.0], ..MockSettings::default() };
+    let (mut system, _rx) = mock_system(config.clone(), settings).await?;
+
+    // No ADS1299 here, so the mock is shut down and brought back
+    assert!(system.reconfigure(AdcConfig { board_driver: DriverType::Ads1299, ..config.clone() }).await.is_err());
+    assert_eq!(system.driver_config().await?.board_driver, DriverType::Mock);
+
+    let report = system.measure_impedance(ImpedanceSettings { window_secs: 0.5, ..ImpedanceSettings::default() }).await?;
+    for (channel, expected) in [(0, 5.0), (1, 20.0)] {
+        let kohms = report.kohms(channel).expect("every electrode measured");
+        assert!((kohms - expected).abs() < expected * 0.02, "channel {}: {} kOhm, expected {}", channel, kohms, expected);
+    }
+
+    system.shutdown().await?;
+    Ok(())
+}
+
+/// Mock that refuses to start acquisition with lead-off excitation on.
+struct NoExcitationDriver(MockDriver);
+
+#[async_trait::async_trait]
+impl AdcDriver for NoExcitationDriver {
+    async fn start_acquisition(&mut self) -> Result<(), DriverError> {
+        if self.0.get_config().await?.lead_off.is_some() {
+            return Err(DriverError::AcquisitionError("Excitation not available".to_string()));
+        }
+        self.0.start_acquisition().await
+    }
+    async fn stop_acquisition(&mut self) -> Result<(), DriverError> { self.0.stop_acquisition().await }
+    async fn shutdown(&mut self) -> Result<(), DriverError> { self.0.shutdown().await }
+    async fn get_config(&self) -> Result<AdcConfig, DriverError> { self.0.get_config().await }
+    async fn get_status(&self) -> DriverStatus { self.0.get_status().await }
+    async fn configure(&mut self, config: AdcConfig) -> Result<(), DriverError> { self.0.configure(config).await }
+    async fn capabilities(&self) -> DriverCapabilities { self.0.capabilities().await }
+}
+
+#[tokio::test]
+async fn test_impedance_check_that_fails_to_start() -> Result<(), Box<dyn Error>> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let config = AdcConfig { channels: vec![0, 1], batch_size: 25, ..Default::default() };
+    let (driver, events) = MockDriver::with_settings(config.clone(), MockSettings::default(), 0)?;
+    let (mut system, mut rx) = EegSystem::with_driver(Box::new(NoExcitationDriver(driver)), events, FilterChainSpec::default()).await?;
+    system.start(config.clone()).await?;
+    assert!(rx.recv().await.is_some());
+
+    assert!(system.start_impedance_check(ImpedanceSettings::default()).await.is_err());
+    // Not left in the check, and back on the original configuration with processing resumed
+    assert!(!system.is_checking_impedance());
+    assert_eq!(system.state(), SystemState::Running);
+    assert!(system.driver_config().await?.lead_off.is_none());
+    while rx.try_recv().is_ok() {}
+    assert!(rx.recv().await.is_some());
+
+    assert!(system.measure_impedance(ImpedanceSettings::default()).await.is_err());
+    assert!(!system.is_checking_impedance());
+    assert_eq!(system.state(), SystemState::Running);
+    assert!(system.driver_config().await?.lead_off.is_none());
+
+    system.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_filter_chain_swapped_while_streaming() -> Result<(), Box<dyn Error>> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let config = AdcConfig { channels: vec![0, 1], ..Default::default() };
+    let (mut system, mut rx) = EegSystem::new(config.clone(), FilterChainSpec::default()).await?;
+    system.start(config).await?;
+    assert!(rx.recv().await.is_some());
+
+    let european = FilterChainSpec::with_mains(50.0);
+    system.set_filter_chain(european.clone()).await?;
+    assert_eq!(system.filter_chain().await, european);
+    // The driver keeps running and data keeps flowing
+    assert_eq!(system.driver_status().await, DriverStatus::Running);
+    while rx.try_recv().is_ok() {}
+    assert_eq!(rx.recv().await.map(|data| data.channel_count), Some(2));
+
+    // An invalid chain is rejected and the current one stays
+    let invalid = FilterChainSpec::new(vec![FilterStage::notch(50.0).with_q(0.0)]);
+    assert!(system.set_filter_chain(invalid).await.is_err());
+    assert_eq!(system.filter_chain().await, european);
+
+    system.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_band_power_published_alongside_data() -> Result<(), Box<dyn Error>> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    // The mock puts 2, 6 and 10 Hz sines on these channels: delta, theta and alpha
+    let config = AdcConfig { channels: vec![0, 1, 2], ..Default::default() };
+    let (mut system, mut rx) = EegSystem::new(config.clone(), FilterChainSpec::default()).await?;
+    let settings = BandPowerSettings { window_secs: 1.0, hop_secs: 0.25, ..Default::default() };
+    system.set_band_power(Some(settings)).await?;
+    let mut reports = system.subscribe_band_power();
+    system.start(config).await?;
+    let drain = tokio::spawn(async move { while rx.recv().await.is_some() {} });
+
+    let report = tokio::time::timeout(Duration::from_secs(5), reports.recv()).await??;
+    assert_eq!(report.channels.iter().map(|bins| bins.channel).collect::<Vec<_>>(), vec![0, 1, 2]);
+    let dominant = |bins: &FrequencyBins| {
+        let r = &bins.relative;
+        [r.delta, r.theta, r.alpha, r.beta, r.gamma].iter().enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1)).map(|(band, _)| band).unwrap()
+    };
+    assert_eq!(report.channels.iter().map(dominant).collect::<Vec<_>>(), vec![0, 1, 2]);
+
+    // Turning it off stops the reports
+    system.set_band_power(None).await?;
+    while reports.try_recv().is_ok() {}
+    sleep(Duration::from_millis(400)).await;
+    assert!(reports.try_recv().is_err());
+
+    system.shutdown().await?;
+    drain.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_psd_of_mock_sinusoids() -> Result<(), Box<dyn Error>> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    // Unfiltered, so the mock's 50 µV sines at 2 + 4i Hz arrive untouched
+    let config = AdcConfig { sample_rate: 1000, batch_size: 50, channels: vec![0, 1, 2], ..Default::default() };
+    let (mut system, mut rx) = EegSystem::new(config.clone(), FilterChainSpec::passthrough()).await?;
+    system.start(config).await?;
+
+    let mut buffers = Vec::new();
+    while buffers.iter().map(|b: &ProcessedData| b.data[0].len()).sum::<usize>() < 1500 {
+        buffers.push(tokio::time::timeout(Duration::from_secs(2), rx.recv()).await?.expect("Processed data"));
+    }
+    system.shutdown().await?;
+
+    let welch = crate::dsp::psd::welch_processed(&buffers, 1000.0, &WelchSettings { segment_secs: 1.0, ..Default::default() })?;
+    let multitaper = crate::dsp::psd::multitaper_processed(&buffers, 1000.0, &MultitaperSettings { time_half_bandwidth: 2.0, tapers: None })?;
+    for (i, (welch, multitaper)) in welch.iter().zip(&multitaper).enumerate() {
+        let frequency = 2.0 + 4.0 * i as f32;
+        // A sine of amplitude A carries A²/2
+        let expected = MOCK_AMPLITUDE_UV * MOCK_AMPLITUDE_UV / 2.0;
+        assert_eq!(welch.peak().map(|(f, _)| f), Some(frequency));
+        let power = welch.band_power(frequency - 2.0, frequency + 2.0);
+        assert!((power - expected).abs() < 0.03 * expected, "Welch channel {}: {} µV²", i, power);
+        let power = multitaper.band_power(frequency - 2.0, frequency + 2.0);
+        assert!((power - expected).abs() < 0.03 * expected, "Multitaper channel {}: {} µV²", i, power);
+    }
+
+    // Buffers of different channel counts can't be joined
+    buffers[1].data.pop();
+    assert_eq!(
+        crate::dsp::psd::welch_processed(&buffers, 1000.0, &WelchSettings::default()).err(),
+        Some(DspError::ChannelCountMismatch { expected: 3, found: 2 })
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_spectrogram_streamed_on_its_own_channel() -> Result<(), Box<dyn Error>> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let config = AdcConfig { channels: vec![0, 1], ..Default::default() };
+    let (mut system, mut rx) = EegSystem::new(config.clone(), FilterChainSpec::default()).await?;
+    let settings = SpectrogramSettings { window_secs: 0.5, hop_secs: 0.1, ..Default::default() };
+    system.set_spectrogram(Some(settings)).await?;
+    let mut frames = system.subscribe_spectrogram();
+    system.start(config).await?;
+    let drain = tokio::spawn(async move { while rx.recv().await.is_some() {} });
+
+    for expected in 0..4 {
+        let frame = tokio::time::timeout(Duration::from_secs(5), frames.recv()).await??;
+        assert_eq!(frame.index, expected);
+        assert_eq!(frame.channels.len(), 2);
+        // 2 Hz bins up to 60 Hz
+        assert_eq!(frame.resolution_hz, 2.0);
+        assert_eq!(frame.channels[0].len(), 31);
+        assert!(frame.channels.iter().flatten().all(|x| x.is_finite()));
+    }
+
+    system.shutdown().await?;
+    drain.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_hilbert_envelope_of_mock_sinusoids() -> Result<(), Box<dyn Error>> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let config = AdcConfig { channels: vec![0, 1], ..Default::default() };
+    let (mut system, mut rx) = EegSystem::new(config.clone(), FilterChainSpec::new(Vec::new())).await?;
+    system.set_hilbert(Some(HilbertSettings { low_hz: 3.0 })).await?;
+    let mut frames = system.subscribe_hilbert();
+    system.start(config).await?;
+    let drain = tokio::spawn(async move { while rx.recv().await.is_some() {} });
+
+    // Collect past the filter's delay and its start-up transient, twice the delay
+    let mut amplitude: Vec<Vec<f32>> = vec![Vec::new(); 2];
+    let mut delay_samples = 0;
+    while amplitude[0].len() < 2 * delay_samples + 250 {
+        let frame = tokio::time::timeout(Duration::from_secs(5), frames.recv()).await??;
+        assert_eq!(frame.channels.len(), 2);
+        // 250 Hz by default
+        delay_samples = (frame.delay_us * 250 / 1_000_000) as usize;
+        assert!(delay_samples > 0);
+        for (samples, channel) in amplitude.iter_mut().zip(&frame.channels) {
+            samples.extend(&channel.amplitude);
+        }
+    }
+    // The 6 Hz sine of channel 1 is within the transformer's range; its envelope is flat
+    for &a in &amplitude[1][2 * delay_samples + 1..] {
+        assert!((a - MOCK_AMPLITUDE_UV).abs() < 0.05 * MOCK_AMPLITUDE_UV, "envelope {}", a);
+    }
+
+    system.shutdown().await?;
+    drain.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_re_referencing_changes_channels_and_labels() -> Result<(), Box<dyn Error>> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let config = AdcConfig { channels: vec![0, 1, 2], ..Default::default() };
+    let (mut system, mut rx) = EegSystem::new(config.clone(), FilterChainSpec::passthrough()).await?;
+    system.set_reference(Reference::Bipolar { pairs: vec![(1, 0), (2, 1)] }).await?;
+    let labels = |electrodes: &[ElectrodeInfo]| electrodes.iter().map(|e| e.label.clone()).collect::<Vec<_>>();
+    assert_eq!(labels(&system.electrodes().await?), ["Ch1-Ch0", "Ch2-Ch1"]);
+    system.start(config).await?;
+
+    let data = tokio::time::timeout(Duration::from_secs(2), rx.recv()).await?.expect("data");
+    assert_eq!(data.channel_count, 2);
+    assert_eq!(data.data.len(), 2);
+    assert_eq!(labels(&data.electrodes), ["Ch1-Ch0", "Ch2-Ch1"]);
+
+    // Switching to the common average mid-stream brings the third channel back, and the
+    // channels then sum to zero at every sample
+    system.set_reference(Reference::CommonAverage).await?;
+    let data = loop {
+        let data = tokio::time::timeout(Duration::from_secs(2), rx.recv()).await?.expect("data");
+        if data.channel_count == 3 {
+            break data;
+        }
+    };
+    assert_eq!(labels(&data.electrodes), ["Ch0", "Ch1", "Ch2"]);
+    for i in 0..data.data[0].len() {
+        let sum: f32 = data.data.iter().map(|channel| channel[i]).sum();
+        assert!(sum.abs() < 1e-3, "sample {} sums to {}", i, sum);
+    }
+
+    // References must name channels that exist
+    assert!(system.set_reference(Reference::Channel { channel: 3 }).await.is_err());
+    assert_eq!(system.reference().await, Reference::CommonAverage);
+
+    system.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_electrodes_carried_on_every_stream() -> Result<(), Box<dyn Error>> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let config = AdcConfig {
+        channels: vec![0, 1],
+        electrodes: vec![ElectrodeInfo::eeg("Cz", Montage::Standard1020), ElectrodeInfo::new("EKG", ElectrodeType::Ecg)],
+        ..Default::default()
+    };
+    let (mut system, mut rx) = EegSystem::new(config.clone(), FilterChainSpec::default()).await?;
+    system.set_band_power(Some(BandPowerSettings { window_secs: 0.5, hop_secs: 0.5, ..Default::default() })).await?;
+    system.set_spectrogram(Some(SpectrogramSettings { window_secs: 0.5, hop_secs: 0.5, ..Default::default() })).await?;
+    let mut reports = system.subscribe_band_power();
+    let mut frames = system.subscribe_spectrogram();
+    system.start(config.clone()).await?;
+
+    let data = tokio::time::timeout(Duration::from_secs(2), rx.recv()).await?.expect("data");
+    assert_eq!(data.electrodes, config.electrodes);
+    assert!(data.electrodes[0].position.is_some());
+    // Recorded data keeps them
+    let recorded: ProcessedData = serde_json::from_str(&serde_json::to_string(&data)?)?;
+    assert_eq!(recorded.electrodes[1].kind, ElectrodeType::Ecg);
+
+    let drain = tokio::spawn(async move { while rx.recv().await.is_some() {} });
+    let report = tokio::time::timeout(Duration::from_secs(5), reports.recv()).await??;
+    assert_eq!(report.channels.iter().map(|bins| bins.label.as_str()).collect::<Vec<_>>(), ["Cz", "EKG"]);
+    let frame = tokio::time::timeout(Duration::from_secs(5), frames.recv()).await??;
+    assert_eq!(frame.labels, ["Cz", "EKG"]);
+
+    system.shutdown().await?;
+    drain.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_artifacts_injected_by_mock_are_flagged() -> Result<(), Box<dyn Error>> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let script = |channel: usize, artifact: MockArtifact| ScriptedArtifact { channel, artifact, from_secs: 0.6, until_secs: None };
+    let config = AdcConfig {
+        channels: vec![0, 1, 2],
+        ..Default::default()
+    };
+    let settings = MockSettings {
+        artifacts: vec![script(0, MockArtifact::Saturation), script(1, MockArtifact::Flatline)],
+        ..Default::default()
+    };
+    let (mut system, mut rx) = mock_system(config.clone(), settings).await?;
+    let settings = ArtifactSettings { window_secs: 0.2, ..Default::default() };
+    system.set_artifact_detection(Some(settings)).await?;
+    system.start(config).await?;
+
+    let mut found = [ArtifactMask::default(); 3];
+    for _ in 0..50 {
+        let data = tokio::time::timeout(Duration::from_secs(2), rx.recv()).await?.expect("data");
+        assert_eq!(data.artifacts.len(), 3);
+        assert!(data.artifacts.iter().all(|masks| masks.len() == data.data[0].len()));
+        for (all, batch) in found.iter_mut().zip(data.batch_artifacts()) {
+            *all = all.union(batch);
+        }
+        if found[0].contains(Artifact::Clipping) && found[1].contains(Artifact::Flatline) {
+            break;
+        }
+    }
+    assert!(found[0].contains(Artifact::Clipping));
+    assert!(found[1].contains(Artifact::Flatline));
+    assert!(found[2].is_clean());
+
+    // Turning detection off leaves the masks empty, once batches already queued are through
+    system.set_artifact_detection(None).await?;
+    let mut cleared = false;
+    for _ in 0..20 {
+        let data = tokio::time::timeout(Duration::from_secs(2), rx.recv()).await?.expect("data");
+        if data.artifacts.is_empty() {
+            cleared = true;
+            break;
+        }
+    }
+    assert!(cleared);
+
+    system.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fan_out_backpressure_policies() {
+    let fan_out: fanout::FanOut<u32> = fanout::FanOut::new();
+    let mut oldest = fan_out.subscribe(Backpressure::DropOldest, 2);
+    let mut newest = fan_out.subscribe(Backpressure::DropNewest, 2);
+    let mut blocking = fan_out.subscribe(Backpressure::Block, 2);
+    let reader = tokio::spawn(async move {
+        let mut received = Vec::new();
+        while let Some(item) = blocking.recv().await {
+            received.push(item);
+        }
+        received
+    });
+
+    for item in 0..5 {
+        fan_out.send(item).await;
+    }
+    // Only the blocking subscriber sees everything; the others lose 3 items each
+    assert_eq!((oldest.try_recv(), oldest.try_recv()), (Ok(3), Ok(4)));
+    assert_eq!((newest.try_recv(), newest.try_recv()), (Ok(0), Ok(1)));
+    assert_eq!((oldest.dropped(), newest.dropped()), (3, 3));
+    assert!(oldest.try_recv().is_err());
+
+    // A dropped subscription goes away; the rest see the end of the stream
+    drop(newest);
+    fan_out.send(5).await;
+    let stats = fan_out.stats();
+    assert_eq!(stats.iter().map(|s| s.backpressure).collect::<Vec<_>>(), [Backpressure::DropOldest, Backpressure::Block]);
+    assert_eq!(stats[0].queued, 1);
+    drop(fan_out);
+    assert_eq!(oldest.recv().await, Some(5));
+    assert_eq!(oldest.recv().await, None);
+    assert_eq!(reader.await.unwrap(), [0, 1, 2, 3, 4, 5]);
+}
+
+#[tokio::test]
+async fn test_independent_processed_subscribers() -> Result<(), Box<dyn Error>> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let config = AdcConfig { batch_size: 8, ..Default::default() };
+    let (mut system, mut recorder) = EegSystem::new(config.clone(), FilterChainSpec::default()).await?;
+    let mut display = system.subscribe_processed(Backpressure::DropOldest, 1);
+    let stalled = system.subscribe_processed(Backpressure::DropNewest, 2);
+    system.start(config).await?;
+
+    // Each subscriber sees the stream its own way; a stalled one holds nobody up
+    let mut timestamps = Vec::new();
+    for _ in 0..10 {
+        timestamps.push(tokio::time::timeout(Duration::from_secs(2), recorder.recv()).await?.expect("data").timestamp);
+    }
+    assert!(timestamps.windows(2).all(|pair| pair[0] < pair[1]));
+    let latest = display.recv().await.expect("data");
+    assert!(latest.timestamp >= timestamps[8]);
+    assert!(stalled.dropped() > 0);
+
+    // Subscribers join at any time, and their lag is reported
+    let late = system.subscribe_processed(Backpressure::Block, 4);
+    let stats = system.processed_subscribers();
+    assert_eq!(stats.len(), 4);
+    assert_eq!(stats.iter().find(|s| s.id == stalled.stats().id).map(|s| s.queued), Some(2));
+    assert_eq!(stats.last().map(|s| (s.id, s.dropped)), Some((late.stats().id, 0)));
+    drop(late);
+
+    let drain = tokio::spawn(async move { while recorder.recv().await.is_some() {} });
+    system.shutdown().await?;
+    drain.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_raw_stream_matches_processed_indices() -> Result<(), Box<dyn Error>> {
+    use crate::board_driver::ads1299_driver::code_to_volts;
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let config = AdcConfig { channels: vec![0, 1], batch_size: 10, ..Default::default() };
+    // With no filters the processed samples are the raw codes in microvolts
+    let (mut system, mut processed) = EegSystem::new(config.clone(), FilterChainSpec::new(vec![])).await?;
+    let mut raw = system.subscribe_raw(Backpressure::Block, 16);
+    system.start(config.clone()).await?;
+
+    let mut next_sample = 0;
+    for _ in 0..5 {
+        let raw_batch = tokio::time::timeout(Duration::from_secs(2), raw.recv()).await?.expect("raw data");
+        let data = tokio::time::timeout(Duration::from_secs(2), processed.recv()).await?.expect("processed data");
+        assert_eq!(raw_batch.first_sample, next_sample);
+        assert_eq!(data.first_sample, raw_batch.first_sample);
+        assert_eq!(data.timestamp, raw_batch.samples.last().unwrap().timestamp);
+        assert_eq!(raw_batch.channels, config.channels);
+        assert_eq!(raw_batch.electrodes[1].label, "Ch1");
+        for (i, sample) in raw_batch.samples.iter().enumerate() {
+            for (ch, codes) in sample.samples.iter().enumerate() {
+                let uv = code_to_volts(codes[0], config.gain) * 1e6;
+                assert!((data.data[ch][i] - uv).abs() < 1e-3, "sample {} channel {}", i, ch);
+            }
+        }
+        next_sample += raw_batch.samples.len() as u64;
+    }
+    assert_eq!(system.raw_subscribers().len(), 1);
+
+    // Restarting numbers samples from zero again
+    system.stop().await?;
+    while processed.try_recv().is_ok() {}
+    while raw.try_recv().is_ok() {}
+    system.start(config).await?;
+    let data = tokio::time::timeout(Duration::from_secs(2), processed.recv()).await?.expect("processed data");
+    assert_eq!(data.first_sample, 0);
+
+    let drain = tokio::spawn(async move { while processed.recv().await.is_some() {} });
+    let drain_raw = tokio::spawn(async move { while raw.recv().await.is_some() {} });
+    system.shutdown().await?;
+    drain.abort();
+    drain_raw.abort();
+    Ok(())
+}
+
+#[test]
+fn test_sample_tracker_flags_gaps_and_repeats() {
+    // One sample per millisecond at 1 kHz
+    let sample = |index: u64| AdcData { samples: vec![vec![0.0]], timestamp: 5_000 + index * 1_000, sample_index: index, status: vec![] };
+    let indices = |batch: &[AdcData]| batch.iter().map(|sample| sample.sample_index).collect::<Vec<_>>();
+    let mut tracker = SampleTracker::new(1000);
+
+    let (kept, gaps) = tracker.push((0..4).map(sample).collect());
+    assert_eq!(indices(&kept), vec![0, 1, 2, 3]);
+    assert!(gaps.is_empty());
+    assert_eq!(tracker.stats().effective_hz, 0.0);
+
+    // Three samples lost
+    let (kept, gaps) = tracker.push([4, 5, 9, 10].into_iter().map(sample).collect());
+    assert_eq!(indices(&kept), vec![4, 5, 9, 10]);
+    assert_eq!(gaps, vec![DataGap { expected: 6, received: 9, missing: 3, duplicated: 0, timestamp: 14_000 }]);
+
+    // Two samples delivered again are reported once and left out
+    let (kept, gaps) = tracker.push([9, 10, 11, 12].into_iter().map(sample).collect());
+    assert_eq!(indices(&kept), vec![11, 12]);
+    assert_eq!(gaps, vec![DataGap { expected: 11, received: 9, missing: 0, duplicated: 2, timestamp: 14_000 }]);
+
+    let stats = tracker.stats();
+    assert_eq!((stats.received, stats.missing, stats.duplicated), (10, 3, 2));
+    assert_eq!(stats.nominal_hz, 1000.0);
+    // Six samples after the first batch, over the 9 ms from its end to sample 12
+    assert!((stats.effective_hz - 6000.0 / 9.0).abs() < 1e-3);
+    assert!(stats.deviation_ppm() < 0.0);
+}
+
+#[tokio::test]
+async fn test_dropped_samples_reported_as_data_gap() -> Result<(), Box<dyn Error>> {
+    let _hardware = MOCK_HARDWARE.lock().await;
+    let config = AdcConfig {
+        channels: vec![0, 1],
+        batch_size: 10,
+        ..Default::default()
+    };
+    let settings = MockSettings { dropped_samples: vec![(25, 7)], ..Default::default() };
+    let (mut system, mut rx) = mock_system(config.clone(), settings).await?;
+    let mut events = system.subscribe_events();
+    system.start(config).await?;
+
+    let mut first_samples = Vec::new();
+    for _ in 0..20 {
+        let data = tokio::time::timeout(Duration::from_secs(2), rx.recv()).await?.expect("data");
+        first_samples.push(data.first_sample);
+    }
+    assert_eq!(&first_samples[..5], &[0, 10, 20, 32, 40]);
+
+    let gap = loop {
+        match tokio::time::timeout(Duration::from_secs(2), events.recv()).await?? {
+            SystemEvent::DataGap(gap) => break gap,
+            _ => continue,
+        }
+    };
+    assert_eq!((gap.expected, gap.received, gap.missing, gap.duplicated), (25, 32, 7, 0));
+
+    // The mock stamps whole batches as it makes them, so the rate is only about right
+    let stats = system.sample_rate_stats();
+    assert_eq!(stats.nominal_hz, 250.0);
+    assert_eq!(stats.missing, 7);
+    assert!(stats.received >= 193);
+    assert!((stats.effective_hz - 250.0).abs() < 50.0, "effective rate {} Hz", stats.effective_hz);
+
+    // Each run is tracked afresh
+    system.stop().await?;
+    assert_eq!(system.sample_rate_stats().missing, 7);
+    while rx.try_recv().is_ok() {}
+    system.start(AdcConfig { channels: vec![0, 1], batch_size: 10, ..Default::default() }).await?;
+    let data = tokio::time::timeout(Duration::from_secs(2), rx.recv()).await?.expect("data");
+    assert_eq!(data.first_sample, 0);
+    assert_eq!(system.sample_rate_stats().missing, 0);
+
+    let drain = tokio::spawn(async move { while rx.recv().await.is_some() {} });
+    system.shutdown().await?;
+    drain.abort();
+    Ok(())
+}
@@ -3,8 +3,11 @@ pub mod dsp;
 pub mod eeg_system;
 
 // Re-export the main types that users need
-pub use eeg_system::EegSystem;
-pub use board_driver::types::{AdcConfig, DriverType, DriverStatus};
+pub use eeg_system::{Backpressure, DataGap, EegSystem, ImpedanceReport, ImpedanceSettings, SampleRateStats, SubscriberStats, Subscription, SystemEvent, SystemState};
+pub use board_driver::types::{AdcConfig, AdcData, ChannelCalibration, ChannelSettings, DriverType, DriverStatus, LeadOffConfig, LeadOffStatus};
+pub use board_driver::capabilities::{DriverCapabilities, SampleRates, TimestampPrecision};
+pub use board_driver::montage::{ElectrodeInfo, ElectrodeType, Montage, Position};
+pub use dsp::{AnalyticFrame, Artifact, ArtifactMask, ArtifactSettings, BandPowerReport, BandPowerSettings, CwtSettings, DspError, FilterChainSpec, FilterFamily, FilterKind, FilterStage, HilbertSettings, LaplacianSite, Reference, ScalogramFrame, SignalUnit, SpectrogramFrame, SpectrogramSettings, StageAdjustment};
 use serde::{Serialize, Deserialize};
 
 /// Processed EEG data structure
@@ -13,6 +16,47 @@ pub struct ProcessedData {
     pub data: Vec<Vec<f32>>,
     pub timestamp: u64,
     pub channel_count: usize,
+    #[serde(default)]
+    pub unit: SignalUnit,
+    /// Delay the filter chain adds to the samples; subtract it from `timestamp` to line
+    /// the data up with the acquisition time
+    #[serde(default)]
+    pub group_delay_us: u64,
+    /// Name, type and position of each channel in `data`, as the re-referencing left them
+    #[serde(default)]
+    pub electrodes: Vec<ElectrodeInfo>,
+    /// Artifacts found in each sample of `data`, per channel; empty while detection is off.
+    /// They are found before filtering, so they lead the data by `group_delay_us`.
+    #[serde(default)]
+    pub artifacts: Vec<Vec<ArtifactMask>>,
+    /// Driver's index of the first sample in `data`, shared with the `RawData` of the
+    /// same batch. Samples follow on one index apart unless a `SystemEvent::DataGap` was
+    /// sent for the batch
+    #[serde(default)]
+    pub first_sample: u64,
+}
+
+/// A batch of samples exactly as the driver delivered it, before any processing.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RawData {
+    /// Output codes per sample and channel, with their timestamps and status words
+    pub samples: Vec<AdcData>,
+    /// Index of the first sample since acquisition started; the `ProcessedData` made from
+    /// this batch starts at the same index
+    pub first_sample: u64,
+    /// ADC channel number of each channel in the samples
+    pub channels: Vec<usize>,
+    /// Electrode on each channel
+    pub electrodes: Vec<ElectrodeInfo>,
+}
+
+impl ProcessedData {
+    /// Artifacts found anywhere in the batch, per channel; empty while detection is off.
+    pub fn batch_artifacts(&self) -> Vec<ArtifactMask> {
+        self.artifacts.iter()
+            .map(|masks| masks.iter().fold(ArtifactMask::default(), |batch, &mask| batch.union(mask)))
+            .collect()
+    }
 }
 
 // Optionally expose lower-level access through a raw module
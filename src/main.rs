@@ -1,9 +1,6 @@
-mod board_driver;
-
-use tokio;
 use std::error::Error;
 use clap::Parser;
-use eeg_driver::{AdcConfig, EegSystem, DriverType};
+use eeg_driver::{AdcConfig, DriverCapabilities, EegSystem, DriverType, FilterChainSpec, ImpedanceSettings};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -19,6 +16,22 @@ struct Args {
     /// Channels to read (comma-separated)
     #[arg(long, value_delimiter = ',', default_values_t = vec![0, 1, 2, 3])]
     channels: Vec<usize>,
+
+    /// PGA gain of every channel
+    #[arg(long, default_value_t = 24.0)]
+    gain: f32,
+
+    /// Print what the selected driver supports and exit
+    #[arg(long)]
+    capabilities: bool,
+
+    /// Measure electrode impedances before streaming
+    #[arg(long)]
+    impedance: bool,
+
+    /// Local mains frequency in Hz; notch only this one instead of both 50 and 60 Hz
+    #[arg(long)]
+    mains: Option<f32>,
 }
 
 #[tokio::main]
@@ -27,16 +40,34 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     // Create a basic ADC configuration
     let config = AdcConfig {
-        sample_rate: 250,
-        channels: vec![0, 1, 2, 3],
-        gain: 24.0,
-        board_driver: DriverType::Mock,
+        sample_rate: args.sample_rate,
+        channels: args.channels,
+        gain: args.gain,
+        board_driver: if args.mock { DriverType::Mock } else { DriverType::Ads1299 },
         batch_size: 32,
+        ..Default::default()
     };
 
-    // Create the EEG system (using mock driver)
-    let (mut eeg_system, mut data_rx) = EegSystem::new(config.clone()).await?;
+    // Check the request against the hardware before opening it
+    let capabilities = DriverCapabilities::for_config(&config);
+    if args.capabilities {
+        println!("{:#?}", capabilities);
+        return Ok(());
+    }
+    capabilities.validate(&config)?;
+
+    let filters = args.mains.map_or_else(FilterChainSpec::default, FilterChainSpec::with_mains);
+
+    // Create the EEG system
+    let (mut eeg_system, mut data_rx) = EegSystem::new(config.clone(), filters).await?;
     
+    if args.impedance {
+        let report = eeg_system.measure_impedance(ImpedanceSettings::default()).await?;
+        for reading in &report.readings {
+            println!("Channel {}: {:.1} kOhm", reading.channel, reading.kohms);
+        }
+    }
+
     // Start the system
     eeg_system.start(config).await?;
 